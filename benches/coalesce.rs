@@ -0,0 +1,35 @@
+//! 1000 writes of 64 bytes followed by a flush, with and without a coalesce window for the background writer.
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_tls_duplex_stream::StreamConfig;
+use std::time::Duration;
+
+fn small_writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("small_writes");
+    group.throughput(Throughput::Bytes(1000 * 64));
+    for window in [None, Some(Duration::from_millis(1))] {
+        let config = StreamConfig {
+            write_coalesce_window: window,
+            ..StreamConfig::default()
+        };
+        let (client, server) = common::memory_pair(&config);
+        let drained = common::drain(server);
+        let message = [7u8; 64];
+        let name = if window.is_some() { "coalesced" } else { "direct" };
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                for _ in 0..1000 {
+                    client.write_all(&message).unwrap();
+                }
+                client.flush().unwrap();
+            });
+        });
+        client.send_close_notify().unwrap();
+        drained.join().unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, small_writes);
+criterion_main!(benches);
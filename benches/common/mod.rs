@@ -0,0 +1,48 @@
+#![allow(dead_code)]
+
+#[path = "../../tests/common/mod.rs"]
+mod tls;
+
+pub use tls::*;
+
+use rust_tls_duplex_stream::test_utils::in_memory_pair;
+use rust_tls_duplex_stream::{ClientDuplexStream, ServerDuplexStream, SpawnConfig, StreamConfig};
+use std::thread;
+use std::thread::JoinHandle;
+
+/// Returns a connected (client, server) pair of duplex streams over the in memory transport.
+pub fn memory_pair(config: &StreamConfig) -> (ClientDuplexStream, ServerDuplexStream) {
+    let (client_transport, server_transport) = in_memory_pair();
+    let client = ClientDuplexStream::new_unpooled_with_config(
+        client_connection(),
+        client_transport.clone(),
+        client_transport,
+        config,
+        SpawnConfig::default(),
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled_with_config(
+        server_connection(),
+        server_transport.clone(),
+        server_transport,
+        config,
+        SpawnConfig::default(),
+    )
+    .unwrap();
+    (client, server)
+}
+
+/// Reads and drops everything the server receives until EOF, returns the amount of bytes.
+/// End the benchmark with `send_close_notify`, a dropped client keeps the in memory transport open while its threads run.
+pub fn drain(server: ServerDuplexStream) -> JoinHandle<u64> {
+    thread::spawn(move || {
+        let mut buf = vec![0u8; 0x1_00_00];
+        let mut total = 0;
+        loop {
+            match server.read(&mut buf) {
+                Ok(0) | Err(_) => return total,
+                Ok(count) => total += count as u64,
+            }
+        }
+    })
+}
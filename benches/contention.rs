@@ -0,0 +1,40 @@
+//! Several threads write small messages to one stream while the peer consumes them.
+//! Run once as is and once with `--features parking_lot` to compare the mutex backends.
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rust_tls_duplex_stream::StreamConfig;
+use std::thread;
+use std::time::Instant;
+
+fn contended_writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contended_writes");
+    for threads in [4u64, 8] {
+        let (client, server) = common::memory_pair(&StreamConfig::default());
+        let drained = common::drain(server);
+        let message = [7u8; 64];
+        group.bench_function(BenchmarkId::from_parameter(threads), |b| {
+            b.iter_custom(|iters| {
+                let per_thread = iters.div_ceil(threads);
+                let start = Instant::now();
+                thread::scope(|scope| {
+                    for _ in 0..threads {
+                        scope.spawn(|| {
+                            for _ in 0..per_thread {
+                                client.write_all(&message).unwrap();
+                            }
+                        });
+                    }
+                });
+                client.flush().unwrap();
+                start.elapsed()
+            });
+        });
+        client.send_close_notify().unwrap();
+        drained.join().unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, contended_writes);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+//! Small and medium messages read one at a time. The read pipe copies the ciphertext it pops straight into
+//! the buffer of rust-tls and only falls back to its cursor if it does not fit.
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_tls_duplex_stream::StreamConfig;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
+use std::thread;
+
+fn read_messages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read_messages");
+    for size in [128usize, 0x2000] {
+        let (client, server) = common::memory_pair(&StreamConfig::default());
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let message = vec![7u8; size];
+                while !stop.load(SeqCst) {
+                    server.write_all(&message).unwrap();
+                }
+                server.send_close_notify().unwrap();
+            })
+        };
+
+        let mut buf = vec![0u8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| client.read_exact(&mut buf).unwrap());
+        });
+        stop.store(true, SeqCst);
+        while client.read(&mut buf).unwrap() != 0 {}
+        writer.join().unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, read_messages);
+criterion_main!(benches);
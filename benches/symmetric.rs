@@ -0,0 +1,177 @@
+//! One thread writes messages while another reads their echo, so both queues of both streams have a waiting
+//! reader and a waiting writer most of the time. Measured once in time and once in context switches of the process.
+//! Context switches are read from `/proc`, they are always 0 elsewhere. Compare them against a build from before the
+//! queue condvar was split into read and write conditions to see the wakeups that no longer happen.
+mod common;
+
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rust_tls_duplex_stream::{ClientDuplexStream, ServerDuplexStream, StreamConfig};
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+const MESSAGE_SIZE: usize = 0x400;
+
+/// Counts the context switches of the whole process between start and end.
+struct ContextSwitches;
+
+impl Measurement for ContextSwitches {
+    type Intermediate = u64;
+    type Value = u64;
+
+    fn start(&self) -> u64 {
+        process_switches()
+    }
+
+    fn end(&self, start: u64) -> u64 {
+        process_switches().saturating_sub(start)
+    }
+
+    fn add(&self, v1: &u64, v2: &u64) -> u64 {
+        v1 + v2
+    }
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn to_f64(&self, value: &u64) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        self
+    }
+}
+
+impl ValueFormatter for ContextSwitches {
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        "switches"
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn scale_throughputs(&self, _typical_value: f64, throughput: &Throughput, values: &mut [f64]) -> &'static str {
+        let (count, unit) = match *throughput {
+            Throughput::Bytes(count) | Throughput::BytesDecimal(count) => (count, "switches/B"),
+            Throughput::Elements(count) => (count, "switches/elem"),
+        };
+        for value in values {
+            *value /= count as f64;
+        }
+        unit
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "switches"
+    }
+}
+
+/// Voluntary and involuntary context switches listed in a `status` file of `/proc`, 0 if it can not be read.
+fn switches_in(status: &Path) -> u64 {
+    fs::read_to_string(status).map_or(0, |status| {
+        status
+            .lines()
+            .filter(|line| line.contains("ctxt_switches"))
+            .filter_map(|line| line.split_whitespace().nth(1)?.parse::<u64>().ok())
+            .sum()
+    })
+}
+
+/// Context switches of the calling thread.
+fn thread_switches() -> u64 {
+    switches_in(Path::new("/proc/thread-self/status"))
+}
+
+/// Context switches of all threads that currently run in this process. Threads that already ended are not counted.
+fn process_switches() -> u64 {
+    fs::read_dir("/proc/self/task").map_or(0, |tasks| {
+        tasks.flatten().map(|task| switches_in(&task.path().join("status"))).sum()
+    })
+}
+
+/// Writes everything the server receives back until EOF.
+fn echo(server: ServerDuplexStream) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = vec![0u8; 0x1_00_00];
+        loop {
+            match server.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(count) => {
+                    if server.write_all(&buf[..count]).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Writes `iters` messages on one thread and reads their echo on another.
+/// Returns the context switches of both threads, they end before `process_switches` could see them.
+fn exchange(client: &ClientDuplexStream, iters: u64) -> u64 {
+    thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let start = thread_switches();
+            let mut buf = [0u8; MESSAGE_SIZE];
+            for _ in 0..iters {
+                client.read_exact(&mut buf).unwrap();
+            }
+            thread_switches() - start
+        });
+        let writer = scope.spawn(|| {
+            let start = thread_switches();
+            let message = [7u8; MESSAGE_SIZE];
+            for _ in 0..iters {
+                client.write_all(&message).unwrap();
+            }
+            client.flush().unwrap();
+            thread_switches() - start
+        });
+        reader.join().unwrap() + writer.join().unwrap()
+    })
+}
+
+fn symmetric_time(c: &mut Criterion) {
+    let mut group = c.benchmark_group("symmetric_time");
+    group.throughput(Throughput::Bytes(MESSAGE_SIZE as u64));
+    let (client, server) = common::memory_pair(&StreamConfig::default());
+    let echoed = echo(server);
+    group.bench_function("echo", |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            exchange(&client, iters);
+            start.elapsed()
+        });
+    });
+    client.send_close_notify().unwrap();
+    echoed.join().unwrap();
+    group.finish();
+}
+
+fn symmetric_switches(c: &mut Criterion<ContextSwitches>) {
+    let mut group = c.benchmark_group("symmetric_switches");
+    let (client, server) = common::memory_pair(&StreamConfig::default());
+    let echoed = echo(server);
+    group.bench_function("echo", |b| {
+        b.iter_custom(|iters| {
+            let start = process_switches();
+            let exchanged = exchange(&client, iters);
+            process_switches().saturating_sub(start) + exchanged
+        });
+    });
+    client.send_close_notify().unwrap();
+    echoed.join().unwrap();
+    group.finish();
+}
+
+criterion_group!(timed, symmetric_time);
+criterion_group! {
+    name = switched;
+    config = Criterion::default().with_measurement(ContextSwitches);
+    targets = symmetric_switches
+}
+criterion_main!(timed, switched);
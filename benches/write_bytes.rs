@@ -0,0 +1,28 @@
+//! Multi megabyte payloads written as `Bytes` and as a borrowed slice.
+//! Both are only copied by rust-tls when it encrypts, `write_bytes` must not fall behind `write_all`.
+mod common;
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rust_tls_duplex_stream::StreamConfig;
+
+fn write_payload(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_payload");
+    let (client, server) = common::memory_pair(&StreamConfig::default());
+    let drained = common::drain(server);
+    let payload = Bytes::from(vec![7u8; 0x40_0000]);
+    group.throughput(Throughput::Bytes(payload.len() as u64));
+    group.sample_size(20);
+    group.bench_function("write_bytes", |b| {
+        b.iter(|| client.write_bytes(payload.clone()).unwrap());
+    });
+    group.bench_function("write_all", |b| {
+        b.iter(|| client.write_all(&payload).unwrap());
+    });
+    client.send_close_notify().unwrap();
+    drained.join().unwrap();
+    group.finish();
+}
+
+criterion_group!(benches, write_payload);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+//! rust-tls hands each record to the background writer as header and payload in one `write_vectored` call.
+//! Compare against a checkout without `CombinedPipe::write_vectored` to see what the saved copy is worth.
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_tls_duplex_stream::StreamConfig;
+use std::io::IoSlice;
+
+fn write_records(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_records");
+    for size in [0x400usize, 0x4000] {
+        let (client, server) = common::memory_pair(&StreamConfig::default());
+        let drained = common::drain(server);
+        let message = vec![7u8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("write_all", size), &message, |b, message| {
+            b.iter(|| client.write_all(message).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("write_vectored", size), &message, |b, message| {
+            let (header, body) = message.split_at(16);
+            b.iter(|| {
+                let mut slices = [IoSlice::new(header), IoSlice::new(body)];
+                let mut slices = &mut slices[..];
+                while !slices.is_empty() {
+                    let count = client.write_vectored(slices).unwrap();
+                    IoSlice::advance_slices(&mut slices, count);
+                }
+            });
+        });
+        client.send_close_notify().unwrap();
+        drained.join().unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, write_records);
+criterion_main!(benches);
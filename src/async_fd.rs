@@ -0,0 +1,136 @@
+//! Registration of a stream with the `async-io` reactor that smol and async-std run on.
+use crate::{RustTlsDuplexStream, TlsConnection};
+use async_io::Async;
+use std::io;
+use std::io::ErrorKind;
+use std::os::fd::OwnedFd;
+
+///
+/// Wrapper that registers the readiness fds of a `RustTlsDuplexStream` with the `async-io` reactor.
+///
+/// The read side is the fd of `readiness_fd`, it is readable while received data waits or the connection died or ended.
+/// The write side is a second fd that is writable while the write queue is at or below its low watermark
+/// or the connection died. Both are level triggered, the reactor reports them for as long as they are set.
+///
+/// The stream is switched to non-blocking reads and writes. `read_with` and `write_with` retry the operation
+/// until it does not return `WouldBlock`, waiting for the fd in between. Readiness is a hint: received ciphertext
+/// that does not complete a record (or only carries a session ticket) makes the fd readable without any plaintext,
+/// the read then returns `WouldBlock` and the wait starts over.
+///
+/// `flush` and `send_close_notify` of the stream still wait for the background writer, call them where blocking is fine.
+///
+#[derive(Debug)]
+pub struct AsyncIoDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    /// The wrapped stream.
+    stream: RustTlsDuplexStream<C>,
+    /// Copy of the read readiness fd, registered with the reactor.
+    readable: Async<OwnedFd>,
+    /// Copy of the write room fd, registered with the reactor.
+    writable: Async<OwnedFd>,
+}
+
+impl<C> AsyncIoDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    /// Constructor for `AsyncIoDuplexStream`, this enables non-blocking reads and writes of `stream`.
+    /// # Errors
+    /// if an fd could not be created, duplicated or registered, in case of poisoned mutex
+    pub fn new(stream: RustTlsDuplexStream<C>) -> io::Result<Self> {
+        stream.set_read_non_block(true)?;
+        stream.set_write_non_block(true)?;
+        //Copies, so the registrations do not borrow from the stream. They share the readiness with the originals.
+        let readable = Async::new(stream.read_q.readiness()?.fd().try_clone_to_owned()?)?;
+        let writable = Async::new(stream.write_q.room()?.fd().try_clone_to_owned()?)?;
+        Ok(Self {
+            stream,
+            readable,
+            writable,
+        })
+    }
+
+    /// Returns the wrapped stream.
+    #[must_use]
+    pub const fn get_ref(&self) -> &RustTlsDuplexStream<C> {
+        &self.stream
+    }
+
+    /// Deregisters the fds and returns the wrapped stream, it stays non-blocking.
+    #[must_use]
+    pub fn into_inner(self) -> RustTlsDuplexStream<C> {
+        self.stream
+    }
+
+    /// Waits until received data waits to be read or the connection died.
+    /// # Errors
+    /// propagated from the reactor
+    pub async fn readable(&self) -> io::Result<()> {
+        self.readable.readable().await
+    }
+
+    /// Waits until the write queue drained to its low watermark or the connection died.
+    /// # Errors
+    /// propagated from the reactor
+    pub async fn writable(&self) -> io::Result<()> {
+        self.writable.writable().await
+    }
+
+    /// Runs `op` until it does not return `WouldBlock`, waiting with `readable` in between.
+    /// # Errors
+    /// the error of `op`, propagated from the reactor
+    pub async fn read_with<R>(&self, mut op: impl FnMut(&RustTlsDuplexStream<C>) -> io::Result<R>) -> io::Result<R> {
+        loop {
+            match op(&self.stream) {
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+
+            self.readable().await?;
+        }
+    }
+
+    /// Runs `op` until it does not return `WouldBlock`, waiting with `writable` in between.
+    /// # Errors
+    /// the error of `op`, propagated from the reactor
+    pub async fn write_with<R>(&self, mut op: impl FnMut(&RustTlsDuplexStream<C>) -> io::Result<R>) -> io::Result<R> {
+        loop {
+            match op(&self.stream) {
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+
+            self.writable().await?;
+        }
+    }
+
+    /// see `RustTlsDuplexStream::read`, waits for data instead of returning `WouldBlock`.
+    /// # Errors
+    /// same as `read`
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_with(|stream| stream.read(buf)).await
+    }
+
+    /// see `RustTlsDuplexStream::write`, waits for room instead of returning `WouldBlock`.
+    /// # Errors
+    /// same as `write`
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.write_with(|stream| stream.write(buf)).await
+    }
+
+    /// Writes all of `buf`, see `write`.
+    /// # Errors
+    /// same as `write`
+    pub async fn write_all(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(io::Error::from(ErrorKind::WriteZero)),
+                count => buf = &buf[count..],
+            }
+        }
+
+        Ok(())
+    }
+}
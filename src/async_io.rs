@@ -0,0 +1,211 @@
+//! `futures-io` adapter for executors other than a thread per stream.
+use crate::error::queue_dead;
+use crate::write_pipe::{Done, TaskFlush, WriteOp};
+use crate::{unwrap_poison, RustTlsDuplexStream, TlsConnection};
+use futures_io::{AsyncBufRead, AsyncRead, AsyncWrite};
+use std::io;
+use std::io::{ErrorKind, IoSlice, Write};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// Runs `op`, if it would block registers the waker of `cx` with `register` and runs it once more,
+/// readiness that arrived before the waker was registered is not missed that way.
+fn poll_io<T>(
+    cx: &Context<'_>,
+    mut op: impl FnMut() -> io::Result<T>,
+    register: impl FnOnce(Waker) -> io::Result<()>,
+) -> Poll<io::Result<T>> {
+    match op() {
+        Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+        res => return Poll::Ready(res),
+    }
+
+    if let Err(err) = register(cx.waker().clone()) {
+        return Poll::Ready(Err(err));
+    }
+
+    match op() {
+        Err(err) if err.kind() == ErrorKind::WouldBlock => Poll::Pending,
+        res => Poll::Ready(res),
+    }
+}
+
+///
+/// Adapter that implements the `futures-io` traits `AsyncRead`, `AsyncWrite` and `AsyncBufRead` for a `RustTlsDuplexStream`.
+///
+/// The stream is switched to non-blocking reads and writes, a read or write that would block registers the
+/// task with `register_read_waker` or `register_write_waker` and returns `Pending`, so no executor thread ever blocks.
+/// A flush completes once the background writer flushed the underlying connection, `poll_close` sends a
+/// tls `close_notify` like `send_close_notify` and then flushes.
+///
+/// Only one task should read and one task should write through the adapter. A read or write of another thread
+/// through `get_ref` makes a poll return `Pending` without registering a wakeup for the end of that operation.
+///
+#[derive(Debug)]
+pub struct AsyncDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    /// The wrapped stream.
+    stream: RustTlsDuplexStream<C>,
+    /// Current chunk of `poll_fill_buf`.
+    buffer: Vec<u8>,
+    /// Amount of bytes in buffer that were already consumed.
+    pos: usize,
+    /// Flush of the underlying connection that is in progress.
+    flush: Option<Arc<TaskFlush>>,
+    /// Set once `close_notify` was queued.
+    closed: bool,
+}
+
+impl<C> AsyncDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    /// Constructor for `AsyncDuplexStream`, this enables non-blocking reads and writes of `stream`.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn new(stream: RustTlsDuplexStream<C>) -> io::Result<Self> {
+        stream.set_read_non_block(true)?;
+        stream.set_write_non_block(true)?;
+        Ok(Self {
+            stream,
+            buffer: Vec::new(),
+            pos: 0,
+            flush: None,
+            closed: false,
+        })
+    }
+
+    /// Returns the wrapped stream.
+    #[must_use]
+    pub const fn get_ref(&self) -> &RustTlsDuplexStream<C> {
+        &self.stream
+    }
+
+    /// Returns the wrapped stream, it stays non-blocking.
+    /// Data that was buffered by `poll_fill_buf` but not consumed yet is handed back, it can be read again.
+    #[must_use]
+    pub fn into_inner(self) -> RustTlsDuplexStream<C> {
+        self.stream.unread(&self.buffer[self.pos..]);
+        self.stream
+    }
+
+    /// Queues the records rust-tls holds and a flush marker the background writer notifies once it flushed.
+    fn start_flush(&self) -> io::Result<Arc<TaskFlush>> {
+        let outer_guard = unwrap_poison(self.stream.write_mutex.lock())?; //make writes block other writes
+        let mut guard = unwrap_poison(self.stream.connection.lock())?;
+        let res = guard.flush();
+        drop(guard);
+        res?;
+
+        let flush = Arc::new(TaskFlush::new());
+        let res = self.stream.write_q.try_push(WriteOp::Flush(Some(Done::Task(Arc::clone(&flush)))));
+        drop(outer_guard);
+        res.map_err(|err| self.stream.write_q_err(err))?;
+        Ok(flush)
+    }
+}
+
+/// Nothing is pinned structurally, the connection is only ever accessed through its mutex.
+impl<C> Unpin for AsyncDuplexStream<C> where C: TlsConnection {}
+
+impl<C> AsyncRead for AsyncDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.pos < this.buffer.len() {
+            let count = buf.len().min(this.buffer.len() - this.pos);
+            buf[..count].copy_from_slice(&this.buffer[this.pos..this.pos + count]);
+            this.pos += count;
+            return Poll::Ready(Ok(count));
+        }
+
+        let stream = &this.stream;
+        poll_io(cx, || stream.read(buf), |waker| stream.register_read_waker(waker))
+    }
+}
+
+impl<C> AsyncBufRead for AsyncDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.pos >= this.buffer.len() {
+            let stream = &this.stream;
+            match poll_io(cx, || stream.read_chunk(), |waker| stream.register_read_waker(waker)) {
+                Poll::Ready(Ok(chunk)) => {
+                    this.buffer = chunk;
+                    this.pos = 0;
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        Poll::Ready(Ok(&this.buffer[this.pos..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.pos = this.buffer.len().min(this.pos + amt);
+    }
+}
+
+impl<C> AsyncWrite for AsyncDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let stream = &self.stream;
+        poll_io(cx, || stream.write(buf), |waker| stream.register_write_waker(waker))
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        let stream = &self.stream;
+        poll_io(cx, || stream.write_vectored(bufs), |waker| stream.register_write_waker(waker))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let flush = if let Some(flush) = this.flush.take() {
+            flush
+        } else {
+            let this = &*this;
+            match poll_io(cx, || this.start_flush(), |waker| this.stream.register_write_waker(waker)) {
+                Poll::Ready(Ok(flush)) => flush,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        };
+
+        match flush.poll(cx) {
+            Ok(Poll::Ready(true)) => Poll::Ready(Ok(())),
+            Ok(Poll::Ready(false)) => Poll::Ready(Err(this.stream.write_q_err(queue_dead()))),
+            Ok(Poll::Pending) => {
+                this.flush = Some(flush);
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.closed {
+            //Queued inside rust-tls, the flush hands it to the background writer after all previously written data.
+            let outer_guard = unwrap_poison(this.stream.write_mutex.lock())?; //make writes block other writes
+            let mut guard = unwrap_poison(this.stream.connection.lock())?;
+            guard.conn.common_state_mut().send_close_notify();
+            drop(guard);
+            drop(outer_guard);
+            this.closed = true;
+        }
+
+        Pin::new(this).poll_flush(cx)
+    }
+}
@@ -0,0 +1,157 @@
+//! `BufRead` adapter for line oriented protocols.
+use crate::{RustTlsDuplexStream, TlsConnection};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::{BufRead, IoSlice, Read, Write};
+use std::time::Duration;
+
+/// Buffered view of a `RustTlsDuplexStream` that implements `BufRead`.
+///
+/// Reads are served from whole plaintext chunks of the stream so data is not copied twice.
+/// Writes pass through to the stream unbuffered.
+/// Data that was buffered but not consumed is handed back to the stream when this is dropped,
+/// so it can be read again with any of the streams read fns.
+pub struct BufferedDuplexStream<'a, C>
+where
+    C: TlsConnection,
+{
+    /// The stream we read from and write to.
+    stream: &'a RustTlsDuplexStream<C>,
+    /// Current chunk.
+    buffer: Vec<u8>,
+    /// Amount of bytes in buffer that were already consumed.
+    pos: usize,
+}
+
+impl<C> Debug for BufferedDuplexStream<'_, C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferedDuplexStream")
+            .field("stream", &self.stream)
+            .field("buffered", &(self.buffer.len() - self.pos))
+            .finish()
+    }
+}
+
+impl<'a, C> BufferedDuplexStream<'a, C>
+where
+    C: TlsConnection,
+{
+    /// Constructor for `BufferedDuplexStream`
+    pub(crate) const fn new(stream: &'a RustTlsDuplexStream<C>) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Returns the underlying stream.
+    #[must_use]
+    pub const fn get_ref(&self) -> &'a RustTlsDuplexStream<C> {
+        self.stream
+    }
+
+    /// Returns the data that is buffered but was not consumed yet.
+    #[must_use]
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer[self.pos..]
+    }
+
+    /// see `RustTlsDuplexStream::set_read_timeout`
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    /// see `RustTlsDuplexStream::read_timeout`
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        self.stream.read_timeout()
+    }
+
+    /// see `RustTlsDuplexStream::set_read_non_block`
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn set_read_non_block(&self, on: bool) -> io::Result<()> {
+        self.stream.set_read_non_block(on)
+    }
+
+    /// see `RustTlsDuplexStream::set_write_timeout`
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_write_timeout(timeout)
+    }
+
+    /// see `RustTlsDuplexStream::write_timeout`
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        self.stream.write_timeout()
+    }
+}
+
+impl<C> Drop for BufferedDuplexStream<'_, C>
+where
+    C: TlsConnection,
+{
+    fn drop(&mut self) {
+        self.stream.unread(&self.buffer[self.pos..]);
+    }
+}
+
+impl<C> BufRead for BufferedDuplexStream<'_, C>
+where
+    C: TlsConnection,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.buffer.len() {
+            self.buffer = self.stream.read_chunk()?;
+            self.pos = 0;
+        }
+
+        Ok(&self.buffer[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = self.buffer.len().min(self.pos + amt);
+    }
+}
+
+impl<C> Read for BufferedDuplexStream<'_, C>
+where
+    C: TlsConnection,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.buffer.len() && buf.len() >= self.buffer.capacity() {
+            //Nothing buffered and the caller has room for a whole chunk, skip the buffer.
+            return self.stream.read(buf);
+        }
+
+        let count = self.fill_buf()?.read(buf)?;
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl<C> Write for BufferedDuplexStream<'_, C>
+where
+    C: TlsConnection,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.stream.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
@@ -0,0 +1,51 @@
+//! Tokens that make blocked calls of a stream give up without closing the connection.
+use crate::error::DuplexStreamError;
+use crate::queue::Queue;
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
+
+/// Cancels blocking reads of a `RustTlsDuplexStream`, see `RustTlsDuplexStream::read_cancel_token`.
+///
+/// Once canceled, reads that would block fail with `DuplexStreamError::Canceled` until the token is reset.
+/// Reads that find data still succeed and writing is not affected, so the stream stays usable.
+#[derive(Debug, Clone)]
+pub struct ReadCancelToken {
+    /// Shared with the stream, set while canceled.
+    canceled: Arc<AtomicBool>,
+    /// Read queue of the stream, woken so blocked reads notice the cancellation.
+    queue: Arc<Queue>,
+}
+
+impl ReadCancelToken {
+    /// Constructor, the flag is shared with the stream.
+    pub(crate) const fn new(canceled: Arc<AtomicBool>, queue: Arc<Queue>) -> Self {
+        Self { canceled, queue }
+    }
+
+    /// Makes blocked and future blocking reads fail. Canceling again has no further effect.
+    pub fn cancel(&self) {
+        self.canceled.store(true, SeqCst);
+        self.queue.wake_all();
+    }
+
+    /// Returns true if the token is canceled.
+    #[must_use]
+    pub fn is_canceled(&self) -> bool {
+        self.canceled.load(SeqCst)
+    }
+
+    /// Lets reads block again.
+    pub fn reset(&self) {
+        self.canceled.store(false, SeqCst);
+    }
+}
+
+/// Error of a call that was canceled.
+///
+/// This is not `Interrupted` because `Read::read_exact` and friends retry those,
+/// which would never end while the token is canceled.
+pub fn canceled() -> io::Error {
+    DuplexStreamError::Canceled.into()
+}
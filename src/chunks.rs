@@ -0,0 +1,83 @@
+//! Iterator over decrypted chunks.
+use crate::{RustTlsDuplexStream, TlsConnection};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::ErrorKind;
+
+/// Iterator over the plaintext chunks of a `RustTlsDuplexStream`, see `RustTlsDuplexStream::chunks`.
+///
+/// Every call to `next` behaves like `RustTlsDuplexStream::read_chunk`:
+/// * A clean EOF ends the iteration.
+/// * `WouldBlock` (non-blocking mode), `TimedOut` (read timeout) and `Interrupted` are yielded
+///   without ending the iteration, calling `next` again retries.
+/// * Any other error is yielded once, after that the iteration ends.
+///
+/// Each chunk is handed to exactly one reader. Multiple iterators or other readers on the same stream
+/// receive disjoint chunks in the order they acquired the read mutex.
+pub struct Chunks<'a, C>
+where
+    C: TlsConnection,
+{
+    /// The stream we read from.
+    stream: &'a RustTlsDuplexStream<C>,
+    /// Set once EOF or a fatal error was returned.
+    done: bool,
+}
+
+impl<C> Debug for Chunks<'_, C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Chunks")
+            .field("stream", &self.stream)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl<'a, C> Chunks<'a, C>
+where
+    C: TlsConnection,
+{
+    /// Constructor for `Chunks`
+    pub(crate) const fn new(stream: &'a RustTlsDuplexStream<C>) -> Self {
+        Self {
+            stream,
+            done: false,
+        }
+    }
+}
+
+impl<C> Iterator for Chunks<'_, C>
+where
+    C: TlsConnection,
+{
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.stream.read_chunk() {
+            Ok(chunk) if chunk.is_empty() => {
+                self.done = true;
+                None
+            }
+            Ok(chunk) => Some(Ok(chunk)),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) =>
+            {
+                Some(Err(err))
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
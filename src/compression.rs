@@ -0,0 +1,276 @@
+//! Zlib compression layered on top of the plaintext of a stream.
+use crate::sync::Mutex;
+use crate::{unwrap_poison, RustTlsDuplexStream, TlsConnection};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+/// Amount of compressed bytes read from the stream at once.
+const INPUT_CHUNK: usize = 0x4000;
+
+/// Converts a difference of the `total_in`/`total_out` counters of flate2, which never exceeds a buffer length.
+fn delta(after: u64, before: u64) -> usize {
+    usize::try_from(after - before).unwrap_or(usize::MAX)
+}
+
+/// Deflate state of the writing side.
+#[derive(Debug)]
+struct Compressor {
+    /// The zlib stream.
+    inner: Compress,
+}
+
+impl Compressor {
+    /// Compresses all of `input` and returns the compressed bytes that are ready.
+    /// With `FlushCompress::Sync` everything that was compressed so far is returned.
+    fn compress(&mut self, input: &[u8], flush: FlushCompress) -> io::Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(input.len() / 2 + 64);
+        let mut consumed = 0;
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(output.capacity());
+            }
+
+            let before = self.inner.total_in();
+            self.inner
+                .compress_vec(&input[consumed..], &mut output, flush)
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+            consumed += delta(self.inner.total_in(), before);
+            //zlib is done once it consumed everything and did not fill the output.
+            if consumed == input.len() && output.len() < output.capacity() {
+                return Ok(output);
+            }
+        }
+    }
+}
+
+/// Inflate state of the reading side.
+#[derive(Debug)]
+struct Decompressor {
+    /// The zlib stream.
+    inner: Decompress,
+    /// Compressed bytes that were read from the stream.
+    input: Vec<u8>,
+    /// Amount of bytes of `input` that zlib consumed.
+    position: usize,
+    /// Set once the zlib stream ended.
+    finished: bool,
+}
+
+impl Decompressor {
+    /// Decompresses into `buf`, reading compressed bytes from `stream` if zlib needs more.
+    /// Blocks with the semantics of `RustTlsDuplexStream::read` only if nothing could be decompressed.
+    fn read<C: TlsConnection>(&mut self, stream: &RustTlsDuplexStream<C>, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.finished {
+            return Ok(0);
+        }
+
+        loop {
+            if self.position < self.input.len() {
+                let (before_in, before_out) = (self.inner.total_in(), self.inner.total_out());
+                let status = self
+                    .inner
+                    .decompress(&self.input[self.position..], buf, FlushDecompress::None)
+                    .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+                let consumed = delta(self.inner.total_in(), before_in);
+                let produced = delta(self.inner.total_out(), before_out);
+                self.position += consumed;
+                self.finished = status == Status::StreamEnd;
+                if produced > 0 || self.finished {
+                    return Ok(produced);
+                }
+
+                if consumed > 0 {
+                    continue;
+                }
+            }
+
+            if self.position == self.input.len() {
+                self.input.clear();
+                self.position = 0;
+            }
+
+            let mut chunk = [0u8; INPUT_CHUNK];
+            let count = stream.read(&mut chunk)?;
+            if count == 0 {
+                if self.position < self.input.len() {
+                    return Err(io::Error::new(ErrorKind::UnexpectedEof, "compressed data is truncated"));
+                }
+
+                //The peer may close without ending the zlib stream, everything it flushed was returned.
+                return Ok(0);
+            }
+
+            self.input.extend_from_slice(&chunk[..count]);
+        }
+    }
+}
+
+///
+/// Wrapper around a `RustTlsDuplexStream` that zlib compresses all data that is written
+/// and decompresses all data that is read. Both sides of the connection must use it.
+///
+/// Compressed data is only sent once the compressor emits it, `flush` makes it emit everything
+/// that was written so far before flushing the stream.
+/// Reading and writing can happen concurrently like with the wrapped stream.
+/// After an error the compressed stream is in an undefined state and should be dropped.
+///
+#[derive(Debug)]
+pub struct CompressedDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    /// The stream that carries the compressed data.
+    inner: RustTlsDuplexStream<C>,
+    /// Compresses written data, held for the entire write so the compressed data stays in order.
+    compressor: Mutex<Compressor>,
+    /// Decompresses read data, held for the entire read.
+    decompressor: Mutex<Decompressor>,
+}
+
+impl<C> CompressedDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    /// Wraps the stream with the default compression level.
+    #[must_use]
+    pub fn new(inner: RustTlsDuplexStream<C>) -> Self {
+        Self::with_level(inner, Compression::default())
+    }
+
+    /// Wraps the stream with the given compression level.
+    #[must_use]
+    pub fn with_level(inner: RustTlsDuplexStream<C>, level: Compression) -> Self {
+        Self {
+            inner,
+            compressor: Mutex::new(Compressor {
+                inner: Compress::new(level, true),
+            }),
+            decompressor: Mutex::new(Decompressor {
+                inner: Decompress::new(true),
+                input: Vec::new(),
+                position: 0,
+                finished: false,
+            }),
+        }
+    }
+
+    /// Returns the wrapped stream. Reading or writing it directly corrupts the compressed data.
+    pub const fn get_ref(&self) -> &RustTlsDuplexStream<C> {
+        &self.inner
+    }
+
+    /// Returns the wrapped stream, data that was written but not flushed is lost.
+    pub fn into_inner(self) -> RustTlsDuplexStream<C> {
+        self.inner
+    }
+
+    /// see `Read::read`
+    /// Blocks like `RustTlsDuplexStream::read` until at least one byte could be decompressed.
+    /// # Errors
+    /// same as `RustTlsDuplexStream::read`, `InvalidData` if the peer sent data that is not zlib compressed.
+    pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut guard = unwrap_poison(self.decompressor.lock())?;
+        let res = guard.read(&self.inner, buf);
+        drop(guard);
+        res
+    }
+
+    /// see `Write::write`
+    /// The entire buffer is compressed, the compressed bytes that are ready are written to the stream.
+    /// # Errors
+    /// same as `RustTlsDuplexStream::write_all`
+    pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.compress(buf, FlushCompress::None)?;
+        Ok(buf.len())
+    }
+
+    /// see `Write::flush`
+    /// Finishes a sync flush of the compressor so the peer can decompress everything written so far,
+    /// then flushes the stream.
+    /// # Errors
+    /// same as `RustTlsDuplexStream::flush`
+    pub fn flush(&self) -> io::Result<()> {
+        self.compress(&[], FlushCompress::Sync)?;
+        self.inner.flush()
+    }
+
+    /// Compresses and writes while holding the compressor.
+    fn compress(&self, buf: &[u8], flush: FlushCompress) -> io::Result<()> {
+        let mut guard = unwrap_poison(self.compressor.lock())?;
+        let compressed = guard.compress(buf, flush)?;
+        let res = if compressed.is_empty() {
+            Ok(())
+        } else {
+            self.inner.write_all(&compressed)
+        };
+        drop(guard);
+        res
+    }
+
+    /// see `RustTlsDuplexStream::set_read_timeout`
+    /// # Errors
+    /// same as `RustTlsDuplexStream::set_read_timeout`
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    /// see `RustTlsDuplexStream::set_write_timeout`
+    /// # Errors
+    /// same as `RustTlsDuplexStream::set_write_timeout`
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    /// see `RustTlsDuplexStream::set_read_non_block`
+    /// # Errors
+    /// same as `RustTlsDuplexStream::set_read_non_block`
+    pub fn set_read_non_block(&self, on: bool) -> io::Result<()> {
+        self.inner.set_read_non_block(on)
+    }
+}
+
+impl<C> Read for CompressedDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Self::read(self, buf)
+    }
+}
+
+impl<C> Read for &CompressedDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        CompressedDuplexStream::read(self, buf)
+    }
+}
+
+impl<C> Write for CompressedDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Self::write(self, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Self::flush(self)
+    }
+}
+
+impl<C> Write for &CompressedDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        CompressedDuplexStream::write(self, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        CompressedDuplexStream::flush(self)
+    }
+}
@@ -0,0 +1,382 @@
+//! Abstraction over the rustls connection types that can be driven by the duplex stream.
+use rustls::{
+    ClientConnection, CommonState, Connection, ConnectionCommon, IoState, Reader, ServerConnection,
+    SideData, Writer,
+};
+use std::io;
+use std::io::{BufRead, ErrorKind, IoSlice, Read, Write};
+
+///
+/// A rustls connection that can be wrapped by `RustTlsDuplexStream`.
+///
+/// This is implemented for `ClientConnection`, `ServerConnection`, `ConnectionCommon`,
+/// the `rustls::Connection` enum and boxes of any of those.
+/// Use `rustls::Connection` if you need to hold client and server connections in the same collection.
+///
+pub trait TlsConnection: Send {
+    /// Returns the state shared by client and server connections.
+    fn common_state(&self) -> &CommonState;
+
+    /// Returns the state shared by client and server connections mutably.
+    fn common_state_mut(&mut self) -> &mut CommonState;
+
+    /// see `ConnectionCommon::complete_io`
+    /// # Errors
+    /// propagated from the io or rustls
+    fn complete_io<T: Read + Write>(&mut self, io: &mut T) -> io::Result<(usize, usize)>;
+
+    /// see `ConnectionCommon::reader`
+    fn reader(&mut self) -> Reader<'_>;
+
+    /// see `ConnectionCommon::writer`
+    fn writer(&mut self) -> Writer<'_>;
+
+    /// see `ConnectionCommon::process_new_packets`
+    /// # Errors
+    /// propagated from rustls, i.e. if the peer violated the protocol
+    fn process_new_packets(&mut self) -> Result<IoState, rustls::Error>;
+
+    /// see `ConnectionCommon::refresh_traffic_keys`
+    /// # Errors
+    /// propagated from rustls, i.e. if the connection is not a tls 1.3 connection that finished its handshake
+    fn refresh_traffic_keys(&mut self) -> Result<(), rustls::Error>;
+
+    /// see `ConnectionCommon::export_keying_material`
+    /// # Errors
+    /// propagated from rustls, i.e. if the handshake did not finish yet or `output` is empty
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: Option<&[u8]>,
+    ) -> Result<(), rustls::Error>;
+
+    /// The server name the client indicated with SNI. Only servers know it, the default returns None.
+    fn server_name(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl<D: SideData + Send> TlsConnection for ConnectionCommon<D> {
+    fn common_state(&self) -> &CommonState {
+        self
+    }
+
+    fn common_state_mut(&mut self) -> &mut CommonState {
+        self
+    }
+
+    fn complete_io<T: Read + Write>(&mut self, io: &mut T) -> io::Result<(usize, usize)> {
+        Self::complete_io(self, io)
+    }
+
+    fn reader(&mut self) -> Reader<'_> {
+        Self::reader(self)
+    }
+
+    fn writer(&mut self) -> Writer<'_> {
+        Self::writer(self)
+    }
+
+    fn process_new_packets(&mut self) -> Result<IoState, rustls::Error> {
+        Self::process_new_packets(self)
+    }
+    fn refresh_traffic_keys(&mut self) -> Result<(), rustls::Error> {
+        Self::refresh_traffic_keys(self)
+    }
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: Option<&[u8]>,
+    ) -> Result<(), rustls::Error> {
+        Self::export_keying_material(self, output, label, context).map(|_| ())
+    }
+}
+
+impl TlsConnection for ClientConnection {
+    fn common_state(&self) -> &CommonState {
+        self
+    }
+
+    fn common_state_mut(&mut self) -> &mut CommonState {
+        self
+    }
+
+    fn complete_io<T: Read + Write>(&mut self, io: &mut T) -> io::Result<(usize, usize)> {
+        TlsConnection::complete_io(&mut **self, io)
+    }
+
+    fn reader(&mut self) -> Reader<'_> {
+        TlsConnection::reader(&mut **self)
+    }
+
+    fn writer(&mut self) -> Writer<'_> {
+        TlsConnection::writer(&mut **self)
+    }
+
+    fn process_new_packets(&mut self) -> Result<IoState, rustls::Error> {
+        TlsConnection::process_new_packets(&mut **self)
+    }
+    fn refresh_traffic_keys(&mut self) -> Result<(), rustls::Error> {
+        TlsConnection::refresh_traffic_keys(&mut **self)
+    }
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: Option<&[u8]>,
+    ) -> Result<(), rustls::Error> {
+        TlsConnection::export_keying_material(&**self, output, label, context)
+    }
+}
+
+impl TlsConnection for ServerConnection {
+    fn common_state(&self) -> &CommonState {
+        self
+    }
+
+    fn common_state_mut(&mut self) -> &mut CommonState {
+        self
+    }
+
+    fn complete_io<T: Read + Write>(&mut self, io: &mut T) -> io::Result<(usize, usize)> {
+        TlsConnection::complete_io(&mut **self, io)
+    }
+
+    fn reader(&mut self) -> Reader<'_> {
+        TlsConnection::reader(&mut **self)
+    }
+
+    fn writer(&mut self) -> Writer<'_> {
+        TlsConnection::writer(&mut **self)
+    }
+
+    fn process_new_packets(&mut self) -> Result<IoState, rustls::Error> {
+        TlsConnection::process_new_packets(&mut **self)
+    }
+    fn refresh_traffic_keys(&mut self) -> Result<(), rustls::Error> {
+        TlsConnection::refresh_traffic_keys(&mut **self)
+    }
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: Option<&[u8]>,
+    ) -> Result<(), rustls::Error> {
+        TlsConnection::export_keying_material(&**self, output, label, context)
+    }
+
+    fn server_name(&self) -> Option<&str> {
+        Self::server_name(self)
+    }
+}
+
+impl TlsConnection for Connection {
+    fn common_state(&self) -> &CommonState {
+        self
+    }
+
+    fn common_state_mut(&mut self) -> &mut CommonState {
+        self
+    }
+
+    fn complete_io<T: Read + Write>(&mut self, io: &mut T) -> io::Result<(usize, usize)> {
+        Self::complete_io(self, io)
+    }
+
+    fn reader(&mut self) -> Reader<'_> {
+        Self::reader(self)
+    }
+
+    fn writer(&mut self) -> Writer<'_> {
+        Self::writer(self)
+    }
+
+    fn process_new_packets(&mut self) -> Result<IoState, rustls::Error> {
+        Self::process_new_packets(self)
+    }
+    fn refresh_traffic_keys(&mut self) -> Result<(), rustls::Error> {
+        Self::refresh_traffic_keys(self)
+    }
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: Option<&[u8]>,
+    ) -> Result<(), rustls::Error> {
+        Self::export_keying_material(self, output, label, context).map(|_| ())
+    }
+
+    fn server_name(&self) -> Option<&str> {
+        match self {
+            Self::Client(_) => None,
+            Self::Server(conn) => conn.server_name(),
+        }
+    }
+}
+
+impl<C: TlsConnection> TlsConnection for Box<C> {
+    fn common_state(&self) -> &CommonState {
+        C::common_state(self)
+    }
+
+    fn common_state_mut(&mut self) -> &mut CommonState {
+        C::common_state_mut(self)
+    }
+
+    fn complete_io<T: Read + Write>(&mut self, io: &mut T) -> io::Result<(usize, usize)> {
+        C::complete_io(self, io)
+    }
+
+    fn reader(&mut self) -> Reader<'_> {
+        C::reader(self)
+    }
+
+    fn writer(&mut self) -> Writer<'_> {
+        C::writer(self)
+    }
+
+    fn process_new_packets(&mut self) -> Result<IoState, rustls::Error> {
+        C::process_new_packets(self)
+    }
+    fn refresh_traffic_keys(&mut self) -> Result<(), rustls::Error> {
+        C::refresh_traffic_keys(self)
+    }
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: Option<&[u8]>,
+    ) -> Result<(), rustls::Error> {
+        C::export_keying_material(self, output, label, context)
+    }
+
+    fn server_name(&self) -> Option<&str> {
+        C::server_name(self)
+    }
+}
+
+/// Owned combination of a `TlsConnection` and its transport.
+/// This is the same as rust-tls `StreamOwned` except that it works for every `TlsConnection`.
+#[derive(Debug)]
+pub struct TlsStream<C, T> {
+    /// Our connection
+    pub conn: C,
+    /// The underlying transport
+    pub sock: T,
+}
+
+impl<C: TlsConnection, T: Read + Write> TlsStream<C, T> {
+    /// Constructor for `TlsStream`
+    pub const fn new(conn: C, sock: T) -> Self {
+        Self { conn, sock }
+    }
+
+    /// If we're handshaking, complete all the IO for that.
+    /// If we have data to write, write it all.
+    fn complete_prior_io(&mut self) -> io::Result<()> {
+        if self.conn.common_state().is_handshaking() {
+            self.conn.complete_io(&mut self.sock)?;
+        }
+
+        if self.conn.common_state().wants_write() {
+            self.conn.complete_io(&mut self.sock)?;
+        }
+
+        Ok(())
+    }
+    /// Drives the handshake and hands records that were not written yet to the transport.
+    /// Returns true if a write would only have to encrypt the plaintext.
+    pub fn writable(&mut self) -> io::Result<bool> {
+        self.complete_prior_io()?;
+
+        let state = self.conn.common_state();
+        Ok(!state.is_handshaking() && !state.wants_write())
+    }
+
+    /// Processes all ciphertext the transport hands out and returns true if a read would return
+    /// plaintext or EOF without waiting for more.
+    pub fn readable(&mut self) -> io::Result<bool> {
+        self.complete_prior_io()?;
+
+        while self.conn.common_state().wants_read() {
+            if self.conn.complete_io(&mut self.sock)?.0 == 0 {
+                break;
+            }
+        }
+
+        let state = self
+            .conn
+            .process_new_packets()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        Ok(state.plaintext_bytes_to_read() > 0 || state.peer_has_closed())
+    }
+
+    /// Hands all ciphertext a single read needs to rust-tls.
+    fn complete_read_io(&mut self) -> io::Result<()> {
+        self.complete_prior_io()?;
+
+        // A single call may only read a partial packet from the transport.
+        while self.conn.common_state().wants_read() {
+            if self.conn.complete_io(&mut self.sock)?.0 == 0 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like `read` but drops up to `n` bytes of plaintext instead of copying them out.
+    /// Returns the amount of bytes dropped, 0 means EOF.
+    pub fn skip(&mut self, n: usize) -> io::Result<usize> {
+        self.complete_read_io()?;
+
+        let mut reader = self.conn.reader();
+        let len = reader.fill_buf()?.len().min(n);
+        reader.consume(len);
+        Ok(len)
+    }
+}
+
+impl<C: TlsConnection, T: Read + Write> Read for TlsStream<C, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.complete_read_io()?;
+
+        self.conn.reader().read(buf)
+    }
+}
+
+impl<C: TlsConnection, T: Read + Write> Write for TlsStream<C, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.complete_prior_io()?;
+
+        let len = self.conn.writer().write(buf)?;
+
+        // Errors must not mask the fact that we consumed len bytes, the next call will report them.
+        _ = self.conn.complete_io(&mut self.sock);
+
+        Ok(len)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.complete_prior_io()?;
+
+        let len = self.conn.writer().write_vectored(bufs)?;
+
+        // Errors must not mask the fact that we consumed len bytes, the next call will report them.
+        _ = self.conn.complete_io(&mut self.sock);
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.complete_prior_io()?;
+
+        self.conn.writer().flush()?;
+        if self.conn.common_state().wants_write() {
+            self.conn.complete_io(&mut self.sock)?;
+        }
+        Ok(())
+    }
+}
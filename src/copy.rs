@@ -0,0 +1,209 @@
+//! Plaintext forwarding between two endpoints.
+use crate::sync::Mutex;
+use crate::{unwrap_poison, RustTlsDuplexStream, TlsConnection};
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::thread;
+
+///
+/// An endpoint that `copy_bidirectional` can forward data from and to.
+/// Both directions use the endpoint concurrently from different threads.
+///
+/// This is implemented for `RustTlsDuplexStream`, `TcpStream` and `PlainEndpoint`
+/// which wraps any other `Read` + `Write` pair.
+///
+pub trait ProxyEndpoint: Sync {
+    /// see `Read::read`, `Ok(0)` is EOF.
+    /// # Errors
+    /// propagated from the endpoint
+    fn proxy_read(&self, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// see `Write::write_all`
+    /// # Errors
+    /// propagated from the endpoint
+    fn proxy_write_all(&self, buffer: &[u8]) -> io::Result<()>;
+
+    /// Signals EOF to the peer once everything written before was delivered.
+    /// # Errors
+    /// propagated from the endpoint
+    fn shutdown_write(&self) -> io::Result<()>;
+
+    /// Makes pending and future reads return, this is used to stop the other direction after an error.
+    fn shutdown_read(&self);
+}
+
+impl<C> ProxyEndpoint for RustTlsDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn proxy_read(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.read(buffer)
+    }
+
+    fn proxy_write_all(&self, buffer: &[u8]) -> io::Result<()> {
+        self.write_all(buffer)
+    }
+
+    fn shutdown_write(&self) -> io::Result<()> {
+        self.send_close_notify()
+    }
+
+    fn shutdown_read(&self) {
+        self.kill_read();
+    }
+}
+
+impl ProxyEndpoint for TcpStream {
+    fn proxy_read(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        (&mut &*self).read(buffer)
+    }
+
+    fn proxy_write_all(&self, buffer: &[u8]) -> io::Result<()> {
+        (&mut &*self).write_all(buffer)
+    }
+
+    fn shutdown_write(&self) -> io::Result<()> {
+        self.shutdown(Shutdown::Write)
+    }
+
+    fn shutdown_read(&self) {
+        _ = self.shutdown(Shutdown::Read);
+    }
+}
+
+///
+/// Adapter that turns a separate `Read` and `Write` into a `ProxyEndpoint`.
+///
+/// `shutdown_write` flushes and drops the writer, which signals EOF for pipes and similar writers.
+/// `shutdown_read` can not interrupt a read that is blocked in the reader,
+/// the reader should have a timeout or be closed by other means if the other endpoint may fail.
+///
+#[derive(Debug)]
+pub struct PlainEndpoint<R, W> {
+    /// The read half.
+    read: Mutex<R>,
+    /// The write half, None once shut down.
+    write: Mutex<Option<W>>,
+    /// Set once reading should stop.
+    read_shutdown: AtomicBool,
+}
+
+impl<R, W> PlainEndpoint<R, W>
+where
+    R: Read + Send,
+    W: Write + Send,
+{
+    /// Constructor for `PlainEndpoint`
+    pub const fn new(read: R, write: W) -> Self {
+        Self {
+            read: Mutex::new(read),
+            write: Mutex::new(Some(write)),
+            read_shutdown: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<R, W> ProxyEndpoint for PlainEndpoint<R, W>
+where
+    R: Read + Send,
+    W: Write + Send,
+{
+    fn proxy_read(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.read_shutdown.load(SeqCst) {
+            return Err(io::Error::from(ErrorKind::BrokenPipe));
+        }
+
+        unwrap_poison(self.read.lock())?.read(buffer)
+    }
+
+    fn proxy_write_all(&self, buffer: &[u8]) -> io::Result<()> {
+        let mut guard = unwrap_poison(self.write.lock())?;
+        let Some(write) = guard.as_mut() else {
+            return Err(io::Error::from(ErrorKind::BrokenPipe));
+        };
+        let res = write.write_all(buffer);
+        drop(guard);
+        res
+    }
+
+    fn shutdown_write(&self) -> io::Result<()> {
+        let Some(mut write) = unwrap_poison(self.write.lock())?.take() else {
+            return Ok(());
+        };
+        write.flush()
+    }
+
+    fn shutdown_read(&self) {
+        self.read_shutdown.store(true, SeqCst);
+    }
+}
+
+///
+/// Forwards data between 2 endpoints in both directions until both directions reached EOF.
+/// This is the building block of a tls terminating proxy.
+///
+/// One direction is copied by a scoped thread, the other one by the calling thread.
+/// EOF of one endpoint is propagated with `shutdown_write` to the other endpoint,
+/// for a `RustTlsDuplexStream` this sends a `close_notify`.
+/// If one direction fails the read side of the other direction is shut down so its thread does not stay blocked forever.
+///
+/// Returns the amount of bytes copied from `a` to `b` and from `b` to `a`.
+///
+/// # Errors
+/// The error of the direction that failed first.
+///
+pub fn copy_bidirectional<A, B>(a: &A, b: &B) -> io::Result<(u64, u64)>
+where
+    A: ProxyEndpoint + ?Sized,
+    B: ProxyEndpoint + ?Sized,
+{
+    let failed = AtomicBool::new(false);
+    let ((a_to_b, a_res), (b_to_a, b_res)) = thread::scope(|scope| {
+        let forward = scope.spawn(|| copy_one(a, b, &failed));
+        let backward = copy_one(b, a, &failed);
+        let forward = forward.join().unwrap_or_else(|_| {
+            b.shutdown_read();
+            (0, Err(io::Error::other("copy thread panicked")))
+        });
+        (forward, backward)
+    });
+
+    match (a_res, b_res) {
+        (Err(err), _) | (_, Err(err)) => Err(err),
+        _ => Ok((a_to_b, b_to_a)),
+    }
+}
+
+/// Copies from `src` to `dst` until EOF or an error.
+/// Returns the amount of bytes copied and the error if this direction failed first.
+fn copy_one<S, D>(src: &S, dst: &D, failed: &AtomicBool) -> (u64, io::Result<()>)
+where
+    S: ProxyEndpoint + ?Sized,
+    D: ProxyEndpoint + ?Sized,
+{
+    let mut buffer = vec![0u8; 0x4000];
+    let mut total = 0u64;
+    let res = loop {
+        let count = match src.proxy_read(&mut buffer) {
+            Ok(0) => break dst.shutdown_write(),
+            Ok(count) => count,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => break Err(err),
+        };
+
+        if let Err(err) = dst.proxy_write_all(&buffer[..count]) {
+            break Err(err);
+        }
+        total += count as u64;
+    };
+
+    if res.is_err() && !failed.swap(true, SeqCst) {
+        dst.shutdown_read(); //The other direction reads from dst.
+        return (total, res);
+    }
+
+    (total, Ok(()))
+}
@@ -0,0 +1,161 @@
+//! Structured error type for callers that want to match on the cause instead of an `io::ErrorKind`.
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::ErrorKind;
+
+/// Result with a `DuplexStreamError`.
+pub type Result<T> = std::result::Result<T, DuplexStreamError>;
+
+/// Cause of an error returned by the stream.
+///
+/// The stream returns `io::Error` so it can implement `Read` and `Write`, convert those with `DuplexStreamError::from`.
+/// `handshake`, `ping` and `keying_material` return it directly, their `io::Result` predecessors are deprecated.
+/// Errors created by this crate carry a `DuplexStreamError` as payload, errors of rust-tls carry a `rustls::Error`,
+/// both are recovered by the conversion. A `TimedOut` without payload becomes `Timeout`, everything else ends up in `Io`.
+#[derive(Debug)]
+pub enum DuplexStreamError {
+    /// rust-tls rejected the connection, i.e. the peer violated the protocol or sent an alert.
+    Tls(rustls::Error),
+    /// The underlying connection failed or another io error occurred.
+    Io(io::Error),
+    /// A background thread of the connection died without reporting an error.
+    QueueDead,
+    /// A thread panicked while holding a mutex of the stream.
+    PoisonedMutex,
+    /// A timeout elapsed or a deadline passed.
+    Timeout,
+    /// The call was canceled by a cancel token.
+    Canceled,
+}
+
+impl Display for DuplexStreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tls(err) => write!(f, "tls error: {err}"),
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::QueueDead => f.write_str("queue is dead"),
+            Self::PoisonedMutex => f.write_str("Poisoned Mutex"),
+            Self::Timeout => f.write_str("timed out"),
+            Self::Canceled => f.write_str("canceled"),
+        }
+    }
+}
+
+impl Error for DuplexStreamError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Tls(err) => Some(err),
+            Self::Io(err) => Some(err),
+            Self::QueueDead | Self::PoisonedMutex | Self::Timeout | Self::Canceled => None,
+        }
+    }
+}
+
+impl From<rustls::Error> for DuplexStreamError {
+    fn from(err: rustls::Error) -> Self {
+        Self::Tls(err)
+    }
+}
+
+impl From<io::Error> for DuplexStreamError {
+    fn from(err: io::Error) -> Self {
+        let kind = err.kind();
+        if !err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<Self>() || inner.is::<rustls::Error>())
+        {
+            //A payload like `DeadlineExceeded` carries details that `Timeout` would lose.
+            return if kind == ErrorKind::TimedOut && err.get_ref().is_none() {
+                Self::Timeout
+            } else {
+                Self::Io(err)
+            };
+        }
+
+        let Some(inner) = err.into_inner() else {
+            return Self::Io(io::Error::from(kind));
+        };
+
+        match inner.downcast::<Self>() {
+            Ok(own) => *own,
+            Err(inner) => inner
+                .downcast::<rustls::Error>()
+                .map_or_else(|inner| Self::Io(io::Error::new(kind, inner)), |tls| Self::Tls(*tls)),
+        }
+    }
+}
+
+impl From<DuplexStreamError> for io::Error {
+    fn from(err: DuplexStreamError) -> Self {
+        match err {
+            DuplexStreamError::Io(err) => err,
+            DuplexStreamError::Tls(err) => Self::new(ErrorKind::InvalidData, err),
+            DuplexStreamError::QueueDead => Self::new(ErrorKind::BrokenPipe, err),
+            DuplexStreamError::PoisonedMutex | DuplexStreamError::Canceled => Self::other(err),
+            DuplexStreamError::Timeout => Self::new(ErrorKind::TimedOut, err),
+        }
+    }
+}
+
+/// Error of a queue that was killed.
+pub fn queue_dead() -> io::Error {
+    DuplexStreamError::QueueDead.into()
+}
+
+/// Error of a poisoned mutex.
+#[cfg(not(feature = "parking_lot"))]
+pub fn poisoned() -> io::Error {
+    DuplexStreamError::PoisonedMutex.into()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::DuplexStreamError;
+    use crate::DeadlineExceeded;
+    use std::io;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn round_trip() {
+        for err in [
+            DuplexStreamError::QueueDead,
+            DuplexStreamError::PoisonedMutex,
+            DuplexStreamError::Timeout,
+            DuplexStreamError::Canceled,
+        ] {
+            let expected = err.to_string();
+            let io_err = io::Error::from(err);
+            assert_eq!(DuplexStreamError::from(io_err).to_string(), expected);
+        }
+
+        assert_eq!(io::Error::from(DuplexStreamError::QueueDead).kind(), ErrorKind::BrokenPipe);
+        let tls = io::Error::new(ErrorKind::InvalidData, rustls::Error::DecryptError);
+        assert!(matches!(
+            DuplexStreamError::from(tls),
+            DuplexStreamError::Tls(rustls::Error::DecryptError)
+        ));
+    }
+
+    #[test]
+    fn foreign_errors_stay_io() {
+        assert!(matches!(
+            DuplexStreamError::from(io::Error::from(ErrorKind::TimedOut)),
+            DuplexStreamError::Timeout
+        ));
+        let err = DuplexStreamError::from(io::Error::new(ErrorKind::ConnectionReset, "reset"));
+        let DuplexStreamError::Io(err) = err else {
+            panic!("expected io error");
+        };
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+        assert_eq!(err.to_string(), "reset");
+
+        let exceeded = DeadlineExceeded { transferred: 4 };
+        let err = DuplexStreamError::from(io::Error::new(ErrorKind::TimedOut, exceeded));
+        let DuplexStreamError::Io(err) = err else {
+            panic!("expected io error");
+        };
+        assert_eq!(err.get_ref().and_then(|err| err.downcast_ref::<DeadlineExceeded>()), Some(&exceeded));
+    }
+}
@@ -0,0 +1,203 @@
+//! Lifecycle events of a stream.
+use crate::info::ConnectionInfo;
+use crate::sync::Mutex;
+use crate::unwrap_poison;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::ErrorKind;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::{mpsc, Arc};
+
+///
+/// Receives the lifecycle events of a stream, see `RustTlsDuplexStream::set_event_handler`.
+///
+/// Each event is delivered at most once per connection and never while the connection mutex is held.
+/// Events of the background threads are delivered on those threads. Every method does nothing by default.
+///
+pub trait StreamEvents: Send + Sync {
+    /// The handshake finished, `info` holds the negotiated parameters. Not delivered once an error was.
+    fn on_handshake_complete(&self, _info: &ConnectionInfo) {}
+
+    /// The peer closed the connection, either with a tls `close_notify` or by closing the transport.
+    /// Received data may still wait to be read.
+    fn on_peer_closed(&self) {}
+
+    /// The transport failed or rust-tls refused the data of the peer, the connection is dead.
+    fn on_error(&self, _err: &io::Error) {}
+}
+
+///
+/// Readiness event of a stream, see `RustTlsDuplexStream::subscribe`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamEvent {
+    /// Received data waits to be read. Sent once the buffer of received data becomes non-empty,
+    /// not again until it was read empty.
+    Readable,
+    /// A non-blocking write or flush failed with `WouldBlock` because the queue was full,
+    /// the queue drained to its low watermark since. Sent once per refusal.
+    WriteCapacityAvailable,
+    /// The peer closed the connection, received data may still wait to be read.
+    PeerClosed,
+    /// The connection failed with an error of this kind. This is the last event.
+    Error(ErrorKind),
+}
+
+/// Slot for an optional event handler that remembers which events were delivered.
+pub struct EventSlot {
+    /// The handler, cleared once it panicked.
+    handler: Mutex<Option<Arc<dyn StreamEvents>>>,
+    /// Set once the handshake finished.
+    handshake: AtomicBool,
+    /// Set once the peer closed the connection.
+    closed: AtomicBool,
+    /// Set once the connection failed.
+    failed: AtomicBool,
+    /// Senders of `subscribe`, None once the stream died.
+    subscribers: Mutex<Option<Vec<mpsc::Sender<StreamEvent>>>>,
+}
+
+impl Default for EventSlot {
+    fn default() -> Self {
+        Self {
+            handler: Mutex::default(),
+            handshake: AtomicBool::default(),
+            closed: AtomicBool::default(),
+            failed: AtomicBool::default(),
+            subscribers: Mutex::new(Some(Vec::new())),
+        }
+    }
+}
+
+impl Debug for EventSlot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let set = unwrap_poison(self.handler.lock()).map(|handler| handler.is_some());
+        f.debug_struct("EventSlot")
+            .field("set", &set.ok())
+            .field("handshake", &self.handshake)
+            .field("closed", &self.closed)
+            .field("failed", &self.failed)
+            .field("subscribers", &unwrap_poison(self.subscribers.lock()).ok().map(|guard| guard.as_ref().map(Vec::len)))
+            .finish()
+    }
+}
+
+impl EventSlot {
+    /// Replaces the handler.
+    pub fn set(&self, handler: Arc<dyn StreamEvents>) {
+        if let Ok(mut guard) = unwrap_poison(self.handler.lock()) {
+            *guard = Some(handler);
+        }
+    }
+
+    /// Forgets which events were delivered and accepts subscribers again, for a new connection.
+    pub fn reset(&self) {
+        self.handshake.store(false, SeqCst);
+        self.closed.store(false, SeqCst);
+        self.failed.store(false, SeqCst);
+        if let Ok(mut guard) = unwrap_poison(self.subscribers.lock()) {
+            guard.get_or_insert_with(Vec::new);
+        }
+    }
+
+    /// Returns a receiver for the events sent from now on, it receives `Readable` first if `readable` is set.
+    /// It is disconnected right away if the stream died.
+    pub fn subscribe(&self, readable: bool) -> mpsc::Receiver<StreamEvent> {
+        let (sender, receiver) = mpsc::channel();
+        if readable {
+            _ = sender.send(StreamEvent::Readable);
+        }
+        if let Ok(mut guard) = unwrap_poison(self.subscribers.lock()) {
+            if let Some(subscribers) = guard.as_mut() {
+                subscribers.push(sender);
+            }
+        }
+        receiver
+    }
+
+    /// Drops every subscriber, their receivers disconnect once they received the events sent before.
+    pub fn close_subscribers(&self) {
+        if let Ok(mut guard) = unwrap_poison(self.subscribers.lock()) {
+            *guard = None;
+        }
+    }
+
+    /// Sends `Readable` to the subscribers.
+    pub fn readable(&self) {
+        self.broadcast(StreamEvent::Readable);
+    }
+
+    /// Sends `WriteCapacityAvailable` to the subscribers.
+    pub fn write_capacity(&self) {
+        self.broadcast(StreamEvent::WriteCapacityAvailable);
+    }
+
+    /// Returns true while the end of the handshake was neither delivered nor made pointless by an error.
+    pub fn handshake_pending(&self) -> bool {
+        !self.handshake.load(SeqCst) && !self.failed.load(SeqCst)
+    }
+
+    /// Delivers the end of the handshake unless it or an error was delivered before.
+    pub fn handshake_complete(&self, info: &ConnectionInfo) {
+        if self.failed.load(SeqCst) || self.handshake.swap(true, SeqCst) {
+            return;
+        }
+
+        self.call(|handler| handler.on_handshake_complete(info));
+    }
+
+    /// Delivers that the peer closed the connection unless that was delivered before.
+    pub fn peer_closed(&self) {
+        if self.closed.swap(true, SeqCst) {
+            return;
+        }
+
+        self.broadcast(StreamEvent::PeerClosed);
+        self.call(|handler| handler.on_peer_closed());
+    }
+
+    /// Delivers the error unless an error was delivered before.
+    pub fn error(&self, err: &io::Error) {
+        if self.failed.swap(true, SeqCst) {
+            return;
+        }
+
+        self.broadcast(StreamEvent::Error(err.kind()));
+        self.close_subscribers();
+        self.call(|handler| handler.on_error(err));
+    }
+
+    /// Sends the event to every subscriber, those whose receiver was dropped are removed.
+    fn broadcast(&self, event: StreamEvent) {
+        if let Ok(mut guard) = unwrap_poison(self.subscribers.lock()) {
+            if let Some(subscribers) = guard.as_mut() {
+                subscribers.retain(|subscriber| subscriber.send(event).is_ok());
+            }
+        }
+    }
+
+    /// Invokes the handler if one is set, without holding any lock. A handler that panics is cleared.
+    fn call(&self, event: impl FnOnce(&dyn StreamEvents)) {
+        let Ok(guard) = unwrap_poison(self.handler.lock()) else {
+            return;
+        };
+
+        let Some(handler) = guard.as_ref().map(Arc::clone) else {
+            return;
+        };
+        drop(guard);
+
+        if panic::catch_unwind(AssertUnwindSafe(|| event(handler.as_ref()))).is_err() {
+            warn!("event handler panicked, it was removed");
+            if let Ok(mut guard) = unwrap_poison(self.handler.lock()) {
+                //It may have been replaced while it ran.
+                if guard.as_ref().is_some_and(|current| Arc::ptr_eq(current, &handler)) {
+                    *guard = None;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! FIFO turns for threads that read from the same stream.
+use crate::sync::Mutex;
+use crate::unwrap_poison;
+use std::collections::VecDeque;
+use std::io;
+use std::io::ErrorKind;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+use std::thread;
+use std::thread::Thread;
+use std::time::Instant;
+
+/// A thread that waits for its turn.
+#[derive(Debug)]
+struct Waiter {
+    /// Identifies the waiter, tickets are handed out in arrival order.
+    ticket: u64,
+    /// Unparked once the waiter is at the front.
+    thread: Thread,
+}
+
+///
+/// Line of threads that take turns in arrival order.
+///
+/// Unlike a mutex this hands the turn to the longest waiting thread and only unparks that thread,
+/// so every waiter is woken once per turn instead of all of them racing for it.
+///
+#[derive(Debug, Default)]
+pub struct FairReadQueue {
+    /// Waiting threads, the front one holds the turn.
+    waiters: Mutex<VecDeque<Waiter>>,
+    /// Next ticket to hand out.
+    next_ticket: AtomicU64,
+}
+
+/// Turn of a thread, the next waiter is woken once this is dropped.
+#[derive(Debug)]
+pub struct Turn<'a> {
+    /// The line this turn belongs to.
+    queue: &'a FairReadQueue,
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        let Ok(mut guard) = unwrap_poison(self.queue.waiters.lock()) else {
+            return;
+        };
+
+        guard.pop_front();
+        let next = guard.front().map(|waiter| waiter.thread.clone());
+        drop(guard);
+        if let Some(next) = next {
+            next.unpark();
+        }
+    }
+}
+
+impl FairReadQueue {
+    /// Waits until every thread that entered earlier left, returns right away if nobody waits.
+    /// # Errors
+    /// `TimedOut` once the deadline passed, `WouldBlock` if `non_blocking` is set and another thread holds the turn.
+    /// In case of poisoned mutex
+    pub fn enter(&self, deadline: Option<Instant>, non_blocking: bool) -> io::Result<Turn<'_>> {
+        let mut guard = unwrap_poison(self.waiters.lock())?;
+        if non_blocking && !guard.is_empty() {
+            return Err(io::Error::from(ErrorKind::WouldBlock));
+        }
+
+        let ticket = self.next_ticket.fetch_add(1, Relaxed);
+        guard.push_back(Waiter {
+            ticket,
+            thread: thread::current(),
+        });
+
+        while guard.front().is_some_and(|waiter| waiter.ticket != ticket) {
+            drop(guard);
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(self.leave(ticket));
+                    }
+                    thread::park_timeout(deadline - now);
+                }
+                None => thread::park(),
+            }
+            guard = unwrap_poison(self.waiters.lock())?;
+        }
+
+        drop(guard);
+        Ok(Turn { queue: self })
+    }
+
+    /// Removes a waiter that gave up before its turn came and returns the `TimedOut` error for it.
+    fn leave(&self, ticket: u64) -> io::Error {
+        let Ok(mut guard) = unwrap_poison(self.waiters.lock()) else {
+            return io::Error::from(ErrorKind::TimedOut);
+        };
+
+        let at_front = guard.front().is_some_and(|waiter| waiter.ticket == ticket);
+        if at_front {
+            //The turn came right after the deadline passed, hand it on.
+            drop(guard);
+            drop(Turn { queue: self });
+        } else {
+            guard.retain(|waiter| waiter.ticket != ticket);
+            drop(guard);
+        }
+
+        io::Error::from(ErrorKind::TimedOut)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::FairReadQueue;
+    use crate::unwrap_poison;
+    use std::io::ErrorKind;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Waits until `count` threads are in line.
+    fn await_waiters(queue: &FairReadQueue, count: usize) {
+        while unwrap_poison(queue.waiters.lock()).unwrap().len() < count {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn fifo() {
+        let queue = Arc::new(FairReadQueue::default());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let turn = queue.enter(None, false).unwrap();
+        assert_eq!(queue.enter(None, true).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        let mut handles = Vec::new();
+        for i in 0..4 {
+            let queue_copy = Arc::clone(&queue);
+            let order_copy = Arc::clone(&order);
+            handles.push(thread::spawn(move || {
+                let turn = queue_copy.enter(None, false).unwrap();
+                order_copy.lock().unwrap().push(i);
+                drop(turn);
+            }));
+            await_waiters(&queue, i + 2);
+        }
+
+        drop(turn);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3]);
+        assert!(unwrap_poison(queue.waiters.lock()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn timeout_leaves_line() {
+        let queue = Arc::new(FairReadQueue::default());
+        let turn = queue.enter(None, false).unwrap();
+        let queue_copy = Arc::clone(&queue);
+        let late = thread::spawn(move || queue_copy.enter(None, false).map(drop).unwrap());
+        await_waiters(&queue, 2);
+
+        let err = queue
+            .enter(Some(Instant::now() + Duration::from_millis(20)), false)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert_eq!(unwrap_poison(queue.waiters.lock()).unwrap().len(), 2);
+
+        drop(turn);
+        late.join().unwrap();
+        assert!(unwrap_poison(queue.waiters.lock()).unwrap().is_empty());
+    }
+}
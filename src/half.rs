@@ -0,0 +1,182 @@
+//! Cloneable read and write halves of a shared stream.
+use crate::{check_timeout, RustTlsDuplexStream, TlsConnection};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::{IoSlice, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Cloneable read half of a shared `RustTlsDuplexStream`, see `RustTlsDuplexStream::clone_read_half`.
+///
+/// Reads of all clones are serialized by the read mutex of the stream.
+pub struct ReadHalf<C>
+where
+    C: TlsConnection,
+{
+    /// The shared stream.
+    stream: Arc<RustTlsDuplexStream<C>>,
+    /// Read timeout of this half.
+    timeout: Option<Duration>,
+}
+
+impl<C> Debug for ReadHalf<C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadHalf")
+            .field("stream", &self.stream)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Cloneable write half of a shared `RustTlsDuplexStream`, see `RustTlsDuplexStream::clone_write_half`.
+///
+/// Writes of all clones are serialized by the write mutex of the stream.
+pub struct WriteHalf<C>
+where
+    C: TlsConnection,
+{
+    /// The shared stream.
+    stream: Arc<RustTlsDuplexStream<C>>,
+    /// Write timeout of this half.
+    timeout: Option<Duration>,
+}
+
+impl<C> Debug for WriteHalf<C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WriteHalf")
+            .field("stream", &self.stream)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Creates a read half that starts out with the stored read timeout of the stream.
+pub fn read_half<C>(stream: &Arc<RustTlsDuplexStream<C>>) -> io::Result<ReadHalf<C>>
+where
+    C: TlsConnection,
+{
+    Ok(ReadHalf {
+        stream: Arc::clone(stream),
+        timeout: stream.read_timeout()?,
+    })
+}
+
+/// Creates a write half that starts out with the stored write timeout of the stream.
+pub fn write_half<C>(stream: &Arc<RustTlsDuplexStream<C>>) -> io::Result<WriteHalf<C>>
+where
+    C: TlsConnection,
+{
+    Ok(WriteHalf {
+        stream: Arc::clone(stream),
+        timeout: stream.write_timeout()?,
+    })
+}
+
+impl<C> Clone for ReadHalf<C>
+where
+    C: TlsConnection,
+{
+    fn clone(&self) -> Self {
+        Self {
+            stream: Arc::clone(&self.stream),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<C> Clone for WriteHalf<C>
+where
+    C: TlsConnection,
+{
+    fn clone(&self) -> Self {
+        Self {
+            stream: Arc::clone(&self.stream),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<C> ReadHalf<C>
+where
+    C: TlsConnection,
+{
+    /// Returns the underlying stream.
+    #[must_use]
+    pub fn get_ref(&self) -> &RustTlsDuplexStream<C> {
+        &self.stream
+    }
+
+    /// sets the read timeout of this half, other clones and the stream keep theirs.
+    /// see `RustTlsDuplexStream::set_read_timeout`
+    /// # Errors
+    /// `InvalidInput` if the timeout is zero
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the read timeout of this half.
+    #[must_use]
+    pub const fn read_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+impl<C> Read for ReadHalf<C>
+where
+    C: TlsConnection,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read_with_timeout(buf, self.timeout)
+    }
+}
+
+impl<C> WriteHalf<C>
+where
+    C: TlsConnection,
+{
+    /// Returns the underlying stream.
+    #[must_use]
+    pub fn get_ref(&self) -> &RustTlsDuplexStream<C> {
+        &self.stream
+    }
+
+    /// sets the write timeout of this half, other clones and the stream keep theirs.
+    /// see `RustTlsDuplexStream::set_write_timeout`
+    /// # Errors
+    /// `InvalidInput` if the timeout is zero
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the write timeout of this half.
+    #[must_use]
+    pub const fn write_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+impl<C> Write for WriteHalf<C>
+where
+    C: TlsConnection,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write_with_timeout(buf, self.timeout)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let buf = bufs.iter().find(|buf| !buf.is_empty()).map_or(&[][..], |buf| &**buf);
+        self.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush_with_timeout(self.timeout)
+    }
+}
@@ -0,0 +1,111 @@
+//! Cheap cloneable owning handle.
+use crate::{ReadHalf, RustTlsDuplexStream, TlsConnection, WriteHalf};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::{IoSlice, IoSliceMut, Read, Write};
+use std::ops::Deref;
+use std::sync::Arc;
+
+///
+/// Cloneable owning handle to a `RustTlsDuplexStream`.
+///
+/// This implements `Read` + `Write` by delegating to the shared stream and is `Send + 'static`
+/// for every `'static` connection type, so it can be handed to apis that want to own their transport.
+/// Clones refer to the same stream, which is dropped together with the last handle.
+///
+pub struct DuplexHandle<C>(Arc<RustTlsDuplexStream<C>>)
+where
+    C: TlsConnection;
+
+impl<C> Debug for DuplexHandle<C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("DuplexHandle").field(&self.0).finish()
+    }
+}
+
+impl<C> DuplexHandle<C>
+where
+    C: TlsConnection,
+{
+    /// Constructor for `DuplexHandle`
+    #[must_use]
+    pub fn new(stream: RustTlsDuplexStream<C>) -> Self {
+        Self(Arc::new(stream))
+    }
+
+    /// see `RustTlsDuplexStream::clone_read_half`
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn clone_read_half(&self) -> io::Result<ReadHalf<C>> {
+        self.0.clone_read_half()
+    }
+
+    /// see `RustTlsDuplexStream::clone_write_half`
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn clone_write_half(&self) -> io::Result<WriteHalf<C>> {
+        self.0.clone_write_half()
+    }
+}
+
+impl<C> Clone for DuplexHandle<C>
+where
+    C: TlsConnection,
+{
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<C> Deref for DuplexHandle<C>
+where
+    C: TlsConnection,
+{
+    type Target = RustTlsDuplexStream<C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<C> From<RustTlsDuplexStream<C>> for DuplexHandle<C>
+where
+    C: TlsConnection,
+{
+    fn from(stream: RustTlsDuplexStream<C>) -> Self {
+        Self::new(stream)
+    }
+}
+
+impl<C> Read for DuplexHandle<C>
+where
+    C: TlsConnection,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        self.0.read_vectored(bufs)
+    }
+}
+
+impl<C> Write for DuplexHandle<C>
+where
+    C: TlsConnection,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.0.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
@@ -0,0 +1,198 @@
+//! Idle timeout of a stream, enforced by a reaper thread that all streams of the process share.
+use crate::events::EventSlot;
+use crate::queue::Queue;
+use crate::stats::StatCounters;
+use crate::sync::Mutex;
+use crate::unwrap_poison;
+use crate::write_pipe::WriteOp;
+use std::io;
+use std::io::ErrorKind;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest time the reaper sleeps before it checks the streams again.
+const REAPER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Streams the reaper watches and whether its thread runs, the thread ends once no stream is left.
+static REAPER: Mutex<Reaper> = Mutex::new(Reaper {
+    watches: Vec::new(),
+    running: false,
+});
+
+/// State of the reaper thread.
+#[derive(Debug)]
+struct Reaper {
+    /// Streams with an idle timeout, dropped streams are removed on the next check.
+    watches: Vec<Weak<IdleWatch>>,
+    /// Set while the reaper thread runs.
+    running: bool,
+}
+
+/// The last progress of the queues the reaper saw.
+#[derive(Debug, Clone, Copy)]
+struct Progress {
+    /// Total amount of bytes pushed onto the read and the write queue.
+    received: (u64, u64),
+    /// When `received` last changed, or when the idle timeout was last set.
+    at: Instant,
+}
+
+/// Idle state of one stream, shared by the stream and the reaper.
+#[derive(Debug)]
+pub struct IdleWatch {
+    /// Id of the stream, for tracing.
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    id: u64,
+    /// The stream is killed once it was idle for this long, None disables the timeout.
+    timeout: Mutex<Option<Duration>>,
+    /// Set once the reaper killed the stream, until it reconnects. Shared with the transport of the stream.
+    expired: Arc<AtomicBool>,
+    /// Set once this was handed to the reaper.
+    registered: AtomicBool,
+    /// Progress of the queues, so a large transfer that is still under way counts as activity.
+    progress: Mutex<Progress>,
+    /// Plaintext activity of the stream.
+    stats: Arc<StatCounters>,
+    /// Queue of the background reader.
+    read_q: Arc<Queue>,
+    /// Queue of the background writer.
+    write_q: Arc<Queue<WriteOp>>,
+    /// Receives the error once the timeout elapsed.
+    events: Arc<EventSlot>,
+}
+
+impl IdleWatch {
+    /// Creates the idle state of a stream without a timeout.
+    pub fn new(
+        id: u64,
+        expired: Arc<AtomicBool>,
+        stats: Arc<StatCounters>,
+        read_q: Arc<Queue>,
+        write_q: Arc<Queue<WriteOp>>,
+        events: Arc<EventSlot>,
+    ) -> Self {
+        Self {
+            id,
+            timeout: Mutex::new(None),
+            expired,
+            registered: AtomicBool::new(false),
+            progress: Mutex::new(Progress {
+                received: (0, 0),
+                at: Instant::now(),
+            }),
+            stats,
+            read_q,
+            write_q,
+            events,
+        }
+    }
+
+    /// Changes the timeout and starts the idle window over. The first timeout hands this to the reaper.
+    /// # Errors
+    /// if the reaper thread could not be spawned, in case of poisoned mutex
+    pub fn set_timeout(self: &Arc<Self>, timeout: Option<Duration>) -> io::Result<()> {
+        *unwrap_poison(self.progress.lock())? = self.current_progress();
+        *unwrap_poison(self.timeout.lock())? = timeout;
+        if timeout.is_none() || self.registered.load(SeqCst) {
+            return Ok(());
+        }
+
+        let mut reaper = unwrap_poison(REAPER.lock())?;
+        if !reaper.running {
+            thread::Builder::new().name("tls-idle-reaper".to_string()).spawn(reap)?;
+            reaper.running = true;
+        }
+        reaper.watches.push(Arc::downgrade(self));
+        drop(reaper);
+        self.registered.store(true, SeqCst);
+        Ok(())
+    }
+
+    /// Starts the idle window over after the stream reconnected.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn reset(&self) -> io::Result<()> {
+        *unwrap_poison(self.progress.lock())? = self.current_progress();
+        self.expired.store(false, SeqCst);
+        Ok(())
+    }
+
+    /// Replaces the error of a transport the reaper killed with `TimedOut`.
+    pub fn map_err(expired: &AtomicBool, err: io::Error) -> io::Error {
+        if !expired.load(SeqCst) || !matches!(err.kind(), ErrorKind::BrokenPipe | ErrorKind::ConnectionReset) {
+            return err;
+        }
+
+        Self::error()
+    }
+
+    /// The error reads and writes fail with once the reaper killed the stream.
+    fn error() -> io::Error {
+        io::Error::new(ErrorKind::TimedOut, "nothing was read or written within the idle timeout")
+    }
+
+    /// The progress of the queues as of now.
+    fn current_progress(&self) -> Progress {
+        Progress {
+            received: (self.read_q.received(), self.write_q.received()),
+            at: Instant::now(),
+        }
+    }
+
+    /// Kills the stream if it was idle for longer than the timeout.
+    fn check(&self) {
+        let (Ok(Some(timeout)), Ok(mut progress)) =
+            (unwrap_poison(self.timeout.lock()).map(|guard| *guard), unwrap_poison(self.progress.lock()))
+        else {
+            return;
+        };
+
+        let current = self.current_progress();
+        if current.received != progress.received {
+            *progress = current;
+        }
+        let last_activity = progress.at.max(self.stats.last_read()).max(self.stats.last_write());
+        drop(progress);
+
+        if last_activity.elapsed() < timeout || self.read_q.is_dead() {
+            return;
+        }
+
+        error!(id = self.id, idle = ?last_activity.elapsed(), "idle timeout elapsed");
+        self.expired.store(true, SeqCst);
+        self.events.error(&Self::error());
+        self.read_q.kill("idle timeout elapsed");
+        self.write_q.kill("idle timeout elapsed");
+    }
+}
+
+/// Reaper thread loop, checks every stream that has an idle timeout until none is left.
+fn reap() {
+    enter_span!("tls-idle-reaper");
+    loop {
+        thread::sleep(REAPER_INTERVAL);
+        let Ok(mut reaper) = unwrap_poison(REAPER.lock()) else {
+            return;
+        };
+
+        let mut watches = Vec::with_capacity(reaper.watches.len());
+        reaper.watches.retain(|watch| {
+            watch.upgrade().is_some_and(|watch| {
+                watches.push(watch);
+                true
+            })
+        });
+        if watches.is_empty() {
+            reaper.running = false;
+            return;
+        }
+        drop(reaper);
+
+        for watch in watches {
+            watch.check();
+        }
+    }
+}
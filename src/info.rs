@@ -0,0 +1,102 @@
+//! Snapshot of the state of a stream.
+use rustls::{ProtocolVersion, SupportedCipherSuite};
+use std::fmt::{Display, Formatter};
+
+///
+/// Snapshot of the negotiated parameters and traffic counters of a stream, see `RustTlsDuplexStream::connection_info`.
+///
+/// The byte counters count ciphertext as it was received from and queued for the transport.
+/// The queue depths count records, the unit of the read and write watermarks.
+///
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// Unique id of the stream, see `RustTlsDuplexStream::connection_id`.
+    pub id: u64,
+    /// The negotiated cipher suite, None while the handshake is in progress.
+    pub cipher_suite: Option<SupportedCipherSuite>,
+    /// The negotiated protocol version, None while the handshake is in progress.
+    pub protocol_version: Option<ProtocolVersion>,
+    /// The protocol selected with ALPN, None if none was negotiated (yet).
+    pub alpn_protocol: Option<Vec<u8>>,
+    /// The server name the client indicated with SNI, only known to servers.
+    pub server_name: Option<String>,
+    /// Whether the stream is handshaking, open or closed.
+    pub state: SessionState,
+    /// Total amount of bytes received from the transport.
+    pub bytes_read: u64,
+    /// Total amount of bytes queued for the transport.
+    pub bytes_written: u64,
+    /// Amount of records that were received and not read yet.
+    pub read_queue_depth: usize,
+    /// Amount of records that were queued and not written to the transport yet.
+    pub write_queue_depth: usize,
+}
+
+impl Display for ConnectionInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "connection {} ", self.id)?;
+        write_session(
+            f,
+            self.protocol_version,
+            self.cipher_suite,
+            self.alpn_protocol.as_deref(),
+            self.server_name.as_deref(),
+            self.state,
+        )?;
+        write!(
+            f,
+            " read={}B written={}B queued={}/{}",
+            self.bytes_read, self.bytes_written, self.read_queue_depth, self.write_queue_depth
+        )
+    }
+}
+
+/// Lifecycle state of a stream, see `ConnectionInfo::state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// The handshake did not finish yet.
+    Handshaking,
+    /// The handshake finished and the transport is usable.
+    Open,
+    /// The transport closed or failed, or the stream was shut down.
+    Closed,
+}
+
+impl Display for SessionState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Handshaking => "handshaking",
+            Self::Open => "open",
+            Self::Closed => "closed",
+        })
+    }
+}
+
+/// Writes the one line summary of a session like `TLSv1_3 TLS13_AES_128_GCM_SHA256 alpn=h2 state=open`.
+/// Parameters that are not known are left out.
+pub fn write_session(
+    f: &mut Formatter<'_>,
+    protocol_version: Option<ProtocolVersion>,
+    cipher_suite: Option<SupportedCipherSuite>,
+    alpn_protocol: Option<&[u8]>,
+    server_name: Option<&str>,
+    state: SessionState,
+) -> std::fmt::Result {
+    if let Some(version) = protocol_version {
+        write!(f, "{version:?} ")?;
+    }
+
+    if let Some(suite) = cipher_suite {
+        write!(f, "{:?} ", suite.suite())?;
+    }
+
+    if let Some(alpn) = alpn_protocol {
+        write!(f, "alpn={} ", String::from_utf8_lossy(alpn))?;
+    }
+
+    if let Some(name) = server_name {
+        write!(f, "sni={name} ")?;
+    }
+
+    write!(f, "state={state}")
+}
@@ -17,55 +17,327 @@
     clippy::used_underscore_binding
 )]
 
+#[macro_use]
+mod trace;
+#[cfg(all(unix, feature = "async-io"))]
+mod async_fd;
+#[cfg(feature = "futures-io")]
+mod async_io;
+mod buffered;
+mod cancel;
+mod chunks;
+#[cfg(feature = "compress")]
+mod compression;
+mod connection;
+mod copy;
+mod error;
+mod events;
+mod fair;
+mod half;
+mod handle;
+mod idle;
+mod info;
+#[cfg(feature = "tcp")]
+mod meta;
+#[cfg(all(windows, feature = "windows-named-pipe"))]
+mod named_pipe;
+mod observer;
+#[cfg(all(unix, feature = "mio"))]
+mod mio_source;
+#[cfg(feature = "polling")]
+mod poller;
 mod queue;
 mod read_pipe;
+#[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+mod readiness;
+mod single;
+mod split;
+mod stats;
+mod sync;
+mod tap;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+mod waker;
 mod write_pipe;
-use crate::queue::Queue;
+#[cfg(all(unix, feature = "async-io"))]
+pub use crate::async_fd::AsyncIoDuplexStream;
+#[cfg(feature = "futures-io")]
+pub use crate::async_io::AsyncDuplexStream;
+pub use crate::buffered::BufferedDuplexStream;
+pub use crate::cancel::ReadCancelToken;
+pub use crate::chunks::Chunks;
+#[cfg(feature = "compress")]
+pub use crate::compression::CompressedDuplexStream;
+pub use crate::connection::TlsConnection;
+pub use crate::copy::{copy_bidirectional, PlainEndpoint, ProxyEndpoint};
+pub use crate::error::{DuplexStreamError, Result};
+pub use crate::events::{StreamEvent, StreamEvents};
+pub use crate::half::{ReadHalf, WriteHalf};
+pub use crate::handle::DuplexHandle;
+pub use crate::info::{ConnectionInfo, SessionState};
+#[cfg(feature = "tcp")]
+pub use crate::meta::ConnectionMeta;
+#[cfg(all(windows, feature = "windows-named-pipe"))]
+pub use crate::named_pipe::{NamedPipeReader, NamedPipeTransport, NamedPipeWriter};
+pub use crate::observer::StreamObserver;
+#[cfg(feature = "polling")]
+pub use crate::poller::PollerSink;
+pub use crate::queue::OverflowPolicy;
+pub use crate::read_pipe::DataCallback;
+pub use crate::single::SingleThreadedDuplexStream;
+pub use crate::split::{OwnedReadHalf, OwnedWriteHalf, ReuniteError};
+pub use crate::stats::StreamStats;
+pub use crate::tap::Tap;
+use crate::connection::TlsStream;
+use crate::events::EventSlot;
+use crate::fair::FairReadQueue;
+use crate::idle::IdleWatch;
+use crate::observer::ObserverSlot;
+use crate::queue::{Queue, QueueConfig};
+use crate::read_pipe::{DataCallbackSlot, ReadPipeConfig};
 use crate::read_pipe::ReadPipe;
-use crate::write_pipe::WritePipe;
-use rustls::{ConnectionCommon, StreamOwned};
-use std::fmt::{Arguments, Debug};
-use std::io::{ErrorKind, Read, Write};
-use std::ops::{Deref, DerefMut};
-use std::sync::atomic::AtomicBool;
+use crate::stats::StatCounters;
+use crate::sync::{LockResult, Mutex, MutexGuard};
+use crate::tap::TapSlot;
+use crate::write_pipe::{WriteOp, WritePipe, WritePipeConfig};
+#[cfg(feature = "polling")]
+use polling::{Event, PollMode};
+use rustls::ProtocolVersion;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{Arguments, Debug, Display, Formatter};
+use std::io::{ErrorKind, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::ops::Deref;
+#[cfg(all(unix, feature = "unix"))]
+use std::os::fd::BorrowedFd;
+#[cfg(all(windows, feature = "windows"))]
+use std::os::windows::io::RawHandle;
+use std::sync::atomic::{AtomicBool, AtomicU64};
 use std::sync::atomic::Ordering::SeqCst;
-use std::sync::{Arc, LockResult, Mutex};
-use std::time::Duration;
+use std::sync::{mpsc, Arc};
+use std::task::Waker;
+use std::time::{Duration, Instant};
 use std::{io, thread};
 
-#[derive(Debug)]
-pub struct RustTlsDuplexStream<C, S>
+/// Max amount of plaintext in a single TLS record.
+const MAX_RECORD_SIZE: usize = 0x4000;
+
+/// Size of the buffer the background reader reads ciphertext from the underlying read into.
+pub(crate) const READ_BUFFER_SIZE: usize = 0x1_00_00;
+
+/// Max size of a chunk returned by `read_chunk`, one record worth of plaintext.
+const CHUNK_SIZE: usize = MAX_RECORD_SIZE;
+
+/// Source of the ids returned by `RustTlsDuplexStream::connection_id`.
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Settings of a `RustTlsDuplexStream` that can only be chosen when it is created.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StreamConfig {
+    /// Max amount of bytes buffered in the read and write queues combined. None means unbounded.
+    ///
+    /// Once exceeded the background reader stops reading from the connection and
+    /// writes of plain text block (or time out) until enough data was read by you or written to the connection.
+    /// rust-tls control messages are always queued and may exceed the limit slightly.
+    /// A limit that is smaller than what the peer sends before it reads again can deadlock both sides.
+    pub total_memory_limit: Option<usize>,
+    /// How long the background writer waits for more records before writing to the connection. None means it never waits.
+    ///
+    /// Many small writes are then handed to the connection in few large writes, at the cost of up to this much latency.
+    /// A flush ends the wait early.
+    pub write_coalesce_window: Option<Duration>,
+    /// Makes the background writer flush the connection after every record it writes.
+    ///
+    /// rust-tls already queues a flush behind the records of each of your writes, this also flushes in between.
+    /// Meant for connections that buffer themselves, i.e. a `BufWriter`, so the peer receives the records
+    /// of a large write while the rest of it is still being written.
+    pub write_auto_flush: bool,
+    /// Makes the background writer flush the connection after this many writes, 0 and 1 flush after every write.
+    ///
+    /// Like `write_auto_flush` but trades latency for fewer flushes. With a coalesce window a coalesced write counts once.
+    pub write_flush_every_n_messages: Option<usize>,
+    /// Kill the connection once nothing was received from it for this long. None means it is never killed.
+    ///
+    /// Catches connections that went stale silently, i.e. behind a NAT that forgot them.
+    /// If set the spawner is called a third time for a watchdog thread, see `RustTlsDuplexStream::set_idle_read_timeout`.
+    pub idle_read_timeout: Option<Duration>,
+    /// What the background reader does with received data while the read queue is full. Defaults to waiting for room.
+    ///
+    /// With the drop policies the background reader never stops reading from the connection, for transports
+    /// whose sender does not wait (i.e. datagram sockets) and loses data anyway once the os buffer overflows.
+    /// Every dropped packet corrupts the tls session, the next read then fails with `InvalidData`.
+    pub read_on_full: OverflowPolicy,
+}
+
+/// Settings of the threads that `RustTlsDuplexStream::new_unpooled_with_config` spawns.
+///
+/// Streams created with a custom spawner use whatever threads the spawner provides, configure those there.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnConfig {
+    /// Stack size of each background thread in bytes. None means the default of `thread::Builder`.
+    ///
+    /// The background threads only shuffle buffers around, they get by with far less than the default.
+    pub stack_size: Option<usize>,
+}
+
+/// Error payload of the `TimedOut` error returned by `read_exact_deadline` and `write_all_deadline`.
+///
+/// Retrieve it with `err.get_ref().and_then(|err| err.downcast_ref::<DeadlineExceeded>())`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded {
+    /// Amount of bytes that were read or written before the deadline passed.
+    pub transferred: usize,
+}
+
+impl Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "deadline passed after {} bytes were transferred", self.transferred)
+    }
+}
+
+impl Error for DeadlineExceeded {}
+
+/// Duplex stream wrapper around a rust-tls client connection.
+pub type ClientDuplexStream = RustTlsDuplexStream<rustls::ClientConnection>;
+
+/// Duplex stream wrapper around a rust-tls server connection.
+pub type ServerDuplexStream = RustTlsDuplexStream<rustls::ServerConnection>;
+
+/// Duplex stream wrapper that can hold either a client or a server connection.
+pub type DuplexStream = RustTlsDuplexStream<rustls::Connection>;
+
+pub struct RustTlsDuplexStream<C>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>> + Send,
+    C: TlsConnection,
 {
+    /// Unique id of this stream for correlating log messages.
+    id: u64,
+    /// Optional user supplied label for debugging.
+    label: Mutex<Option<String>>,
     /// Flag for non blocking read.
     non_blocking_read: AtomicBool,
+    /// Flag for non blocking write.
+    non_blocking_write: AtomicBool,
+    /// Set while blocking reads are canceled, shared with every `ReadCancelToken`.
+    read_canceled: Arc<AtomicBool>,
+    /// Set while `cancel_pending_writes` makes blocked writes give up, shared with the write pipe.
+    write_canceled: Arc<AtomicBool>,
     /// Read timeout
     read_timeout: Mutex<Option<Duration>>,
+    /// Deadline of the read that currently waits for data, only set while it waits.
+    read_deadline: Mutex<Option<Instant>>,
     /// Write timeout
     write_timeout: Mutex<Option<Duration>>,
     /// Inner rust-tls pseudo connection
-    connection: Mutex<StreamOwned<C, CombinedPipe>>,
+    connection: Mutex<TlsStream<C, CombinedPipe>>,
     /// Read queue connected to the thread that reads data from the actual connection
     read_q: Arc<Queue>,
     /// Write queue connected to the thread that writes data to the actual connection
-    write_q: Arc<Queue>,
+    write_q: Arc<Queue<WriteOp>>,
     /// Guard mutex that prevents concurrent writes.
     write_mutex: Mutex<()>,
-    /// Guard mutex that prevents concurrent reads.
-    read_mutex: Mutex<()>,
+    /// Guard mutex that prevents concurrent reads. Holds plaintext that was peeked but not read yet.
+    read_mutex: Mutex<VecDeque<u8>>,
+    /// Scratch buffer `read_chunk` decrypts into, allocated once. Only locked while holding the read mutex.
+    chunk_buffer: Mutex<Vec<u8>>,
+    /// Line of threads that wait for their turn in `read_raw_frame`.
+    fair_readers: FairReadQueue,
+    /// Observes plaintext once rust-tls decrypted it, only called while holding the read mutex.
+    read_tap: TapSlot,
+    /// Invoked by the background reader once received data waits, shared with the read pipe.
+    data_callback: Arc<DataCallbackSlot>,
+    /// Observes plaintext once rust-tls accepted it, only called while holding the write mutex.
+    write_tap: TapSlot,
+    /// Receives the lifecycle events, shared with both pipes.
+    events: Arc<EventSlot>,
+    /// Plaintext counters, updated without holding the connection mutex.
+    stats: Arc<StatCounters>,
+    /// Idle timeout, shared with the reaper.
+    idle: Arc<IdleWatch>,
+    /// Receives the metrics, shared with the write pipe.
+    observer: Arc<ObserverSlot>,
+    /// Set while the current poller attachment may post, replaced by every `attach_poller`.
+    #[cfg(feature = "polling")]
+    poller_attachment: Mutex<Arc<AtomicBool>>,
+    /// Settings of the threads if the stream spawns them itself, None if it was created with a custom spawner.
+    spawn: Option<SpawnConfig>,
+    /// Addresses of the transport, see `new_with_meta`.
+    #[cfg(feature = "tcp")]
+    meta: Option<Box<dyn ConnectionMeta>>,
+}
+
+/// Reports the state of the stream without blocking, fields that are locked elsewhere show as None.
+impl<C> Debug for RustTlsDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let label = sync::try_lock(&self.label).ok().flatten();
+        let read_timeout = sync::try_lock(&self.read_timeout).ok().flatten().and_then(|guard| *guard);
+        let write_timeout = sync::try_lock(&self.write_timeout).ok().flatten().and_then(|guard| *guard);
+        let handshake_complete = sync::try_lock(&self.connection)
+            .ok()
+            .flatten()
+            .map(|guard| !guard.conn.common_state().is_handshaking());
+
+        f.debug_struct("RustTlsDuplexStream")
+            .field("id", &self.id)
+            .field("label", &label.as_deref().and_then(Option::as_deref))
+            .field("read_dead", &self.read_q.is_dead())
+            .field("write_dead", &self.write_q.is_dead())
+            .field("read_queue_bytes", &self.read_q.byte_len())
+            .field("write_queue_bytes", &self.write_q.byte_len())
+            .field("non_blocking_read", &self.non_blocking_read.load(SeqCst))
+            .field("non_blocking_write", &self.non_blocking_write.load(SeqCst))
+            .field("read_timeout", &read_timeout)
+            .field("write_timeout", &write_timeout)
+            .field("handshake_complete", &handshake_complete)
+            .finish_non_exhaustive()
+    }
 }
 
-impl<C, S> RustTlsDuplexStream<C, S>
+/// One line summary of the session like `TLSv1_3 TLS13_AES_128_GCM_SHA256 alpn=h2 state=open`.
+/// Never blocks, while another thread holds the connection only the state is shown, as handshaking unless the stream is closed.
+impl<C> Display for RustTlsDuplexStream<C>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>> + Send,
-    S: rustls::SideData,
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Some(guard) = sync::try_lock(&self.connection).ok().flatten() else {
+            let state = if self.read_q.is_dead() || self.write_q.is_dead() {
+                SessionState::Closed
+            } else {
+                SessionState::Handshaking
+            };
+            return write!(f, "state={state}");
+        };
+
+        let state = guard.conn.common_state();
+        let written = info::write_session(
+            f,
+            state.protocol_version(),
+            state.negotiated_cipher_suite(),
+            state.alpn_protocol(),
+            guard.conn.server_name(),
+            self.session_state(&guard.conn),
+        );
+        drop(guard);
+        written
+    }
+}
+
+impl<C> RustTlsDuplexStream<C>
+where
+    C: TlsConnection,
 {
     ///
     /// Creates a new 'unpooled' Tls stream wrapper.
     /// This is a good choice for an application such as a client
     /// that does not create connections and doesn't have a thread pool.
     ///
+    /// Any transport works, for example a `TcpStream` and its `try_clone`
+    /// or on windows a named pipe from `NamedPipeTransport::connect` with the `windows-named-pipe` feature.
+    ///
     /// This fn will spawn 2 new threads using `thread::Builder::new().spawn(...)`.
     /// The threads will terminate when the returned stream is dropped and the read/write errors out.
     ///
@@ -74,6 +346,9 @@ where
     /// Either set a reasonable connection read timeout so that your Read will eventually return
     /// or call your connections shutdown fn like `TcpStream::shutdown` if such a method exists
     /// to ensure that all threads are stopped and no resources are leaked.
+    /// Once the Read reached EOF the reading thread waits at most 30 seconds for you to make room
+    /// in the read buffer, if you do not it ends anyway and reads report `BrokenPipe` instead of EOF
+    /// once the buffered data was read.
     /// 
     /// # Errors
     /// if `thread::Builder::new().spawn` fails to spawn 2 threads.
@@ -83,108 +358,1718 @@ where
         R: Read + Send + 'static,
         W: Write + Send + 'static,
     {
-        Self::new(con, read, write, |task| {
-            thread::Builder::new().spawn(task).map(|_| {})
+        Self::new_unpooled_with_config(con, read, write, &StreamConfig::default(), SpawnConfig::default())
+    }
+
+    ///
+    /// Same as `new_unpooled` but with the given configuration of the stream and its threads.
+    ///
+    /// # Errors
+    /// if `thread::Builder::new().spawn` fails to spawn the threads.
+    ///
+    pub fn new_unpooled_with_config<R, W>(
+        con: C,
+        read: R,
+        write: W,
+        config: &StreamConfig,
+        spawn: SpawnConfig,
+    ) -> io::Result<Self>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let mut stream = Self::with_config(con, read, write, spawn_thread(spawn), config)?;
+        stream.spawn = Some(spawn);
+        Ok(stream)
+    }
+
+    ///
+    /// Same as `new_unpooled` but `peer_addr` and `local_addr` are answered by `meta`,
+    /// i.e. another clone of the `TcpStream` that `read` and `write` are clones of.
+    /// `reconnect` keeps the meta, it keeps describing the original transport.
+    ///
+    /// # Errors
+    /// if `thread::Builder::new().spawn` fails to spawn 2 threads.
+    ///
+    #[cfg(feature = "tcp")]
+    pub fn new_with_meta<R, W, M>(con: C, read: R, write: W, meta: M) -> io::Result<Self>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+        M: ConnectionMeta + 'static,
+    {
+        let mut stream = Self::new_unpooled(con, read, write)?;
+        stream.meta = Some(Box::new(meta));
+        Ok(stream)
+    }
+
+    /// Returns the address of the peer.
+    /// # Errors
+    /// `Unsupported` if the stream was not created with `new_with_meta`, otherwise the error of the meta
+    #[cfg(feature = "tcp")]
+    pub fn peer_addr(&self) -> io::Result<std::net::SocketAddr> {
+        self.meta()?.peer_addr()
+    }
+
+    /// Returns the local address.
+    /// # Errors
+    /// `Unsupported` if the stream was not created with `new_with_meta`, otherwise the error of the meta
+    #[cfg(feature = "tcp")]
+    pub fn local_addr(&self) -> io::Result<std::net::SocketAddr> {
+        self.meta()?.local_addr()
+    }
+
+    /// Returns the meta or `Unsupported` if the stream has none.
+    #[cfg(feature = "tcp")]
+    fn meta(&self) -> io::Result<&dyn ConnectionMeta> {
+        self.meta
+            .as_deref()
+            .ok_or_else(|| io::Error::new(ErrorKind::Unsupported, "the stream was created without connection meta"))
+    }
+
+    ///
+    /// Creates a new Tls stream wrapper.
+    ///
+    /// This fn will spawn 2 new threads using  the provided thread spawner function.
+    /// The thread spawner function is called exactly twice. If the first call yields an error then
+    /// it's not called again. The functions ("threads") will end when the returned stream wrapper is dropped.
+    ///
+    /// # Resource Leaks
+    /// Be aware that a Read which "blocks" forever will cause the thread that's reading to stay alive forever.
+    /// Either set a reasonable connection read timeout so that your Read will eventually return
+    /// or call your connections shutdown fn like `TcpStream::shutdown` if such a method exists
+    /// to ensure that all threads are stopped and no resources are leaked.
+    /// Once the Read reached EOF the reading thread waits at most 30 seconds for you to make room
+    /// in the read buffer, if you do not it ends anyway and reads report `BrokenPipe` instead of EOF
+    /// once the buffered data was read.
+    /// 
+    /// # Errors
+    /// propagated from the spawner fn.
+    ///
+    pub fn new<R, W, T>(con: C, read: R, write: W, spawner: T) -> io::Result<Self>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+        T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>,
+    {
+        Self::with_config(con, read, write, spawner, &StreamConfig::default())
+    }
+
+    ///
+    /// Same as `new` but with the given configuration.
+    /// The spawner is called a third time if `StreamConfig::idle_read_timeout` is set.
+    /// The spawner is handed the reader task first, then the writer task, then the watchdog task.
+    /// It alone decides which threads run them, so settings like the stack size belong into the spawner.
+    ///
+    /// # Errors
+    /// propagated from the spawner fn.
+    ///
+    pub fn with_config<R, W, T>(
+        con: C,
+        read: R,
+        write: W,
+        spawner: T,
+        config: &StreamConfig,
+    ) -> io::Result<Self>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+        T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>,
+    {
+        let id = NEXT_CONNECTION_ID.fetch_add(1, SeqCst);
+        let pipe = CombinedPipe::new(id, read, write, spawner, config)?;
+        let read_q = pipe.0.dup_queue();
+        let write_q = pipe.1.dup_queue();
+        let write_canceled = pipe.1.dup_cancel();
+        let data_callback = pipe.0.dup_data_callback();
+        let events = pipe.0.dup_events();
+        let stats = Arc::new(StatCounters::default());
+        let idle = Arc::new(IdleWatch::new(
+            id,
+            Arc::clone(&pipe.2),
+            Arc::clone(&stats),
+            Arc::clone(&read_q),
+            Arc::clone(&write_q),
+            Arc::clone(&events),
+        ));
+        let observer = pipe.1.dup_observer();
+
+        Ok(Self {
+            id,
+            label: Mutex::new(None),
+            non_blocking_read: AtomicBool::new(false),
+            non_blocking_write: AtomicBool::new(false),
+            read_canceled: Arc::default(),
+            write_canceled,
+            read_q,
+            write_q,
+            write_mutex: Mutex::new(()),
+            read_mutex: Mutex::new(VecDeque::new()),
+            chunk_buffer: Mutex::new(Vec::new()),
+            fair_readers: FairReadQueue::default(),
+            read_tap: TapSlot::default(),
+            data_callback,
+            write_tap: TapSlot::default(),
+            events,
+            stats,
+            idle,
+            observer,
+            #[cfg(feature = "polling")]
+            poller_attachment: Mutex::default(),
+            connection: Mutex::new(TlsStream::new(con, pipe)),
+            read_timeout: Mutex::new(None),
+            read_deadline: Mutex::new(None),
+            write_timeout: Mutex::new(None),
+            spawn: None,
+            #[cfg(feature = "tcp")]
+            meta: None,
+        })
+    }
+
+    ///
+    /// Replaces the connection and the underlying read and write, i.e. after the old transport dropped.
+    /// The new connection has to be fresh, its handshake runs on the next read or write like with a new stream.
+    ///
+    /// Everything else about this stream is kept: its id, label, timeouts, watermarks, taps, the data callback,
+    /// readiness fds and the statistics of the queues. The queues are killed first, so pending reads and writes fail,
+    /// and revived once the background threads of the old transport ended. Received data that was not read yet and
+    /// written data that was not handed to the old transport yet is discarded.
+    ///
+    /// The background threads are spawned again the same way `new_unpooled_with_config` spawned them.
+    /// Streams created with a custom spawner have to use `reconnect_with` instead.
+    ///
+    /// # Blocking
+    /// This waits until the background threads of the old transport ended. A background reader only ends once
+    /// its read returns, call i.e. `TcpStream::shutdown` on the old transport first if that read may block forever.
+    ///
+    /// # Errors
+    /// `Unsupported` if this stream was created with a custom spawner.
+    /// if `thread::Builder::new().spawn` fails, the stream stays dead then.
+    /// In case of poisoned mutex
+    ///
+    pub fn reconnect<R, W>(&self, new_con: C, read: R, write: W) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let Some(spawn) = self.spawn else {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "streams created with a custom spawner must be reconnected with reconnect_with",
+            ));
+        };
+
+        self.reconnect_with(new_con, read, write, spawn_thread(spawn))
+    }
+
+    ///
+    /// Same as `reconnect` but the background threads are spawned with the given spawner,
+    /// it is called in the same order as by `with_config`.
+    ///
+    /// # Errors
+    /// propagated from the spawner fn, the stream stays dead then.
+    /// In case of poisoned mutex
+    ///
+    pub fn reconnect_with<R, W, T>(&self, new_con: C, read: R, write: W, mut spawner: T) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+        T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>,
+    {
+        //Blocked reads and writes give up and release their locks.
+        self.read_q.kill("reconnecting");
+        self.write_q.kill("reconnecting");
+
+        let outer_write_guard = unwrap_poison(self.write_mutex.lock())?;
+        let mut stash = unwrap_poison(self.read_mutex.lock())?;
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        stash.clear();
+        guard.sock.0.replace_transport(read, &mut spawner)?;
+        guard.sock.1.replace_transport(write, &mut spawner)?;
+        guard.conn = new_con;
+        self.events.reset();
+        drop(guard);
+        drop(stash);
+        drop(outer_write_guard);
+        self.idle.reset()
+    }
+
+    ///
+    /// Replaces the transport the background reader reads from, the tls session and the write side are kept.
+    /// Use this once the peer continues the session on a new connection, for example after resuming it there.
+    ///
+    /// Ciphertext that was already received from the old transport is not lost, reads return it before
+    /// anything received from the new one. Reads that wait meanwhile are not interrupted.
+    /// The background reader of the old transport stops queueing data once this was called and ends once its read returns,
+    /// close the old transport after this returned, otherwise its EOF may be read by the stream.
+    ///
+    /// # Errors
+    /// `Unsupported` if the stream was created with a custom spawner, use `replace_read_transport_with` then.
+    /// `UnexpectedEof` if the stream already read EOF of the old transport, use `reconnect` then.
+    /// propagated from spawning the thread, the stream stays dead then.
+    /// In case of poisoned mutex
+    ///
+    pub fn replace_read_transport<R2: Read + Send + 'static>(&self, new_read: R2) -> io::Result<()> {
+        let Some(spawn) = self.spawn else {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "streams created with a custom spawner must use replace_read_transport_with",
+            ));
+        };
+
+        self.replace_read_transport_with(new_read, spawn_thread(spawn))
+    }
+
+    ///
+    /// Same as `replace_read_transport` but the background reader is spawned with the given spawner.
+    /// If the stream has an idle read timeout the spawner is called a second time for its watchdog.
+    ///
+    /// # Errors
+    /// `UnexpectedEof` if the stream already read EOF of the old transport, use `reconnect` then.
+    /// propagated from the spawner fn, the stream stays dead then.
+    /// In case of poisoned mutex
+    ///
+    pub fn replace_read_transport_with<R2, T>(&self, new_read: R2, mut spawner: T) -> io::Result<()>
+    where
+        R2: Read + Send + 'static,
+        T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>,
+    {
+        //Waiting reads do not hold the connection lock, so they keep waiting and pick up the new data.
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        guard.sock.0.swap_transport(new_read, &mut spawner)
+    }
+
+    /// Returns the amount of bytes currently buffered in the read and write queues combined.
+    /// See `StreamConfig::total_memory_limit`.
+    pub fn current_memory_usage(&self) -> usize {
+        self.read_q.memory_usage()
+    }
+
+    /// Returns the amount of ciphertext the background reader received that rust-tls did not take yet.
+    /// Plaintext rust-tls already decrypted is not counted.
+    /// The amount is kept up to date by every push and pop of the read queue, this takes no lock.
+    pub fn pending_read_bytes(&self) -> usize {
+        self.read_q.byte_len()
+    }
+
+    /// Returns the amount of ciphertext the background reader discarded because the read queue was full.
+    /// Only the drop policies of `StreamConfig::read_on_full` discard anything, the session is corrupt once this is not 0.
+    pub fn dropped_read_bytes(&self) -> u64 {
+        self.read_q.dropped_bytes()
+    }
+
+    /// Returns the amount of ciphertext queued for the background writer that it did not take yet.
+    /// Compare it to a threshold to pause producing data before writes start to block.
+    /// The amount is kept up to date by every push and pop of the write queue, this takes no lock.
+    pub fn pending_write_bytes(&self) -> usize {
+        self.write_q.byte_len()
+    }
+
+    /// Sets the amount of queued tls records at which writing blocks.
+    /// Writes of plain text block once `low` records are queued, rust-tls control messages are queued until `high` is reached.
+    /// The defaults are 4096 and 8096.
+    /// # Errors
+    /// `InvalidInput` if `low` is not below `high`, in case of poisoned mutex
+    pub fn set_write_watermarks(&self, low: usize, high: usize) -> io::Result<()> {
+        self.write_q.set_watermarks(low, high)
+    }
+
+    /// Sets the amount of buffered reads at which the background reader stops reading from the connection until you read.
+    /// Only `high` has an effect, `low` must be below it. The defaults are 4096 and 8096.
+    /// # Errors
+    /// `InvalidInput` if `low` is not below `high`, in case of poisoned mutex
+    pub fn set_read_watermarks(&self, low: usize, high: usize) -> io::Result<()> {
+        self.read_q.set_watermarks(low, high)
+    }
+
+    /// Returns a snapshot of the negotiated parameters, traffic counters and queue depths of this stream.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn connection_info(&self) -> io::Result<ConnectionInfo> {
+        let guard = unwrap_poison(self.connection.lock())?;
+        let state = guard.conn.common_state();
+        let cipher_suite = state.negotiated_cipher_suite();
+        let protocol_version = state.protocol_version();
+        let alpn_protocol = state.alpn_protocol().map(<[u8]>::to_vec);
+        let server_name = guard.conn.server_name().map(str::to_owned);
+        let session_state = self.session_state(&guard.conn);
+        drop(guard);
+
+        Ok(ConnectionInfo {
+            id: self.id,
+            cipher_suite,
+            protocol_version,
+            alpn_protocol,
+            server_name,
+            state: session_state,
+            bytes_read: self.read_q.received(),
+            bytes_written: self.write_q.received(),
+            read_queue_depth: self.read_q.len()?,
+            write_queue_depth: self.write_q.len()?,
+        })
+    }
+
+    /// The lifecycle state of the stream, a dead queue counts as closed even if the handshake never finished.
+    fn session_state(&self, conn: &C) -> SessionState {
+        if self.read_q.is_dead() || self.write_q.is_dead() {
+            SessionState::Closed
+        } else if conn.common_state().is_handshaking() {
+            SessionState::Handshaking
+        } else {
+            SessionState::Open
+        }
+    }
+
+    /// Returns the cumulative plaintext counters of this stream and when it was created.
+    /// This takes no lock, so scraping it never delays reads or writes.
+    pub fn stats(&self) -> StreamStats {
+        self.stats.snapshot()
+    }
+
+    /// Returns when plaintext was last read or the handshake finished, or when the stream was created if neither happened yet.
+    /// Like `stats` this takes no lock. The timestamps have a resolution of 1 millisecond.
+    pub fn last_read_activity(&self) -> Instant {
+        self.stats.last_read()
+    }
+
+    /// Returns when rust-tls last accepted plaintext or the handshake finished, or when the stream was created if neither happened yet.
+    /// Like `stats` this takes no lock. The timestamps have a resolution of 1 millisecond.
+    pub fn last_write_activity(&self) -> Instant {
+        self.stats.last_write()
+    }
+
+    /// Returns how long neither `last_read_activity` nor `last_write_activity` changed, for reaping idle streams.
+    pub fn idle_duration(&self) -> Duration {
+        self.last_read_activity().max(self.last_write_activity()).elapsed()
+    }
+
+    /// Returns the unique id of this stream. Ids are assigned in creation order and never reused.
+    pub const fn connection_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Attaches a label to this stream, it is shown in the `Debug` output.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn set_label(&self, label: impl Into<String>) -> io::Result<()> {
+        *unwrap_poison(self.label.lock())? = Some(label.into());
+        Ok(())
+    }
+
+    /// Returns the label of this stream if any
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn label(&self) -> io::Result<Option<String>> {
+        Ok(unwrap_poison(self.label.lock())?.clone())
+    }
+
+    /// see `Write::write`
+    /// In non-blocking write mode this behaves like `try_write`.
+    /// # Errors
+    /// propagated from `Write::write` once subsequent writes/flushes turn into `BrokenPipe`
+    pub fn write(&self, buffer: &[u8]) -> io::Result<usize> {
+        if self.non_blocking_write.load(SeqCst) {
+            return self.try_write(buffer);
+        }
+
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        self.write_locked(buffer, Timeout::Stored)
+    }
+
+    /// Like `write` but gives up with `TimedOut` once the deadline has passed.
+    /// The stored write timeout is ignored.
+    /// # Errors
+    /// `TimedOut` if the deadline has already passed, otherwise same as `write`
+    pub fn write_before(&self, buffer: &[u8], deadline: Instant) -> io::Result<usize> {
+        remaining(deadline)?;
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        self.write_locked(buffer, Timeout::Deadline(Some(deadline)))
+    }
+
+    /// Like `write_all` but gives up with `TimedOut` once the deadline has passed.
+    /// The deadline applies to the entire call and not to each individual write.
+    /// # Errors
+    /// `TimedOut` if the deadline has passed, otherwise same as `write_all`
+    pub fn write_all_before(&self, buffer: &[u8], deadline: Instant) -> io::Result<()> {
+        self.write_all_deadline(buffer, deadline)
+    }
+
+    /// Like `write_all_before` but the `TimedOut` error carries a `DeadlineExceeded`
+    /// that states how many bytes were written before the deadline passed.
+    /// # Errors
+    /// `TimedOut` if the deadline has passed, otherwise same as `write_all`
+    pub fn write_all_deadline(&self, buffer: &[u8], deadline: Instant) -> io::Result<()> {
+        let mut transferred = 0;
+        while transferred < buffer.len() {
+            match self.write_before(&buffer[transferred..], deadline) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::WriteZero)),
+                Ok(count) => transferred += count,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) if err.kind() == ErrorKind::TimedOut => {
+                    return Err(io::Error::new(ErrorKind::TimedOut, DeadlineExceeded { transferred }))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `write_all` but gives up with `TimedOut` once `timeout` has elapsed.
+    /// The timeout applies to the entire call and not to each individual write. The stored write timeout is ignored.
+    /// # Errors
+    /// `TimedOut` if the timeout elapsed, `InvalidInput` if the timeout is too large, otherwise same as `write_all`
+    pub fn write_all_timeout(&self, buffer: &[u8], timeout: Duration) -> io::Result<()> {
+        self.write_all_before(buffer, deadline_after(timeout)?)
+    }
+
+    /// Writes the entire buffer. This holds the write mutex for the entire call and
+    /// hands the data to rust-tls in record sized chunks, so backpressure applies between chunks.
+    /// # Errors
+    /// same as `write`, on error an unknown amount of the data may have been written.
+    #[allow(clippy::needless_pass_by_value)] //rust-tls copies into its records anyway, ownership is for the callers convenience.
+    pub fn write_owned(&self, data: Vec<u8>) -> io::Result<()> {
+        self.write_all_chunked(&data, Timeout::Stored)
+    }
+
+    /// Writes `line` followed by `\r\n` like `write_owned` does, so concurrent writes do not interleave with it.
+    /// # Errors
+    /// same as `write_owned`
+    pub fn write_line(&self, line: &str) -> io::Result<()> {
+        self.write_lines([line])
+    }
+
+    /// Writes every line followed by `\r\n` with a single `write_owned`.
+    /// # Errors
+    /// same as `write_owned`
+    pub fn write_lines<'a, I: IntoIterator<Item = &'a str>>(&self, lines: I) -> io::Result<()> {
+        let mut data = Vec::new();
+        for line in lines {
+            data.extend_from_slice(line.as_bytes());
+            data.extend_from_slice(b"\r\n");
+        }
+
+        self.write_owned(data)
+    }
+
+    /// Streams everything from `source` into this stream until `source` returns EOF.
+    /// Returns the amount of bytes transferred. The write timeout applies to each record sized chunk.
+    /// # Errors
+    /// errors of `source` and of this stream keep their kind, the message states which side failed.
+    /// On error an unknown amount of the data may have been written.
+    pub fn write_reader<R: Read + ?Sized>(&self, source: &mut R) -> io::Result<u64> {
+        let mut buffer = vec![0u8; MAX_RECORD_SIZE];
+        let mut total = 0u64;
+        loop {
+            let count = match source.read(&mut buffer) {
+                Ok(0) => return Ok(total),
+                Ok(count) => count,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    return Err(io::Error::new(err.kind(), format!("reading from source failed: {err}")))
+                }
+            };
+
+            self.write_all(&buffer[..count]).map_err(|err| {
+                io::Error::new(err.kind(), format!("writing to tls stream failed: {err}"))
+            })?;
+            total += count as u64;
+        }
+    }
+
+    /// Writes the entire `Bytes` like `write_owned` does.
+    /// The payload is only copied once by rust-tls when it is encrypted.
+    /// # Errors
+    /// same as `write`, on error an unknown amount of the data may have been written.
+    #[cfg(feature = "bytes")]
+    #[allow(clippy::needless_pass_by_value)] //rust-tls copies into its records anyway, ownership is for the callers convenience.
+    pub fn write_bytes(&self, data: bytes::Bytes) -> io::Result<()> {
+        self.write_all_chunked(&data, Timeout::Stored)
+    }
+
+    /// Writes all data in record sized chunks while holding the write mutex for the entire call.
+    /// The timeout starts anew for each chunk.
+    fn write_all_chunked(&self, data: &[u8], timeout: Timeout) -> io::Result<()> {
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        let mut remaining = data;
+        while !remaining.is_empty() {
+            let chunk = &remaining[..remaining.len().min(MAX_RECORD_SIZE)];
+            match self.write_locked(chunk, timeout) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::WriteZero)),
+                Ok(count) => remaining = &remaining[count..],
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `write` but uses the given timeout instead of the stored write timeout for just this call.
+    /// None blocks until the data could be written. The stored write timeout is not changed.
+    /// Unlike the stored timeout a zero timeout is allowed, it never waits and gives up with `TimedOut` right away.
+    /// # Errors
+    /// same as `write`
+    pub fn write_with_timeout(&self, buffer: &[u8], timeout: Option<Duration>) -> io::Result<usize> {
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        self.write_locked(buffer, Timeout::Fixed(timeout))
+    }
+
+    /// Writes with the given timeout for waiting on the write queue. Caller must hold the write mutex.
+    fn write_locked(&self, buffer: &[u8], mut timeout: Timeout) -> io::Result<usize> {
+        self.stats.write_call();
+        let deadline = self.await_writable(&mut timeout)?;
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        guard.sock.1.deadline(deadline);
+        let res = guard.write(buffer);
+        guard.sock.1.deadline(None); //Control messages caused by reads must not time out.
+        let handshake_finished = self.handshake_finished(&guard.conn);
+        drop(guard);
+        self.report(handshake_finished, &res);
+        if let Ok(count) = res {
+            self.stats.written_bytes(count);
+            self.observe_written(count);
+            self.write_tap.call(&buffer[..count]);
+        }
+        res
+    }
+
+    /// Waits until the write queue is below its low watermark and returns the deadline the write should use.
+    /// `set_write_timeout` wakes the wait so a stored timeout is re-read. Caller must hold the write mutex.
+    fn await_writable(&self, timeout: &mut Timeout) -> io::Result<Option<Instant>> {
+        //Only measured for the observer and only if the write has to wait at all.
+        let start = (self.observer.is_set() && matches!(self.write_q.over_low(), Ok(true))).then(Instant::now);
+        loop {
+            let epoch = self.write_q.epoch();
+            if self.write_canceled.load(SeqCst) {
+                return Err(cancel::canceled());
+            }
+
+            let deadline = timeout.fix(&self.write_timeout)?;
+            if self.write_q.flush_low(deadline, epoch).map_err(|err| self.write_q_err(err))? {
+                if let Some(start) = start {
+                    self.observer.backpressure(start.elapsed());
+                }
+                return Ok(deadline);
+            }
+        }
+    }
+
+    /// Reports plaintext that rust-tls accepted and the depth of the write queue after it.
+    /// Must not be called while holding the connection mutex.
+    fn observe_written(&self, count: usize) {
+        if !self.observer.is_set() {
+            return;
+        }
+
+        self.observer.bytes_written(count);
+        if let Ok(depth) = self.write_q.len() {
+            self.observer.queue_depth(depth);
+        }
+    }
+
+    /// Like `write` but never blocks.
+    /// Returns the amount of bytes rust-tls accepted, which may be less than `buffer` if its send buffer is nearly full.
+    /// # Errors
+    /// `WouldBlock` if another write is in progress or the write queue is full, otherwise same as `write`
+    pub fn try_write(&self, buffer: &[u8]) -> io::Result<usize> {
+        self.try_write_vectored(&[IoSlice::new(buffer)])
+    }
+
+    /// Like `write_vectored` but never blocks, see `try_write`.
+    fn try_write_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.stats.write_call();
+        let Some(_outer_guard) = sync::try_lock(&self.write_mutex)? else {
+            return Err(io::Error::from(ErrorKind::WouldBlock));
+        };
+
+        self.write_q.try_flush_low().map_err(|err| self.write_q_err(err))?;
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        guard.sock.1.nb(true);
+        let res = guard.write_vectored(bufs);
+        guard.sock.1.nb(false); //Control messages caused by reads must not be refused.
+        let handshake_finished = self.handshake_finished(&guard.conn);
+        drop(guard);
+        self.report(handshake_finished, &res);
+        if let Ok(count) = res {
+            self.stats.written_bytes(count);
+            self.observe_written(count);
+            self.write_tap.call_vectored(bufs, count);
+        }
+        res
+    }
+
+    /// Blocks until a `write` would make progress without waiting: the write queue is below its low watermark,
+    /// the handshake is done and rust-tls handed all its records to the write queue.
+    /// Nothing is written. Another thread may fill the queue before the next write, so this is only a hint.
+    /// Unlike the stored timeout a zero timeout is allowed, it only checks and gives up with `TimedOut` right away.
+    /// This ignores the non-blocking flag and the write timeout.
+    /// # Errors
+    /// `TimedOut` if the timeout elapsed, the error of the background writer if it died,
+    /// the error of the handshake if it failed.
+    pub fn wait_writable(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        let mut timeout = Timeout::Fixed(timeout);
+        loop {
+            let deadline = self.await_writable(&mut timeout)?;
+            //Sampled before the handshake is driven, so data that arrives after that wakes the wait below.
+            let epoch = self.read_q.epoch();
+            let mut guard = unwrap_poison(self.connection.lock())?;
+            guard.sock.0.nb(true); //The handshake must not block on the peer while the connection is locked.
+            guard.sock.1.deadline(deadline);
+            let res = guard.writable();
+            guard.sock.0.nb(false); //We must clear this flag or writes may go ballistic.
+            guard.sock.1.deadline(None); //Control messages caused by reads must not time out.
+            let handshake_finished = self.handshake_finished(&guard.conn);
+            match res {
+                Ok(true) => {
+                    drop(guard);
+                    self.report_handshake(handshake_finished);
+                    //The records that were handed over may have filled the queue again.
+                    match self.write_q.try_flush_low() {
+                        Ok(()) => return Ok(()),
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                        Err(err) => return Err(self.write_q_err(err)),
+                    }
+                }
+                Ok(false) => {
+                    drop(guard);
+                    self.report_handshake(handshake_finished);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock && !handshake_finished => {
+                    //The handshake waits for the peer.
+                    self.read_q
+                        .await_pop(guard, deadline, epoch)
+                        .map_err(|err| self.read_q_err(err))?;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    //Only waits once the end of the handshake was reported.
+                    drop(guard);
+                    self.report_handshake(handshake_finished);
+                }
+                Err(err) => {
+                    drop(guard);
+                    self.report_err(&err);
+                    self.report_handshake(handshake_finished);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Blocks until the handshake is done and rust-tls handed all its records to the write queue, nothing is read or written.
+    /// rust-tls only drives the handshake as part of reads and writes, so without this the first of them pays for it.
+    /// Afterwards `connection_info` has the negotiated protocol version and cipher suite,
+    /// as well as the ALPN protocol if the peers agreed on one. Returns immediately if the handshake is done already.
+    /// This honors the read timeout since the time is mostly spent waiting for the peer.
+    /// # Errors
+    /// `Timeout` if the read timeout elapsed, `Canceled` if reads are canceled,
+    /// `Tls` if the handshake failed, the error of the background threads if they died.
+    pub fn handshake(&self) -> error::Result<()> {
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        let mut timeout = Timeout::Stored;
+        loop {
+            let deadline = timeout.fix(&self.read_timeout)?;
+            //Sampled before the handshake is driven, so data that arrives after that wakes the wait below.
+            let epoch = self.read_q.epoch();
+            let mut guard = unwrap_poison(self.connection.lock())?;
+            guard.sock.0.nb(true); //The handshake must not block on the peer while the connection is locked.
+            guard.sock.1.deadline(deadline);
+            let res = guard.writable();
+            guard.sock.0.nb(false); //We must clear this flag or writes may go ballistic.
+            guard.sock.1.deadline(None); //Control messages caused by reads must not time out.
+            let handshake_finished = self.handshake_finished(&guard.conn);
+            match res {
+                Ok(true) => {
+                    drop(guard);
+                    self.report_handshake(handshake_finished);
+                    return Ok(());
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock && guard.conn.common_state().is_handshaking() => {
+                    if self.read_canceled.load(SeqCst) {
+                        drop(guard);
+                        return Err(DuplexStreamError::Canceled);
+                    }
+
+                    //The handshake waits for the peer.
+                    self.read_q
+                        .await_pop(guard, deadline, epoch)
+                        .map_err(|err| self.read_q_err(err))?;
+                }
+                Ok(false) => {
+                    drop(guard);
+                    self.report_handshake(handshake_finished);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    drop(guard);
+                    self.report_handshake(handshake_finished);
+                }
+                Err(err) => {
+                    drop(guard);
+                    self.report_err(&err);
+                    self.report_handshake(handshake_finished);
+                    return Err(err.into());
+                }
+            }
+        }
+    }
+
+    /// Blocks until the handshake is done, see `handshake`.
+    /// # Errors
+    /// same as `handshake`, converted to `io::Error`
+    #[deprecated(since = "0.2.0", note = "use `handshake`, it returns a `DuplexStreamError`")]
+    pub fn complete_handshake(&self) -> io::Result<()> {
+        self.handshake().map_err(io::Error::from)
+    }
+
+    /// Returns true if a `try_write` would currently not be refused because of a full write queue.
+    /// This is only a hint, another thread may fill the queue or the background writer may drain it right after.
+    /// Poll this (or block in `sync`) to learn when writing is possible again after `WouldBlock`.
+    /// # Errors
+    /// The error of the background writer if it died
+    pub fn is_writable(&self) -> io::Result<bool> {
+        match self.write_q.try_flush_low() {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(self.write_q_err(err)),
+        }
+    }
+
+    /// see `Write::write_vectored`
+    /// All slices are handed to rust-tls in a single call.
+    /// In non-blocking write mode this never blocks, see `try_write`.
+    /// # Errors
+    /// propagated from `Write::write_vectored` once subsequent writes/flushes turn into `BrokenPipe`
+    pub fn write_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        if self.non_blocking_write.load(SeqCst) {
+            return self.try_write_vectored(bufs);
+        }
+
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        self.stats.write_call();
+        let deadline = self.await_writable(&mut Timeout::Stored)?;
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        guard.sock.1.deadline(deadline);
+        let res = guard.write_vectored(bufs);
+        guard.sock.1.deadline(None); //Control messages caused by reads must not time out.
+        let handshake_finished = self.handshake_finished(&guard.conn);
+        drop(guard);
+        self.report(handshake_finished, &res);
+        if let Ok(count) = res {
+            self.stats.written_bytes(count);
+            self.observe_written(count);
+            self.write_tap.call_vectored(bufs, count);
+        }
+        res
+    }
+
+    /// Returns true, `write_vectored` is implemented efficiently.
+    pub const fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    /// see `Write::flush`
+    /// Waits at most for the write timeout until everything was written to the underlying connection and it was flushed.
+    /// This is also what the flush of a `BufWriter` around the stream calls, so wrapping the stream in one loses nothing.
+    /// # Errors
+    /// propagated from `Write::flush` once subsequent writes/flushes turn into `BrokenPipe`
+    /// `TimedOut` if the write timeout elapsed, the data stays queued and a later flush may succeed.
+    pub fn flush(&self) -> io::Result<()> {
+        let timeout_copy = unwrap_poison(self.write_timeout.lock())?
+            .deref()
+            .as_ref()
+            .copied();
+        self.flush_with_timeout(timeout_copy)
+    }
+
+    /// Like `flush` but uses the given timeout instead of the stored write timeout for just this call.
+    /// None blocks until everything was written to the underlying connection and it was flushed.
+    /// Unlike the stored timeout a zero timeout is allowed, it never waits and gives up with `TimedOut` right away.
+    /// # Errors
+    /// same as `flush`
+    pub fn flush_with_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        let deadline = deadline_from(timeout);
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        guard.sock.1.deadline(deadline);
+        let res = guard.flush();
+        guard.sock.1.deadline(None); //Control messages caused by reads must not time out.
+        let handshake_finished = self.handshake_finished(&guard.conn);
+        drop(guard);
+        self.report(handshake_finished, &res);
+        res?;
+        write_pipe::flush_transport(&self.write_q, deadline, &self.write_canceled).map_err(|err| self.write_q_err(err))
+    }
+
+    /// Sends a tls `close_notify` alert after all previously written data and flushes.
+    /// The peer reads EOF once it received everything, reading from this stream is still possible.
+    /// Writing after this is not meaningful.
+    /// The alert can not skip ahead of queued records since tls records have to arrive in order,
+    /// lower the write watermarks to bound how long it waits behind them.
+    /// # Errors
+    /// same as `flush`
+    pub fn send_close_notify(&self) -> io::Result<()> {
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        guard.conn.common_state_mut().send_close_notify();
+        guard.flush()?;
+        drop(guard);
+        write_pipe::flush_transport(&self.write_q, None, &self.write_canceled).map_err(|err| self.write_q_err(err))
+    }
+
+    /// Waits until all data that was already handed to the write queue was written to the underlying connection,
+    /// i.e. the queue is empty and the last write of the background writer returned.
+    /// Unlike `flush` this neither flushes rust-tls nor the underlying connection.
+    /// # Errors
+    /// `TimedOut` if the timeout elapsed. The error of the background writer if it died before that,
+    /// `BrokenPipe` if it died without one.
+    pub fn sync(&self, timeout: Option<Duration>) -> io::Result<()> {
+        write_pipe::sync(&self.write_q, deadline_from(timeout)).map_err(|err| self.write_q_err(err))
+    }
+
+    /// Waits until the background writer took everything from the write queue.
+    /// Unlike `sync` nothing is queued, and the last write the background writer took may still be in progress.
+    /// # Errors
+    /// `TimedOut` if the timeout elapsed. The error of the background writer if it died before that,
+    /// `BrokenPipe` if it died without one.
+    pub fn wait_write_queue_empty(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.write_q.wait_until_empty(timeout).map_err(|err| self.write_q_err(err))
+    }
+
+    /// Checks that the peer is still reachable by sending a tls 1.3 `KeyUpdate` that requests a `KeyUpdate` in return.
+    /// Succeeds once anything was received from the peer after the request was sent, this includes its response
+    /// but also any other data it sent in the meantime. The response is processed by rust-tls with the next read.
+    ///
+    /// The peer may defer its response until it sends its next record (RFC 8446 4.6.3), rust-tls peers
+    /// (like this stream) do so. Against such peers this only confirms liveness while they are sending.
+    /// This also needs room in the read queue, so read buffered data first.
+    /// # Errors
+    /// `Io` with `Unsupported` if the connection does not use tls 1.3. There is no tls 1.2 probe: tls 1.2 has no message
+    /// that forces the peer to respond, rust-tls does not renegotiate and never answers an empty application data record.
+    /// Use a ping of your own protocol for tls 1.2 connections.
+    /// `Timeout` if nothing was received before the timeout elapsed. `Io` with `InvalidInput` if the timeout is too large.
+    /// `Tls` if rust-tls refused to send the request, i.e. because the handshake did not finish yet.
+    /// The error of the background reader or writer if either died.
+    pub fn ping(&self, timeout: Duration) -> error::Result<()> {
+        let deadline = deadline_after(timeout)?;
+        let outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        if guard.conn.common_state().protocol_version() != Some(ProtocolVersion::TLSv1_3) {
+            return Err(io::Error::new(ErrorKind::Unsupported, "liveness checks require tls 1.3").into());
+        }
+
+        let since = self.read_q.received();
+        guard.conn.refresh_traffic_keys()?;
+        guard.sock.1.deadline(Some(deadline));
+        let res = guard.flush();
+        guard.sock.1.deadline(None); //Control messages caused by reads must not time out.
+        drop(guard);
+        res?;
+        write_pipe::flush_transport(&self.write_q, Some(deadline), &self.write_canceled).map_err(|err| self.write_q_err(err))?;
+        drop(outer_guard);
+
+        self.read_q
+            .await_received(since, Some(deadline))
+            .map_err(|err| self.read_q_err(err).into())
+    }
+
+    /// Checks that the peer is still reachable, see `ping`.
+    /// # Errors
+    /// same as `ping`, converted to `io::Error`
+    #[deprecated(since = "0.2.0", note = "use `ping`, it returns a `DuplexStreamError`")]
+    pub fn check_alive(&self, timeout: Duration) -> io::Result<()> {
+        self.ping(timeout).map_err(io::Error::from)
+    }
+
+    /// Derives `len` bytes of keying material from the session secrets, see RFC 5705 and RFC 8446 7.5.
+    /// This is the `tls-exporter` used for channel binding.
+    /// # Errors
+    /// `Io` with `InvalidInput` if `len` is 0. `Tls` if rust-tls cannot export for this connection,
+    /// i.e. because the handshake did not finish yet.
+    pub fn keying_material(&self, label: &[u8], context: Option<&[u8]>, len: usize) -> error::Result<Vec<u8>> {
+        if len == 0 {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "cannot export 0 bytes").into());
+        }
+
+        let mut output = vec![0u8; len];
+        let guard = unwrap_poison(self.connection.lock())?;
+        let res = guard.conn.export_keying_material(&mut output, label, context);
+        drop(guard);
+        res?;
+        Ok(output)
+    }
+
+    /// Derives `len` bytes of keying material from the session secrets, see `keying_material`.
+    /// # Errors
+    /// `InvalidInput` if `len` is 0. `Unsupported` if rust-tls cannot export for this connection.
+    #[deprecated(since = "0.2.0", note = "use `keying_material`, it returns a `DuplexStreamError`")]
+    pub fn export_keying_material(&self, label: &[u8], context: Option<&[u8]>, len: usize) -> io::Result<Vec<u8>> {
+        self.keying_material(label, context, len).map_err(|err| match err {
+            DuplexStreamError::Tls(err) => io::Error::new(ErrorKind::Unsupported, err),
+            err => err.into(),
         })
     }
 
+    /// Calls `f` with the rust-tls connection while holding the connection mutex, for rust-tls APIs this stream does not wrap
+    /// like `peer_certificates` or `alpn_protocol`.
+    /// `f` must not call any fn of this stream, reads and writes wait for the connection mutex and would deadlock.
+    /// Records that `f` makes rust-tls queue are only handed to the write queue by the next write or flush.
+    /// Plaintext that `f` takes out of rust-tls bypasses the read tap and is lost for reads of this stream.
+    /// A panic in `f` poisons the connection mutex, the stream is unusable after that.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn with_connection<F, R>(&self, f: F) -> io::Result<R>
+    where
+        F: FnOnce(&mut C) -> R,
+    {
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        let res = f(&mut guard.conn);
+        drop(guard);
+        Ok(res)
+    }
+
+    /// Like `flush` but never waits for the write queue to drain.
+    /// Returns `WouldBlock` while data is still queued for the underlying connection,
+    /// calling this again later will eventually return `Ok` once everything was handed to the connection.
+    /// # Errors
+    /// `WouldBlock` if data is still in flight, otherwise same as `flush`
+    pub fn flush_non_block(&self) -> io::Result<()> {
+        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        guard.sock.1.nb(true);
+        let res = guard.flush();
+        guard.sock.1.nb(false); //Control messages caused by reads must not be refused.
+        drop(guard);
+        res?;
+
+        if self.write_q.is_empty()? {
+            return Ok(());
+        }
+
+        Err(io::Error::from(ErrorKind::WouldBlock))
+    }
+
+    /// see `Read::read`
+    /// # Errors
+    /// propagated from `Read::read` once subsequent reads turn into `BrokenPipe`
+    /// `ConnectionReset` if the background reader died without the connection signaling EOF.
+    pub fn read(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        self.read_locked(&mut stash, buffer, self.non_blocking_read.load(SeqCst), Timeout::Stored)
+    }
+
+    /// Like `read` but uses the given timeout instead of the stored read timeout for just this call.
+    /// None blocks until data is available. The stored read timeout is not changed.
+    /// Unlike the stored timeout a zero timeout is allowed, it never waits and gives up with `TimedOut` right away.
+    /// # Errors
+    /// same as `read`
+    pub fn read_with_timeout(&self, buffer: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        self.read_locked(&mut stash, buffer, self.non_blocking_read.load(SeqCst), Timeout::Fixed(timeout))
+    }
+
+    /// Like `read` but gives up with `TimedOut` once the deadline has passed.
+    /// The stored read timeout is ignored.
+    /// # Errors
+    /// `TimedOut` if the deadline has already passed, otherwise same as `read`
+    pub fn read_before(&self, buffer: &mut [u8], deadline: Instant) -> io::Result<usize> {
+        remaining(deadline)?;
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        self.read_locked(&mut stash, buffer, self.non_blocking_read.load(SeqCst), Timeout::Deadline(Some(deadline)))
+    }
+
+    /// Like `read_exact` but gives up with `TimedOut` once the deadline has passed.
+    /// The deadline applies to the entire call and not to each individual read.
+    /// # Errors
+    /// `TimedOut` if the deadline has passed, otherwise same as `read_exact`
+    pub fn read_exact_before(&self, buffer: &mut [u8], deadline: Instant) -> io::Result<()> {
+        self.read_exact_deadline(buffer, deadline)
+    }
+
+    /// Like `read_exact_before` but the `TimedOut` error carries a `DeadlineExceeded`
+    /// that states how many bytes were read into the start of `buffer` before the deadline passed.
+    /// # Errors
+    /// `TimedOut` if the deadline has passed, otherwise same as `read_exact`
+    pub fn read_exact_deadline(&self, buffer: &mut [u8], deadline: Instant) -> io::Result<()> {
+        let mut transferred = 0;
+        while transferred < buffer.len() {
+            match self.read_before(&mut buffer[transferred..], deadline) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::UnexpectedEof)),
+                Ok(count) => transferred += count,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) if err.kind() == ErrorKind::TimedOut => {
+                    return Err(io::Error::new(ErrorKind::TimedOut, DeadlineExceeded { transferred }))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `read_exact` but gives up with `TimedOut` once `timeout` has elapsed.
+    /// The timeout applies to the entire call and not to each individual read. The stored read timeout is ignored.
+    /// # Errors
+    /// `TimedOut` if the timeout elapsed, `InvalidInput` if the timeout is too large, otherwise same as `read_exact`
+    pub fn read_exact_timeout(&self, buffer: &mut [u8], timeout: Duration) -> io::Result<()> {
+        self.read_exact_before(buffer, deadline_after(timeout)?)
+    }
+
+    /// Reads until `\n` or EOF and appends everything including the `\n` to `line`.
+    /// Data read after the `\n` stays buffered for the next read.
+    /// The read timeout applies to the entire call and not to each individual read.
+    /// Returns the amount of bytes appended, 0 means EOF.
+    /// # Errors
+    /// `InvalidData` if the line is not valid UTF-8, the line is consumed anyway.
+    /// Otherwise same as `read`, the partial line then stays buffered for the next read.
+    pub fn read_line(&self, line: &mut String) -> io::Result<usize> {
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        let mut timeout = Timeout::Stored;
+        timeout.fix(&self.read_timeout)?; //The line shares one start.
+        let mut bytes = Vec::new();
+        let mut chunk = vec![0u8; MAX_RECORD_SIZE];
+        loop {
+            if let Some(pos) = stash.iter().position(|byte| *byte == b'\n') {
+                bytes.extend(stash.drain(..=pos));
+                break;
+            }
+
+            bytes.extend(stash.drain(..));
+            match self.read_locked(&mut stash, &mut chunk, self.non_blocking_read.load(SeqCst), timeout) {
+                Ok(0) => break,
+                Ok(count) => stash.extend(&chunk[..count]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => {
+                    for byte in bytes.iter().rev() {
+                        stash.push_front(*byte);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        drop(stash);
+
+        let text = String::from_utf8(bytes).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        line.push_str(&text);
+        Ok(text.len())
+    }
+
+    /// Consumes and drops up to `n` bytes of plaintext without handing them to the caller.
+    /// Returns the amount of bytes skipped, which is less than `n` if EOF was reached
+    /// or if the data ran out in non-blocking mode or an interrupt occurred after something was skipped.
+    /// Peeked data is dropped first, the rest is dropped straight from the buffer of rust-tls without copying it.
+    /// The read tap does not see the dropped data.
+    /// The read timeout applies to the entire call and not to each individual read.
+    /// # Errors
+    /// same as `read`, the amount of bytes skipped before an error is lost. Except for `TimedOut`,
+    /// that carries a `DeadlineExceeded` stating how many bytes were skipped before the timeout elapsed.
+    pub fn discard(&self, n: u64) -> io::Result<u64> {
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        self.stats.read_call();
+        let stashed = stash.len().min(usize::try_from(n).unwrap_or(usize::MAX));
+        stash.drain(..stashed);
+        let mut skipped = stashed as u64;
+        let mut timeout = Timeout::Stored;
+        timeout.fix(&self.read_timeout)?; //The whole skip shares one start.
+        while skipped < n {
+            let len = usize::try_from(n - skipped).unwrap_or(usize::MAX);
+            match self.receive_locked(true, self.non_blocking_read.load(SeqCst), timeout, |conn| conn.skip(len)) {
+                Ok(0) => break,
+                Ok(count) => skipped += count as u64,
+                Err(err) if skipped > 0 && matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => break,
+                Err(err) if err.kind() == ErrorKind::TimedOut => {
+                    let transferred = usize::try_from(skipped).unwrap_or(usize::MAX);
+                    return Err(io::Error::new(ErrorKind::TimedOut, DeadlineExceeded { transferred }));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        drop(stash);
+        Ok(skipped)
+    }
+
+    /// Blocks until the next `read` returns at least one byte or EOF without waiting, nothing is consumed.
+    /// Plaintext that rust-tls already decrypted counts as well as ciphertext that is still queued.
+    /// Unlike the stored timeout a zero timeout is allowed, it only checks and gives up with `TimedOut` right away.
+    /// This ignores the non-blocking flag and the read timeout.
+    /// # Errors
+    /// `TimedOut` if the timeout elapsed, `DuplexStreamError::Canceled` if reads are canceled,
+    /// the original error of the underlying read or rust-tls if the connection died.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        if !stash.is_empty() {
+            return Ok(());
+        }
+
+        let res = self.wait_readable_locked(Timeout::Fixed(timeout));
+        drop(stash);
+        res
+    }
+
+    /// Waits until rust-tls has plaintext or saw EOF. Caller must hold the read mutex and checked the stash.
+    fn wait_readable_locked(&self, mut timeout: Timeout) -> io::Result<()> {
+        loop {
+            //Sampled before the state is checked, so a cancel that runs after the check wakes the wait below.
+            let epoch = self.read_q.epoch();
+            let mut guard = unwrap_poison(self.connection.lock())?;
+            guard.sock.0.nb(true); //Only look at what is already there.
+            let res = guard.readable();
+            guard.sock.0.nb(false); //We must clear this flag or writes may go ballistic.
+            let handshake_finished = self.handshake_finished(&guard.conn);
+            match res {
+                Ok(true) => {
+                    drop(guard);
+                    self.report_handshake(handshake_finished);
+                    return Ok(());
+                }
+                Ok(false) => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => {
+                    drop(guard);
+                    self.report_err(&err);
+                    self.report_handshake(handshake_finished);
+                    return Err(err);
+                }
+            }
+
+            if handshake_finished {
+                //Only waits once the end of the handshake was reported.
+                drop(guard);
+                self.report_handshake(handshake_finished);
+                continue;
+            }
+
+            if self.read_canceled.load(SeqCst) {
+                return Err(cancel::canceled());
+            }
+
+            let deadline = timeout.fix(&self.read_timeout)?;
+            self.read_q
+                .await_pop(guard, deadline, epoch)
+                .map_err(|err| self.read_q_err(err))?;
+        }
+    }
+
+    /// Returns a fd for `poll(2)` and friends that is readable while received data waits to be read
+    /// or once the connection died or ended. It is cleared once all received data was handed to rust-tls.
+    ///
+    /// Received data is not always plaintext, a wakeup may be followed by a read that would block.
+    /// rust-tls may also still hold plaintext after the fd was cleared, so use non-blocking reads
+    /// and read until `WouldBlock` (or `wait_readable` with a zero timeout fails) every time the fd is readable.
+    /// Never read from the fd, the stream takes care of clearing it.
+    /// # Errors
+    /// if the fd could not be created on the first call, in case of poisoned mutex
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn readiness_fd(&self) -> io::Result<BorrowedFd<'_>> {
+        Ok(self.read_q.readiness()?.fd())
+    }
+
+    /// Returns a manual-reset event for `WaitForMultipleObjects` and friends that is signaled while received data
+    /// waits to be read or once the connection died or ended. It is reset once all received data was handed to rust-tls.
+    /// The same caveats as for `readiness_fd` on unix apply: read until `WouldBlock` every time it is signaled.
+    /// Never set or reset the event yourself. It is closed when the stream is dropped.
+    /// # Errors
+    /// if the event could not be created on the first call, in case of poisoned mutex
+    #[cfg(all(windows, feature = "windows"))]
+    pub fn readiness_event(&self) -> io::Result<RawHandle> {
+        Ok(self.read_q.readiness()?.handle())
+    }
+
+    /// Registers a waker that is woken once received data waits to be read or the connection died.
+    /// Meant for async adapters: after a non-blocking read returned `WouldBlock`, register the waker and read again.
+    /// Only the most recently registered waker is woken, once. If data already waits it is woken right away.
+    /// It is woken without holding any lock of the stream, so it may poll the stream right away.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn register_read_waker(&self, waker: Waker) -> io::Result<()> {
+        self.read_q.register_push_waker(waker)
+    }
+
+    /// Registers a waker that is woken once the write queue drained to its low watermark or the connection died.
+    /// Meant for async adapters: after a non-blocking write returned `WouldBlock`, register the waker and write again.
+    /// Only the most recently registered waker is woken, once. If the queue already has room it is woken right away.
+    /// A `WouldBlock` because another write was in progress does not wake it, that write returns soon anyway.
+    /// It is woken without holding any lock of the stream, so it may poll the stream right away.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn register_write_waker(&self, waker: Waker) -> io::Result<()> {
+        self.write_q.register_room_waker(waker)
+    }
+
+    /// Attaches the stream to the poller of `sink`, `interest.key` identifies the stream in the posted events.
+    /// With `interest.readable` an event is posted once received data waits to be read,
+    /// with `interest.writable` once the write queue drained to its low watermark. Both are posted once the connection died.
     ///
-    /// Creates a new Tls stream wrapper.
-    ///
-    /// This fn will spawn 2 new threads using  the provided thread spawner function.
-    /// The thread spawner function is called exactly twice. If the first call yields an error then
-    /// it's not called again. The functions ("threads") will end when the returned stream wrapper is dropped.
+    /// With `PollMode::Oneshot` each readiness is posted once, it is re-armed by attaching again like `Poller::modify`.
+    /// With `PollMode::Level` it is posted again whenever data arrives or the write queue drains until the stream is detached.
+    /// In both modes it is posted right away if the stream is already ready.
+    /// Read and write non-blocking until `WouldBlock` after every event, events are hints.
     ///
-    /// # Resource Leaks
-    /// Be aware that a Read which "blocks" forever will cause the thread that's reading to stay alive forever.
-    /// Either set a reasonable connection read timeout so that your Read will eventually return
-    /// or call your connections shutdown fn like `TcpStream::shutdown` if such a method exists
-    /// to ensure that all threads are stopped and no resources are leaked.
-    /// 
+    /// This replaces the previous attachment. It uses the wakers of `register_read_waker` and `register_write_waker`,
+    /// registering one of those ends the attachment for that direction.
     /// # Errors
-    /// propagated from the spawner fn.
-    ///
-    pub fn new<R, W, T>(con: C, read: R, write: W, spawner: T) -> io::Result<Self>
-    where
-        R: Read + Send + 'static,
-        W: Write + Send + 'static,
-        T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>,
-    {
-        let pipe = CombinedPipe::new(read, write, spawner)?;
-        let read_q = pipe.0.dup_queue();
-        let write_q = pipe.1.dup_queue();
+    /// `Unsupported` for the edge modes, the stream only knows when readiness starts. In case of poisoned mutex
+    #[cfg(feature = "polling")]
+    pub fn attach_poller(&self, sink: &Arc<PollerSink>, interest: Event, mode: PollMode) -> io::Result<()> {
+        let level = poller::is_level(mode)?;
+        let attached = Arc::new(AtomicBool::new(true));
+        let mut guard = unwrap_poison(self.poller_attachment.lock())?;
+        guard.store(false, SeqCst);
+        *guard = Arc::clone(&attached);
+        drop(guard);
 
-        Ok(Self {
-            non_blocking_read: AtomicBool::new(false),
-            read_q,
-            write_q,
-            write_mutex: Mutex::new(()),
-            read_mutex: Mutex::new(()),
-            connection: Mutex::new(StreamOwned::new(con, pipe)),
-            read_timeout: Mutex::new(None),
-            write_timeout: Mutex::new(None),
-        })
+        self.read_q.replace_push_waker(None)?;
+        self.write_q.replace_room_waker(None)?;
+        if interest.readable {
+            let event = Event::readable(interest.key);
+            poller::attach(&self.read_q, sink, event, level, &attached, Queue::register_push_waker, Queue::replace_push_waker)?;
+        }
+
+        if interest.writable {
+            let event = Event::writable(interest.key);
+            poller::attach(&self.write_q, sink, event, level, &attached, Queue::register_room_waker, Queue::replace_room_waker)?;
+        }
+
+        Ok(())
     }
 
-    /// see `Write::write`
+    /// Detaches the stream from its poller, no new events are posted for it afterwards.
     /// # Errors
-    /// propagated from `Write::write` once subsequent writes/flushes turn into `BrokenPipe`
-    pub fn write(&self, buffer: &[u8]) -> io::Result<usize> {
-        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
-        let timeout_copy = unwrap_poison(self.write_timeout.lock())?
-            .deref()
-            .as_ref()
-            .copied();
-        self.write_q.flush_low(timeout_copy)?;
-        unwrap_poison(self.connection.lock())?.write(buffer)
+    /// In case of poisoned mutex
+    #[cfg(feature = "polling")]
+    pub fn detach_poller(&self) -> io::Result<()> {
+        unwrap_poison(self.poller_attachment.lock())?.store(false, SeqCst);
+        self.read_q.replace_push_waker(None)?;
+        self.write_q.replace_room_waker(None)
     }
 
-    /// see `Write::flush`
+    /// Like `read` but the data remains readable by the next read.
+    /// Repeated peeks return the same data.
+    /// This only blocks if no data was peeked before.
     /// # Errors
-    /// propagated from `Write::flush` once subsequent writes/flushes turn into `BrokenPipe`
-    pub fn flush(&self) -> io::Result<()> {
-        let _outer_guard = unwrap_poison(self.write_mutex.lock())?; //make writes block other writes
-        unwrap_poison(self.connection.lock())?.flush()?;
-        self.write_q.flush_zero()
+    /// same as `read`
+    pub fn peek(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        if stash.is_empty() {
+            let mut data = vec![0u8; buffer.len()];
+            let count =
+                self.read_locked(&mut stash, &mut data, self.non_blocking_read.load(SeqCst), Timeout::Stored)?;
+            stash.extend(&data[..count]);
+        }
+
+        let count = stash.len().min(buffer.len());
+        for (dst, src) in buffer.iter_mut().zip(stash.iter()) {
+            *dst = *src;
+        }
+        drop(stash);
+        Ok(count)
     }
 
-    /// see `Read::read`
+    /// see `Read::read_vectored`
+    /// Fills the buffers with as much data as is available after the first byte has been read.
+    /// This only blocks until at least one byte can be read.
     /// # Errors
     /// propagated from `Read::read` once subsequent reads turn into `BrokenPipe`
-    pub fn read(&self, buffer: &mut [u8]) -> io::Result<usize> {
-        let _outer_guard = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+    pub fn read_vectored(&self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        let Some(mut index) = bufs.iter().position(|buf| !buf.is_empty()) else {
+            return Ok(0);
+        };
+
+        //Peeked data comes first, the connection is only read once it is used up.
+        let mut offset = 0;
+        let mut total = 0;
+        if !stash.is_empty() {
+            self.stats.read_call();
+        }
+        while index < bufs.len() && !stash.is_empty() {
+            let count = stash.read(&mut bufs[index][offset..])?;
+            offset += count;
+            total += count;
+            if offset == bufs[index].len() {
+                index += 1;
+                offset = 0;
+            }
+        }
+
+        if total == 0 {
+            offset =
+                self.read_locked(&mut stash, &mut bufs[index], self.non_blocking_read.load(SeqCst), Timeout::Stored)?;
+            if offset == 0 {
+                return Ok(0);
+            }
+            total = offset;
+        }
+
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        guard.sock.0.nb(true); //Only take what is already there.
+        let first = total;
+        while index < bufs.len() {
+            if offset == bufs[index].len() {
+                index += 1;
+                offset = 0;
+                continue;
+            }
+
+            match guard.read(&mut bufs[index][offset..]) {
+                Ok(0) | Err(_) => break, //Errors will be reported by the next read.
+                Ok(count) => {
+                    self.stats.read_bytes(count);
+                    self.read_tap.call(&bufs[index][offset..offset + count]);
+                    offset += count;
+                    total += count;
+                }
+            }
+        }
+        guard.sock.0.nb(false); //We must clear this flag or writes may go ballistic.
+        drop(guard);
+        drop(stash);
+        self.observer.bytes_read(total - first);
+        Ok(total)
+    }
+
+    /// Returns the next chunk of plaintext of at most `max_len` bytes, threads calling this take turns in arrival order.
+    /// Meant for demultiplexing frames to several reader threads: each turn delivers exactly one chunk
+    /// and only the thread whose turn came is woken. Other reads do not wait in line, only mix them in if the protocol allows it.
+    /// The stored read timeout covers waiting for the turn and for the data. EOF is signaled by an empty chunk.
+    /// # Errors
+    /// `InvalidInput` if `max_len` is 0, `WouldBlock` in non-blocking mode while another thread has its turn,
+    /// otherwise same as `read`
+    pub fn read_raw_frame(&self, max_len: usize) -> io::Result<Vec<u8>> {
+        if max_len == 0 {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "max_len must not be 0"));
+        }
+
+        let non_blocking = self.non_blocking_read.load(SeqCst);
+        let deadline = Timeout::Stored.fix(&self.read_timeout)?;
+        let turn = self.fair_readers.enter(deadline, non_blocking)?;
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        let mut frame = vec![0u8; max_len.min(MAX_RECORD_SIZE)];
+        let res = self.read_locked(&mut stash, &mut frame, non_blocking, Timeout::Deadline(deadline));
+        drop(stash);
+        drop(turn);
+        frame.truncate(res?);
+        Ok(frame)
+    }
+
+    /// Returns the next chunk of plaintext as an owned buffer.
+    /// This has the same blocking semantics as `read`. EOF is signaled by an empty chunk.
+    /// # Errors
+    /// same as `read`
+    pub fn read_chunk(&self) -> io::Result<Vec<u8>> {
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        let non_blocking = self.non_blocking_read.load(SeqCst);
+        let chunk = self.read_chunk_locked(&mut stash, non_blocking)?;
+        drop(stash);
+        Ok(chunk)
+    }
+
+    /// Writes all plaintext until EOF to `out` chunk by chunk without an intermediate buffer.
+    /// Returns the amount of bytes written. Each chunk is read with the same semantics as `read_chunk`.
+    /// # Errors
+    /// same as `read`, errors of `out` are returned unchanged.
+    /// On error an unknown amount of the data may have been written to `out`.
+    pub fn copy_to<W: Write + ?Sized>(&self, out: &mut W) -> io::Result<u64> {
+        let mut total = 0u64;
+        loop {
+            let chunk = match self.read_chunk() {
+                Ok(chunk) => chunk,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+
+            if chunk.is_empty() {
+                return Ok(total);
+            }
+
+            out.write_all(&chunk)?;
+            total += chunk.len() as u64;
+        }
+    }
+
+    /// Like `read_chunk` but never blocks regardless of the non-blocking flag.
+    /// Returns None if no plaintext is available right now.
+    /// # Errors
+    /// same as `read`, never `WouldBlock`
+    pub fn try_read_chunk(&self) -> io::Result<Option<Vec<u8>>> {
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        let res = match self.read_chunk_locked(&mut stash, true) {
+            Ok(chunk) => Ok(Some(chunk)),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(err) => Err(err),
+        };
+        drop(stash);
+        res
+    }
+
+    /// Turns this stream into a cheap cloneable handle that implements `Read` + `Write` and is `'static`.
+    #[must_use]
+    pub fn into_handle(self) -> DuplexHandle<C> {
+        DuplexHandle::new(self)
+    }
+
+    /// Splits this stream into a read and a write half that can be moved to different threads.
+    /// The stored read and write timeouts move into the halves, each half has its own timeout from then on.
+    /// Dropping one half does not affect the other one, use `OwnedReadHalf::reunite` to get the stream back.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn split(self) -> io::Result<(OwnedReadHalf<C>, OwnedWriteHalf<C>)> {
+        split::split(self)
+    }
+
+    /// Returns a cloneable read half of this shared stream that can be handed to a thread pool.
+    /// The half starts out with the stored read timeout, each half has its own timeout from then on.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn clone_read_half(self: &Arc<Self>) -> io::Result<ReadHalf<C>> {
+        half::read_half(self)
+    }
+
+    /// Returns a cloneable write half of this shared stream that can be handed to a thread pool.
+    /// The half starts out with the stored write timeout, each half has its own timeout from then on.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn clone_write_half(self: &Arc<Self>) -> io::Result<WriteHalf<C>> {
+        half::write_half(self)
+    }
+
+    /// Returns an iterator over the plaintext chunks of this stream, see `Chunks` for the exact semantics.
+    pub const fn chunks(&self) -> Chunks<'_, C> {
+        Chunks::new(self)
+    }
+
+    /// Returns a `BufRead` view of this stream for line oriented protocols.
+    /// Data that the view buffered but did not consume is returned to this stream once the view is dropped.
+    pub const fn buffered(&self) -> BufferedDuplexStream<'_, C> {
+        BufferedDuplexStream::new(self)
+    }
+
+    /// Replaces the error of a dead write queue with the original error of the background writer.
+    fn write_q_err(&self, err: io::Error) -> io::Error {
+        if err.kind() != ErrorKind::BrokenPipe {
+            return err;
+        }
+
+        unwrap_poison(self.connection.lock()).map_or(err, |guard| guard.sock.idle_err(guard.sock.1.fetch_err()))
+    }
+
+    /// Replaces the error of a dead read queue with the original error of the background reader.
+    fn read_q_err(&self, err: io::Error) -> io::Error {
+        if err.kind() != ErrorKind::BrokenPipe {
+            return err;
+        }
+
+        unwrap_poison(self.connection.lock()).map_or(err, |guard| guard.sock.idle_err(guard.sock.0.fetch_err()))
+    }
+
+    /// Returns true if the handshake finished and that was not reported yet. Caller must hold the connection mutex.
+    fn handshake_finished(&self, conn: &C) -> bool {
+        self.events.handshake_pending() && !conn.common_state().is_handshaking()
+    }
+
+    /// Reports the end of the handshake if `finished` is the result of `handshake_finished`.
+    /// Caller must not hold the connection mutex.
+    fn report_handshake(&self, finished: bool) {
+        if !finished {
+            return;
+        }
+
+        self.stats.handshake();
+        if let Ok(info) = self.connection_info() {
+            debug!(id = self.id, version = ?info.protocol_version, cipher_suite = ?info.cipher_suite, "handshake complete");
+            self.events.handshake_complete(&info);
+        }
+    }
+
+    /// Reports the outcome of an operation on the connection, see `report_err` and `report_handshake`.
+    /// Caller must not hold the connection mutex.
+    fn report<T>(&self, handshake_finished: bool, res: &io::Result<T>) {
+        if let Err(err) = res {
+            self.report_err(err);
+        }
+        self.report_handshake(handshake_finished);
+    }
+
+    /// Reports errors of rust-tls, they kill the connection. Caller must not hold the connection mutex.
+    /// Errors of the transport are reported by the background threads, everything else is not fatal.
+    fn report_err(&self, err: &io::Error) {
+        if err.kind() == ErrorKind::InvalidData {
+            error!(id = self.id, kind = ?err.kind(), error = %err, "tls failed");
+            self.events.error(err);
+        }
+    }
+
+    /// Kills the read queue, pending and future reads fail with `BrokenPipe`.
+    fn kill_read(&self) {
+        self.read_q.kill("read side shut down");
+    }
+
+    /// Puts plaintext back in front of the data that is read next.
+    fn unread(&self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        if let Ok(mut stash) = unwrap_poison(self.read_mutex.lock()) {
+            for byte in data.iter().rev() {
+                stash.push_front(*byte);
+            }
+        }
+    }
+
+    /// Reads the next chunk, peeked data is returned as is. Caller must hold the read mutex.
+    fn read_chunk_locked(&self, stash: &mut VecDeque<u8>, non_blocking: bool) -> io::Result<Vec<u8>> {
+        if !stash.is_empty() {
+            return Ok(stash.drain(..).collect());
+        }
+
+        let mut buffer = unwrap_poison(self.chunk_buffer.lock())?;
+        buffer.resize(CHUNK_SIZE, 0);
+        let count = self.read_locked(stash, &mut buffer, non_blocking, Timeout::Stored)?;
+        let chunk = buffer[..count].to_vec();
+        drop(buffer);
+        Ok(chunk)
+    }
+
+    /// Appends all plaintext that is available right now to `out` without ever blocking.
+    /// Returns the amount of bytes appended, which is 0 if nothing is available or at EOF.
+    /// This ignores the non-blocking flag and the read timeout.
+    /// # Errors
+    /// propagated from `Read::read` if no data could be appended, never `WouldBlock`
+    pub fn read_available(&self, out: &mut Vec<u8>) -> io::Result<usize> {
+        let mut stash = unwrap_poison(self.read_mutex.lock())?; //make reads block other reads
+        self.stats.read_call();
+        let stashed = stash.len();
+        let mut total = stashed;
+        out.extend(stash.drain(..));
+
+        let mut buffer = [0u8; MAX_RECORD_SIZE];
+        let mut guard = unwrap_poison(self.connection.lock())?;
+        guard.sock.0.nb(true); //Only take what is already there.
+        let res = loop {
+            match guard.read(&mut buffer) {
+                Ok(0) => break Ok(total),
+                Ok(count) => {
+                    self.stats.read_bytes(count);
+                    self.read_tap.call(&buffer[..count]);
+                    out.extend_from_slice(&buffer[..count]);
+                    total += count;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break Ok(total),
+                Err(_) if total > 0 => break Ok(total), //Errors will be reported by the next read.
+                Err(err) => break Err(err),
+            }
+        };
+        guard.sock.0.nb(false); //We must clear this flag or writes may go ballistic.
+        let handshake_finished = self.handshake_finished(&guard.conn);
+        drop(guard);
+        drop(stash);
+        self.report(handshake_finished, &res);
+        self.observer.bytes_read(total - stashed);
+        res
+    }
+
+    /// Reads with the blocking semantics of `read`. Caller must hold the read mutex.
+    /// If `non_blocking` or non-blocking read mode is set `WouldBlock` is returned instead of waiting for data.
+    /// Enabling non-blocking read mode while this waits makes it return `WouldBlock`.
+    /// `timeout` selects whether the stored read timeout, a per-call timeout or a deadline is used.
+    /// A timeout is turned into a deadline once, wakeups that yield no plaintext do not restart it.
+    fn read_locked(
+        &self,
+        stash: &mut VecDeque<u8>,
+        buffer: &mut [u8],
+        non_blocking: bool,
+        timeout: Timeout,
+    ) -> io::Result<usize> {
+        self.stats.read_call();
+        if !stash.is_empty() {
+            return stash.read(buffer);
+        }
+
+        let wanted = !buffer.is_empty();
+        let count = self.receive_locked(wanted, non_blocking, timeout, |conn| conn.read(buffer))?;
+        self.read_tap.call(&buffer[..count]);
+        Ok(count)
+    }
+
+    /// Runs `take` on the connection with the blocking semantics of `read_locked`, ignoring the stash.
+    /// `take` returns the amount of plaintext it consumed, 0 means EOF unless nothing was `wanted`.
+    /// Caller must hold the read mutex.
+    fn receive_locked(
+        &self,
+        wanted: bool,
+        non_blocking: bool,
+        mut timeout: Timeout,
+        mut take: impl FnMut(&mut TlsStream<C, CombinedPipe>) -> io::Result<usize>,
+    ) -> io::Result<usize> {
         loop {
+            //Sampled before the flag is checked, so a setter that runs after the check wakes the wait below.
+            let epoch = self.read_q.epoch();
             let mut guard = unwrap_poison(self.connection.lock())?;
             guard.sock.0.nb(true); //Return instantly if no data.
-            let res = guard.read(buffer);
+            let res = take(&mut guard);
             guard.sock.0.nb(false); //We must clear this flag or writes may go ballistic.
-            return match res {
+            let handshake_finished = self.handshake_finished(&guard.conn);
+            match res {
                 Ok(count) => {
                     drop(guard);
-                    Ok(count)
-                },
+                    self.report_handshake(handshake_finished);
+                    if count == 0 && wanted {
+                        #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+                        self.read_q.end();
+                        self.events.peer_closed();
+                    }
+                    self.stats.read_bytes(count);
+                    self.observer.bytes_read(count);
+                    return Ok(count);
+                }
+                Err(err) if !non_blocking && handshake_finished && err.kind() == ErrorKind::WouldBlock => {
+                    //Only waits once the end of the handshake was reported.
+                    drop(guard);
+                    self.report_handshake(handshake_finished);
+                }
+                Err(err) if non_blocking || err.kind() != ErrorKind::WouldBlock => {
+                    drop(guard);
+                    self.report_err(&err);
+                    self.report_handshake(handshake_finished);
+                    return Err(err);
+                }
                 Err(err) => {
                     if self.non_blocking_read.load(SeqCst) {
+                        drop(guard);
                         return Err(err);
                     }
-                    if err.kind() == ErrorKind::WouldBlock {
-                        //We have entered the fun zone where reads would block writes
-                        let timeout_copy = unwrap_poison(self.read_timeout.lock())?
-                            .deref()
-                            .as_ref()
-                            .copied();
-                        //This drops guard as oon as a handle to read_q is acquired and will return once trying to read again is meaningful.
-                        self.read_q.await_pop(guard, timeout_copy)?;
-                        continue;
+
+                    if self.read_canceled.load(SeqCst) {
+                        drop(guard);
+                        return Err(cancel::canceled());
                     }
-                    
-                    drop(guard);
-                    Err(err)
+
+                    //We have entered the fun zone where reads would block writes
+                    let deadline = timeout.fix(&self.read_timeout)?;
+                    //This drops guard as oon as a handle to read_q is acquired and will return once trying to read again is meaningful.
+                    self.await_read(guard, deadline, epoch)?;
                 }
-            };
+            }
         }
     }
 
+    /// Waits like `Queue::await_pop` and publishes the deadline for `read_deadline` meanwhile. Caller must hold the read mutex.
+    fn await_read<G>(&self, guard: MutexGuard<'_, G>, deadline: Option<Instant>, epoch: u64) -> io::Result<()> {
+        *unwrap_poison(self.read_deadline.lock())? = deadline;
+        let waited = self.read_q.await_pop(guard, deadline, epoch);
+        *unwrap_poison(self.read_deadline.lock())? = None;
+        waited.map_err(|err| self.read_q_err(err))
+    }
+
+    /// sets non-blocking mode for write.
+    /// This has no effect on the underlying connection and purely deals with internal writing semantics.
+    /// `write` and `write_vectored` behave like `try_write` and return `WouldBlock` immediately
+    /// if the write queue is full or another write is in progress.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn set_write_non_block(&self, on: bool) -> io::Result<()> {
+        self.non_blocking_write.store(on, SeqCst);
+        Ok(())
+    }
+
     /// sets the timeout for the writing operation. 
     /// This has no effect on the underlying connection and purely deals with internal writing semantics.
     /// Calls to fns that writs data will return `TimedOut` if no plain text data could be written. 
@@ -192,26 +2077,162 @@ where
     /// This is never caused by writing too much data.
     /// # Errors
     /// In case of poisoned mutex
-    /// 
+    /// `InvalidInput` if the timeout is zero, like `TcpStream::set_read_timeout`
+    ///
+    /// Reads that are already waiting re-read the timeout, measured from when they started waiting.
     pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
-        *unwrap_poison(self.read_timeout.lock())? = timeout;
+        *unwrap_poison(self.read_timeout.lock())? = check_timeout(timeout)?;
+        self.read_q.wake_all();
         Ok(())
     }
 
+    /// Changes the idle read timeout, the connection is killed once nothing was received from it for this long.
+    /// The idle window starts over when this is called. Reads then fail with `TimedOut`
+    /// once the data that was received before is exhausted.
+    /// # Errors
+    /// `Unsupported` if the stream was created without `StreamConfig::idle_read_timeout`,
+    /// since the watchdog thread can only be spawned on creation.
+    /// `InvalidInput` if the timeout is zero. In case of poisoned mutex
+    pub fn set_idle_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        check_timeout(Some(timeout))?;
+        unwrap_poison(self.connection.lock())?.sock.0.idle_timeout(timeout)
+    }
+
+    /// Sets the idle timeout, the stream is killed once nothing was read or written for this long. None disables it.
+    /// Plaintext that is read or written and the handshake are activity, as is ciphertext that is still being
+    /// received or queued for sending, so a large transfer that makes progress never times out.
+    /// The idle window starts over when this is called. Once it elapsed blocked and future reads and writes
+    /// fail with `TimedOut` and the `TimedOut` error is reported to the event handler, no `close_notify` is sent.
+    ///
+    /// The timeout is enforced by a single reaper thread that all streams of the process share. It is spawned
+    /// when the first stream sets a timeout, checks every 100 milliseconds and ends once no such stream is left.
+    /// # Errors
+    /// `InvalidInput` if the timeout is zero, if the reaper thread could not be spawned. In case of poisoned mutex
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.idle.set_timeout(check_timeout(timeout)?)
+    }
+
+    /// Sets a tap that is handed all plaintext that is read, in order and each byte exactly once.
+    /// Data is handed over once it was decrypted, peeked data when it is peeked.
+    /// The tap runs while reads are blocked, keep it short. A tap that panics is removed.
+    pub fn set_read_tap(&self, tap: Tap) {
+        self.read_tap.set(Some(tap));
+    }
+
+    /// Sets a tap that is handed all plaintext that is written, in order and each byte exactly once.
+    /// Data is handed over once rust-tls accepted it, which does not mean that it was sent yet.
+    /// The tap runs while writes are blocked, keep it short. A tap that panics is removed.
+    pub fn set_write_tap(&self, tap: Tap) {
+        self.write_tap.set(Some(tap));
+    }
+
+    /// Sets a callback that the background reader invokes once received data waits to be read, None removes it.
+    /// Invocations are coalesced: a burst of records invokes it once, the next invocation comes with the first record
+    /// that arrives after the received data was drained, i.e. by `read_available` or non-blocking reads until `WouldBlock`.
+    /// It is also invoked once the connection failed, so the next read reports the error, and once right away
+    /// on the calling thread when it is set, so data that arrived earlier is not missed. Any of these may find nothing to read.
+    ///
+    /// It never runs while a lock of the stream is held, so it may read (i.e. `read_available`), write or replace itself.
+    /// It runs on the background reader, which receives nothing until it returned: it must not block on a read
+    /// of this stream, that read would wait for itself. A callback that panics is removed.
+    pub fn set_data_callback(&self, cb: Option<DataCallback>) {
+        let set = cb.is_some();
+        self.data_callback.set(cb);
+        if set {
+            self.data_callback.call();
+        }
+    }
+
+    /// Sets the handler of the lifecycle events of this stream, it replaces the previous one.
+    /// Events that happened before it was set are not delivered, set it right after creating the stream.
+    ///
+    /// The end of the handshake is noticed by the read or write that drives it, errors of rust-tls by the read or
+    /// write that fails with `InvalidData` and a `close_notify` of the peer by the read that returns EOF.
+    /// EOF and errors of the transport are delivered by the background threads as soon as they happen.
+    /// Each event is delivered at most once, a `reconnect` starts over. The end of the handshake is not delivered
+    /// once an error was.
+    ///
+    /// The handler never runs while the connection mutex is held, but the read or write mutex may be held.
+    /// It must not block on a read or write of this stream. A handler that panics is removed.
+    pub fn set_event_handler(&self, handler: Arc<dyn StreamEvents>) {
+        self.events.set(handler);
+    }
+
+    /// Returns a receiver for the readiness events of this stream, any number of receivers may exist.
+    ///
+    /// Events are edges, not levels: `Readable` is sent once received data waits to be read and not again until
+    /// it was drained, i.e. by `read_available` or non-blocking reads until `WouldBlock`. It is sent once right away
+    /// if received data waits that no read looked at yet. `WriteCapacityAvailable` follows a non-blocking write or flush that failed with `WouldBlock`
+    /// once the write queue drained to its low watermark. Any of these may find nothing to do.
+    ///
+    /// The receiver disconnects once the stream failed, after the `Error` event, or was dropped.
+    /// Receivers that are dropped are forgotten. A `reconnect` keeps receivers of a stream that did not fail,
+    /// a stream that failed has to be subscribed again after it.
+    pub fn subscribe(&self) -> mpsc::Receiver<StreamEvent> {
+        self.events.subscribe(matches!(self.read_q.is_empty(), Ok(false)))
+    }
+
+    /// Sets the observer that receives the metrics of this stream, None removes it.
+    /// It is called on the threads that read and write and on the background writer, never while the connection
+    /// mutex is held. While none is set the only cost is an atomic load per read and write. An observer that panics is removed.
+    pub fn set_observer(&self, observer: Option<Arc<dyn StreamObserver>>) {
+        self.observer.set(observer);
+    }
+
+    /// Removes the read and write taps.
+    pub fn clear_taps(&self) {
+        self.read_tap.set(None);
+        self.write_tap.set(None);
+    }
+
     /// Returns the current read timeout if any
     /// # Errors
     /// In case of poisoned mutex
     pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
-        Ok(unwrap_poison(self.read_timeout.lock())?.as_ref().cloned())
+        Ok(unwrap_poison(self.read_timeout.lock())?.as_ref().copied())
+    }
+
+    /// Returns when the read that currently waits for data gives up with `TimedOut`.
+    /// None if no read waits or if it waits without a timeout. Meant for monitoring threads that look for stuck reads.
+    /// Only the waits of reads are covered, `wait_readable` and the handshake of writes are not.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn read_deadline(&self) -> io::Result<Option<Instant>> {
+        Ok(*unwrap_poison(self.read_deadline.lock())?)
+    }
+
+    /// Returns a token that makes blocked and future blocking reads fail with `DuplexStreamError::Canceled`
+    /// (kind `Other`) until it is reset. All tokens of a stream share the same state.
+    #[must_use]
+    pub fn read_cancel_token(&self) -> ReadCancelToken {
+        ReadCancelToken::new(Arc::clone(&self.read_canceled), Arc::clone(&self.read_q))
+    }
+
+    /// Makes every thread that is blocked writing or flushing give up with `DuplexStreamError::Canceled`
+    /// (kind `Other`). Returns once all of them did, writes started afterwards block as usual.
+    /// Data that was already encrypted stays queued and is still sent, so the connection remains usable.
+    /// A canceled `write` may have handed part of its buffer to rust-tls, that part is sent as well.
+    /// `sync` is not affected since it does not take part in writing, give it a timeout instead.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn cancel_pending_writes(&self) -> io::Result<()> {
+        self.write_canceled.store(true, SeqCst);
+        self.write_q.wake_all();
+        let guard = unwrap_poison(self.write_mutex.lock()); //blocked writers hold it until they gave up
+        self.write_canceled.store(false, SeqCst);
+        drop(guard?);
+        Ok(())
     }
 
     /// sets non-blocking mode for read.
     /// This has no effect on the underlying connection and purely deals with internal reading semantics.
     /// Calls to fns that read data will return `WouldBlock` immediately if no plain text data is available to be read.
+    /// Reads that are already blocked waiting for data are woken and return `WouldBlock` as well.
     /// # Errors
     /// In case of poisoned mutex
     pub fn set_read_non_block(&self, on: bool) -> io::Result<()> {
         self.non_blocking_read.store(on, SeqCst);
+        self.read_q.wake_all(); //Blocked reads re-check the flag.
         Ok(())
     }
 
@@ -222,8 +2243,13 @@ where
     /// This is never caused by reading too much data.
     /// # Errors
     /// In case of poisoned mutex
+    /// `InvalidInput` if the timeout is zero, like `TcpStream::set_write_timeout`
+    ///
+    /// Writes that wait for room in the write queue re-read the timeout, measured from when they started waiting.
+    /// A flush that already waits for the underlying connection keeps the timeout it started with.
     pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
-        *unwrap_poison(self.write_timeout.lock())? = timeout;
+        *unwrap_poison(self.write_timeout.lock())? = check_timeout(timeout)?;
+        self.write_q.wake_all();
         Ok(())
     }
 
@@ -231,7 +2257,7 @@ where
     /// # Errors
     /// In case of poisoned mutex
     pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
-        Ok(unwrap_poison(self.write_timeout.lock())?.as_ref().cloned())
+        Ok(unwrap_poison(self.write_timeout.lock())?.as_ref().copied())
     }
 
     /// See `Read::read_to_end`
@@ -270,96 +2296,258 @@ where
     }
 }
 
-impl<C, S> Read for RustTlsDuplexStream<C, S>
+impl<C> Read for RustTlsDuplexStream<C>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>> + Send,
-    S: rustls::SideData,
+    C: TlsConnection,
 {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         Self::read(self, buf)
     }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        Self::read_vectored(self, bufs)
+    }
 }
 
-impl<C, S> Read for &RustTlsDuplexStream<C, S>
+impl<C> Read for &RustTlsDuplexStream<C>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>> + Send,
-    S: rustls::SideData,
+    C: TlsConnection,
 {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         RustTlsDuplexStream::read(self, buf)
     }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        RustTlsDuplexStream::read_vectored(self, bufs)
+    }
 }
 
-impl<C, S> Write for RustTlsDuplexStream<C, S>
+impl<C> Write for RustTlsDuplexStream<C>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>> + Send,
-    S: rustls::SideData,
+    C: TlsConnection,
 {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         Self::write(self, buf)
     }
 
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        Self::write_vectored(self, bufs)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         Self::flush(self)
     }
 }
 
-impl<C, S> Write for &RustTlsDuplexStream<C, S>
+impl<C> Write for &RustTlsDuplexStream<C>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>> + Send,
-    S: rustls::SideData,
+    C: TlsConnection,
 {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         RustTlsDuplexStream::write(self, buf)
     }
 
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        RustTlsDuplexStream::write_vectored(self, bufs)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         RustTlsDuplexStream::flush(self)
     }
 }
 
+///
+/// A tls stream has no position, every seek fails with `Unsupported`, this includes `stream_position` and `rewind`.
+/// This only exists so the stream can be used where a `Seek` bound is required.
+///
+impl<C> Seek for RustTlsDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+        Err(not_seekable())
+    }
+}
+
+/// see the impl for `RustTlsDuplexStream`
+impl<C> Seek for &RustTlsDuplexStream<C>
+where
+    C: TlsConnection,
+{
+    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+        Err(not_seekable())
+    }
+}
+
+/// Spawner that runs every background task on a new thread with the given settings.
+fn spawn_thread(spawn: SpawnConfig) -> impl FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()> {
+    move |task| {
+        let mut builder = thread::Builder::new();
+        if let Some(size) = spawn.stack_size {
+            builder = builder.stack_size(size);
+        }
+        builder.spawn(task).map(|_| {})
+    }
+}
+
+/// Error of every seek.
+fn not_seekable() -> io::Error {
+    io::Error::new(ErrorKind::Unsupported, "TLS stream is not seekable")
+}
+
+/// Which timeout a blocking read or write waits with.
+#[derive(Debug, Clone, Copy)]
+enum Timeout {
+    /// The timeout set by `set_read_timeout` or `set_write_timeout`.
+    Stored,
+    /// The stored timeout measured from the given start, it is re-read so changes affect waits in progress.
+    Since(Instant),
+    /// A timeout given for a single call.
+    Fixed(Option<Duration>),
+    /// An absolute point in time after which `TimedOut` is returned, None means never.
+    Deadline(Option<Instant>),
+}
+
+impl Timeout {
+    /// Turns a timeout into a deadline starting now, so later calls keep measuring from the same start.
+    /// The stored timeout is re-read by every call, the others keep returning the same deadline.
+    fn fix(&mut self, stored: &Mutex<Option<Duration>>) -> io::Result<Option<Instant>> {
+        match *self {
+            Self::Stored => {
+                *self = Self::Since(Instant::now());
+                self.fix(stored)
+            }
+            Self::Since(start) => Ok((*unwrap_poison(stored.lock())?).and_then(|timeout| start.checked_add(timeout))),
+            Self::Fixed(timeout) => {
+                let deadline = deadline_from(timeout);
+                *self = Self::Deadline(deadline);
+                Ok(deadline)
+            }
+            Self::Deadline(deadline) => Ok(deadline),
+        }
+    }
+}
+
 /// Read+Write combiner that is fed into rust-tls and delegates to our special ReadPipe/WritePipe 
 /// that have careful blocking semantics
 #[derive(Debug)]
-struct CombinedPipe(ReadPipe, WritePipe);
+struct CombinedPipe(ReadPipe, WritePipe, Arc<AtomicBool>);
 
 impl CombinedPipe {
     
     ///Constructor for `CombinedPipe`
+    /// Each background task is handed to the spawner as is, nothing about the threads is configured here.
     pub fn new<
         R: Read + Send + 'static,
         W: Write + Send + 'static,
         T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>,
     >(
+        id: u64,
         read: R,
         write: W,
         mut spawner: T,
+        config: &StreamConfig,
     ) -> io::Result<Self> {
+        let queue_config = QueueConfig {
+            id,
+            total_memory_limit: config.total_memory_limit,
+            ..QueueConfig::default()
+        };
+        let memory = Arc::default();
+        let events = Arc::default();
         Ok(Self(
-            ReadPipe::new(read, &mut spawner)?,
-            WritePipe::new(write, &mut spawner)?,
+            ReadPipe::new(
+                read,
+                id,
+                Queue::with_memory(queue_config, Arc::clone(&memory)),
+                Arc::clone(&events),
+                ReadPipeConfig {
+                    idle_timeout: config.idle_read_timeout,
+                    on_full: config.read_on_full,
+                },
+                &mut spawner,
+            )?,
+            WritePipe::new(
+                write,
+                id,
+                Queue::with_memory(queue_config, memory),
+                WritePipeConfig {
+                    coalesce_window: config.write_coalesce_window,
+                    auto_flush: config.write_auto_flush,
+                    flush_every_n_messages: config.write_flush_every_n_messages,
+                },
+                events,
+                Arc::default(),
+                &mut spawner,
+            )?,
+            Arc::default(),
         ))
     }
+
+    /// Replaces the error of a transport the idle reaper killed with `TimedOut`.
+    fn idle_err(&self, err: io::Error) -> io::Error {
+        IdleWatch::map_err(&self.2, err)
+    }
 }
 
 impl Read for CombinedPipe {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.0.read(buf)
+        self.0.read(buf).map_err(|err| self.idle_err(err))
     }
 }
 
 impl Write for CombinedPipe {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.1.write(buf)
+        self.1.write(buf).map_err(|err| self.idle_err(err))
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.1.write_vectored(bufs).map_err(|err| self.idle_err(err))
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.1.flush()
+        self.1.flush().map_err(|err| self.idle_err(err))
+    }
+}
+
+
+/// Time left until the deadline or `TimedOut` if it has already passed.
+pub(crate) fn remaining(deadline: Instant) -> io::Result<Duration> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .ok_or_else(|| io::Error::from(ErrorKind::TimedOut))
+}
+
+/// Rejects zero timeouts the same way `std::net::TcpStream` does.
+pub(crate) fn check_timeout(timeout: Option<Duration>) -> io::Result<Option<Duration>> {
+    if timeout == Some(Duration::ZERO) {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "cannot set a 0 duration timeout"));
     }
+
+    Ok(timeout)
+}
+
+/// Deadline that is `timeout` from now, None if there is no timeout or it is too large to be represented.
+fn deadline_from(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.and_then(|timeout| Instant::now().checked_add(timeout))
 }
 
+/// Deadline that is `timeout` from now.
+fn deadline_after(timeout: Duration) -> io::Result<Instant> {
+    Instant::now()
+        .checked_add(timeout)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "timeout too large"))
+}
 
 /// Poison error to `io::Error`
+#[cfg(not(feature = "parking_lot"))]
 pub(crate) fn unwrap_poison<T>(result: LockResult<T>) -> io::Result<T> {
-    result.map_err(|_| io::Error::new(ErrorKind::Other, "Poisoned Mutex"))
+    result.map_err(|_| error::poisoned())
+}
+
+/// `parking_lot` locks never poison so this never fails.
+#[cfg(feature = "parking_lot")]
+#[allow(clippy::unnecessary_wraps)]
+pub(crate) const fn unwrap_poison<T>(result: LockResult<T>) -> io::Result<T> {
+    Ok(result)
 }
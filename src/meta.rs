@@ -0,0 +1,31 @@
+//! Addresses of the transport of a stream.
+use std::fmt::Debug;
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+
+///
+/// Describes the transport of a stream, see `RustTlsDuplexStream::new_with_meta`.
+///
+/// Implemented for `TcpStream`, implement it for other transports that have socket addresses.
+///
+pub trait ConnectionMeta: Debug + Send + Sync {
+    /// The address of the peer.
+    /// # Errors
+    /// if the transport can not tell
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// The local address.
+    /// # Errors
+    /// if the transport can not tell
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+impl ConnectionMeta for TcpStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Self::peer_addr(self)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Self::local_addr(self)
+    }
+}
@@ -0,0 +1,120 @@
+//! Registration of a stream with a mio `Poll`.
+use crate::{RustTlsDuplexStream, TlsConnection};
+use mio::event::Source;
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+use std::io;
+use std::io::ErrorKind;
+use std::os::fd::{AsRawFd, RawFd};
+
+/// Removes the fd from the registry, an fd that was not registered is ignored.
+fn deregister_fd(registry: &Registry, fd: RawFd) -> io::Result<()> {
+    match registry.deregister(&mut SourceFd(&fd)) {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// Registers the fd or changes its registration if it is already registered.
+fn reregister_fd(registry: &Registry, fd: RawFd, token: Token, interest: Interest) -> io::Result<()> {
+    match registry.reregister(&mut SourceFd(&fd), token, interest) {
+        Err(err) if err.kind() == ErrorKind::NotFound => registry.register(&mut SourceFd(&fd), token, interest),
+        res => res,
+    }
+}
+
+/// Returns the fd that is readable while received data waits and the fd that is writable while the write queue has room.
+fn fds<C: TlsConnection>(stream: &RustTlsDuplexStream<C>) -> io::Result<(RawFd, RawFd)> {
+    Ok((
+        stream.read_q.readiness()?.fd().as_raw_fd(),
+        stream.write_q.room()?.fd().as_raw_fd(),
+    ))
+}
+
+/// see `Source::register`
+fn register<C: TlsConnection>(
+    stream: &RustTlsDuplexStream<C>,
+    registry: &Registry,
+    token: Token,
+    interests: Interest,
+) -> io::Result<()> {
+    let (readable, writable) = fds(stream)?;
+    if interests.is_readable() {
+        registry.register(&mut SourceFd(&readable), token, Interest::READABLE)?;
+    }
+
+    if interests.is_writable() {
+        if let Err(err) = registry.register(&mut SourceFd(&writable), token, Interest::WRITABLE) {
+            //Leave nothing half registered.
+            _ = deregister_fd(registry, readable);
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+/// see `Source::reregister`, each fd is only registered while `interests` wants it.
+fn reregister<C: TlsConnection>(
+    stream: &RustTlsDuplexStream<C>,
+    registry: &Registry,
+    token: Token,
+    interests: Interest,
+) -> io::Result<()> {
+    let (readable, writable) = fds(stream)?;
+    if interests.is_readable() {
+        reregister_fd(registry, readable, token, Interest::READABLE)?;
+    } else {
+        deregister_fd(registry, readable)?;
+    }
+
+    if interests.is_writable() {
+        reregister_fd(registry, writable, token, Interest::WRITABLE)
+    } else {
+        deregister_fd(registry, writable)
+    }
+}
+
+/// see `Source::deregister`
+fn deregister<C: TlsConnection>(stream: &RustTlsDuplexStream<C>, registry: &Registry) -> io::Result<()> {
+    let (readable, writable) = fds(stream)?;
+    deregister_fd(registry, readable)?;
+    deregister_fd(registry, writable)
+}
+
+///
+/// The stream is `READABLE` while received data waits to be read or the connection died
+/// and `WRITABLE` while the write queue is at or below its low watermark or the connection died.
+///
+/// Like all mio sources readiness is edge triggered: once an event arrived read (or write) until `WouldBlock`,
+/// so enable `set_read_non_block` and `set_write_non_block`. Readiness is a hint, a read or write may still
+/// return `WouldBlock`, for example while another thread reads or writes or while the handshake waits for the peer.
+///
+impl<C: TlsConnection> Source for RustTlsDuplexStream<C> {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        register(self, registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        reregister(self, registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        deregister(self, registry)
+    }
+}
+
+/// see the impl for `RustTlsDuplexStream`, this allows registering a shared stream.
+impl<C: TlsConnection> Source for &RustTlsDuplexStream<C> {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        register(self, registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        reregister(self, registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        deregister(self, registry)
+    }
+}
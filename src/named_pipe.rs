@@ -0,0 +1,203 @@
+//! Transport over a windows named pipe.
+//! Both halves use overlapped io on the same handle, synchronous io would serialize a read and a write of the pipe.
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+use std::sync::Arc;
+
+/// The parts of the win32 api that std does not wrap.
+#[allow(non_snake_case, clippy::upper_case_acronyms)]
+mod win32 {
+    use std::ffi::c_void;
+
+    /// win32 `BOOL`
+    pub type BOOL = i32;
+
+    /// see `CreateFileW` in the win32 docs
+    pub const FILE_FLAG_OVERLAPPED: u32 = 0x4000_0000;
+    /// The operation was started and completes later.
+    pub const ERROR_IO_PENDING: i32 = 997;
+    /// The other end of the pipe was closed.
+    pub const ERROR_BROKEN_PIPE: i32 = 109;
+
+    /// win32 `OVERLAPPED`, the offset is unused for pipes.
+    #[repr(C)]
+    pub struct OVERLAPPED {
+        /// Status, written by the system.
+        pub internal: usize,
+        /// Transferred bytes, written by the system.
+        pub internal_high: usize,
+        /// Low half of the offset.
+        pub offset: u32,
+        /// High half of the offset.
+        pub offset_high: u32,
+        /// Manual-reset event that is signaled once the operation completed.
+        pub event: *mut c_void,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        /// see `CreateEventW` in the win32 docs
+        pub fn CreateEventW(attributes: *const c_void, manual_reset: BOOL, initial_state: BOOL, name: *const u16) -> *mut c_void;
+        /// see `ReadFile` in the win32 docs
+        pub fn ReadFile(file: *mut c_void, buffer: *mut u8, len: u32, read: *mut u32, overlapped: *mut OVERLAPPED) -> BOOL;
+        /// see `WriteFile` in the win32 docs
+        pub fn WriteFile(file: *mut c_void, buffer: *const u8, len: u32, written: *mut u32, overlapped: *mut OVERLAPPED) -> BOOL;
+        /// see `GetOverlappedResult` in the win32 docs
+        pub fn GetOverlappedResult(file: *mut c_void, overlapped: *const OVERLAPPED, transferred: *mut u32, wait: BOOL) -> BOOL;
+    }
+}
+
+///
+/// Connects to windows named pipes so they can be used as the transport of a stream.
+///
+/// Use the returned halves with `new_unpooled` or any other constructor that takes a `Read` and a `Write`.
+/// A blocked read of the background reader does not block the background writer, both halves may be used at once.
+///
+/// # Resource Leaks
+/// Like with a `TcpStream` the background reader stays blocked until the other end of the pipe is closed.
+///
+#[derive(Debug)]
+pub enum NamedPipeTransport {}
+
+impl NamedPipeTransport {
+    ///
+    /// Connects to the named pipe `name`, either a full path like `\\.\pipe\app` or only the `app` part of it.
+    ///
+    /// # Errors
+    /// if the pipe does not exist or all of its instances are busy, the error of the open is returned as is.
+    ///
+    pub fn connect(name: &str) -> io::Result<(NamedPipeReader, NamedPipeWriter)> {
+        let path = if name.starts_with(r"\\") {
+            name.to_string()
+        } else {
+            format!(r"\\.\pipe\{name}")
+        };
+
+        let pipe = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(win32::FILE_FLAG_OVERLAPPED)
+            .open(path)?;
+
+        Self::from_handle(pipe.into())
+    }
+
+    ///
+    /// Splits an already connected pipe, for example the server end created by `CreateNamedPipeW`.
+    /// The handle must have been opened with `FILE_FLAG_OVERLAPPED`, otherwise reads block writes.
+    ///
+    /// # Errors
+    /// if the events for the overlapped io could not be created
+    ///
+    pub fn from_handle(handle: OwnedHandle) -> io::Result<(NamedPipeReader, NamedPipeWriter)> {
+        let pipe = Arc::new(File::from(handle));
+        let reader = NamedPipeReader {
+            io: Overlapped::new(Arc::clone(&pipe))?,
+        };
+        let writer = NamedPipeWriter {
+            io: Overlapped::new(pipe)?,
+        };
+
+        Ok((reader, writer))
+    }
+}
+
+/// Reading half of a named pipe, see `NamedPipeTransport`.
+#[derive(Debug)]
+pub struct NamedPipeReader {
+    /// Reads are made here.
+    io: Overlapped,
+}
+
+impl Read for NamedPipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+        let result = self.io.run(|file, overlapped| {
+            // SAFETY: the buffer and overlapped outlive the operation because `run` waits for it to complete.
+            unsafe { win32::ReadFile(file, buf.as_mut_ptr(), len, std::ptr::null_mut(), overlapped) }
+        });
+
+        match result {
+            Err(err) if err.raw_os_error() == Some(win32::ERROR_BROKEN_PIPE) => Ok(0),
+            other => other,
+        }
+    }
+}
+
+/// Writing half of a named pipe, see `NamedPipeTransport`.
+#[derive(Debug)]
+pub struct NamedPipeWriter {
+    /// Writes are made here.
+    io: Overlapped,
+}
+
+impl Write for NamedPipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+        self.io.run(|file, overlapped| {
+            // SAFETY: the buffer and overlapped outlive the operation because `run` waits for it to complete.
+            unsafe { win32::WriteFile(file, buf.as_ptr(), len, std::ptr::null_mut(), overlapped) }
+        })
+    }
+
+    /// Does nothing, a completed write is in the pipe already.
+    /// `FlushFileBuffers` would wait until the other end read everything, which the stream flushes after every write.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The pipe and the event one half waits on for its operations to complete.
+#[derive(Debug)]
+struct Overlapped {
+    /// The pipe, shared by both halves.
+    pipe: Arc<File>,
+    /// Manual-reset event, closed on drop.
+    event: OwnedHandle,
+}
+
+impl Overlapped {
+    /// Creates the event of a half.
+    fn new(pipe: Arc<File>) -> io::Result<Self> {
+        // SAFETY: all pointers are null, which asks for an unnamed event with default security.
+        let event = unsafe { win32::CreateEventW(std::ptr::null(), 1, 0, std::ptr::null()) };
+        if event.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the handle was just created and is owned by nothing else.
+        let event = unsafe { OwnedHandle::from_raw_handle(event) };
+        Ok(Self { pipe, event })
+    }
+
+    /// Starts an operation with `start` and waits for it to complete, returns the amount of transferred bytes.
+    fn run(&self, start: impl FnOnce(*mut c_void, *mut win32::OVERLAPPED) -> win32::BOOL) -> io::Result<usize> {
+        let file = self.pipe.as_raw_handle();
+        let mut overlapped = win32::OVERLAPPED {
+            internal: 0,
+            internal_high: 0,
+            offset: 0,
+            offset_high: 0,
+            event: self.event.as_raw_handle(),
+        };
+
+        if start(file, std::ptr::addr_of_mut!(overlapped)) == 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(win32::ERROR_IO_PENDING) {
+                return Err(err);
+            }
+        }
+
+        let mut transferred = 0u32;
+        // SAFETY: the operation was started with this overlapped, waiting keeps it alive until the operation completed.
+        if unsafe { win32::GetOverlappedResult(file, &overlapped, &mut transferred, 1) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(transferred as usize)
+    }
+}
@@ -0,0 +1,116 @@
+//! Metrics hooks of a stream.
+use crate::sync::Mutex;
+use crate::unwrap_poison;
+use std::fmt::{Debug, Formatter};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::sync::Arc;
+use std::time::Duration;
+
+///
+/// Receives the metrics of a stream as they happen, see `RustTlsDuplexStream::set_observer`.
+///
+/// Meant to feed an exporter such as Prometheus or statsd, so every method should return quickly.
+/// Calls come from the threads that read and write as well as from the background writer, never while
+/// the connection mutex is held. Every method does nothing by default.
+///
+pub trait StreamObserver: Send + Sync {
+    /// rust-tls handed `count` bytes of plaintext to a read, peeked bytes are reported once they were peeked.
+    fn on_bytes_read(&self, _count: usize) {}
+
+    /// rust-tls accepted `count` bytes of plaintext from a write.
+    fn on_bytes_written(&self, _count: usize) {}
+
+    /// A write waited this long for the write queue to drain to its low watermark.
+    fn on_backpressure(&self, _waited: Duration) {}
+
+    /// The write queue holds `depth` records now, reported after records were queued and after the background writer took one.
+    fn on_queue_depth_change(&self, _depth: usize) {}
+}
+
+/// Slot for an optional observer that costs a single atomic load while none is set.
+#[derive(Default)]
+pub struct ObserverSlot {
+    /// The observer, cleared once it panicked.
+    observer: Mutex<Option<Arc<dyn StreamObserver>>>,
+    /// Set while `observer` is set.
+    set: AtomicBool,
+}
+
+impl Debug for ObserverSlot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObserverSlot").field("set", &self.is_set()).finish()
+    }
+}
+
+impl ObserverSlot {
+    /// Replaces the observer, None removes it.
+    pub fn set(&self, observer: Option<Arc<dyn StreamObserver>>) {
+        if let Ok(mut guard) = unwrap_poison(self.observer.lock()) {
+            self.set.store(observer.is_some(), Release);
+            *guard = observer;
+        }
+    }
+
+    /// Returns true if an observer is set, measurements only it needs can be skipped otherwise.
+    pub fn is_set(&self) -> bool {
+        self.set.load(Acquire)
+    }
+
+    /// Reports plaintext that was read, nothing is reported for 0 bytes.
+    pub fn bytes_read(&self, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        self.call(|observer| observer.on_bytes_read(count));
+    }
+
+    /// Reports plaintext that was written, nothing is reported for 0 bytes.
+    pub fn bytes_written(&self, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        self.call(|observer| observer.on_bytes_written(count));
+    }
+
+    /// Reports how long a write waited for room.
+    pub fn backpressure(&self, waited: Duration) {
+        self.call(|observer| observer.on_backpressure(waited));
+    }
+
+    /// Reports the depth of the write queue.
+    pub fn queue_depth(&self, depth: usize) {
+        self.call(|observer| observer.on_queue_depth_change(depth));
+    }
+
+    /// Invokes the observer if one is set, without holding any lock. An observer that panics is cleared.
+    fn call(&self, metric: impl FnOnce(&dyn StreamObserver)) {
+        if !self.is_set() {
+            return;
+        }
+
+        let Ok(guard) = unwrap_poison(self.observer.lock()) else {
+            return;
+        };
+
+        let Some(observer) = guard.as_ref().map(Arc::clone) else {
+            return;
+        };
+        drop(guard);
+
+        if panic::catch_unwind(AssertUnwindSafe(|| metric(observer.as_ref()))).is_err() {
+            warn!("stream observer panicked, it was removed");
+            if let Ok(mut guard) = unwrap_poison(self.observer.lock()) {
+                //It may have been replaced while it ran.
+                if guard.as_ref().is_some_and(|current| Arc::ptr_eq(current, &observer)) {
+                    self.set.store(false, Release);
+                    *guard = None;
+                }
+            }
+        }
+    }
+}
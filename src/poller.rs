@@ -0,0 +1,135 @@
+//! Notifications for event loops built on the polling crate.
+use crate::queue::{Element, Queue};
+use crate::sync::Mutex;
+use crate::unwrap_poison;
+use polling::{Event, PollMode, Poller};
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::{Arc, Weak};
+use std::task::{Wake, Waker};
+
+///
+/// Collects the events of all streams that are attached to a poller.
+///
+/// Posting an event wakes `Poller::wait` with `Poller::notify`, the woken thread takes the events with `drain_into`.
+/// Events of the same key are merged until they are taken.
+///
+#[derive(Debug)]
+pub struct PollerSink {
+    /// The poller that is notified.
+    poller: Arc<Poller>,
+    /// Events that were posted and not taken yet.
+    events: Mutex<Vec<Event>>,
+}
+
+impl PollerSink {
+    /// Constructor for a sink that notifies `poller`.
+    #[must_use]
+    pub const fn new(poller: Arc<Poller>) -> Self {
+        Self {
+            poller,
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the poller that is notified.
+    #[must_use]
+    pub const fn poller(&self) -> &Arc<Poller> {
+        &self.poller
+    }
+
+    /// Moves all events that were posted since the last call into `events`.
+    /// Call this whenever `Poller::wait` returned, the notification does not say which stream posted.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn drain_into(&self, events: &mut Vec<Event>) -> io::Result<()> {
+        let mut guard = unwrap_poison(self.events.lock())?;
+        events.append(&mut guard);
+        drop(guard);
+        Ok(())
+    }
+
+    /// Records the event and notifies the poller.
+    fn post(&self, event: Event) {
+        let Ok(mut guard) = unwrap_poison(self.events.lock()) else {
+            return;
+        };
+
+        if let Some(pending) = guard.iter_mut().find(|pending| pending.key == event.key) {
+            pending.readable |= event.readable;
+            pending.writable |= event.writable;
+        } else {
+            guard.push(event);
+        }
+        drop(guard);
+
+        //Only fails if the os refuses to wake the poller, the event stays pending for the next wait.
+        _ = self.poller.notify();
+    }
+}
+
+/// Registers a waker on a queue without waking it right away.
+type Rearm<T> = fn(&Queue<T>, Option<Waker>) -> io::Result<()>;
+
+/// Posts an event once the queue it is registered with is woken.
+struct PostWaker<T> {
+    /// Where the event is posted.
+    sink: Arc<PollerSink>,
+    /// The event that is posted.
+    event: Event,
+    /// Cleared once the stream is attached again or detached, the waker then does nothing.
+    attached: Arc<AtomicBool>,
+    /// With `PollMode::Level` the queue and the fn that re-arms the waker on it after every wake.
+    rearm: Option<(Weak<Queue<T>>, Rearm<T>)>,
+}
+
+impl<T: Element + Send + 'static> Wake for PostWaker<T> {
+    fn wake(self: Arc<Self>) {
+        if !self.attached.load(SeqCst) {
+            return;
+        }
+
+        self.sink.post(self.event);
+        if let Some((queue, rearm)) = &self.rearm {
+            if let Some(queue) = queue.upgrade() {
+                _ = rearm(&queue, Some(Waker::from(Arc::clone(&self))));
+            }
+        }
+    }
+}
+
+/// Returns true for `PollMode::Level` and false for `PollMode::Oneshot`.
+/// # Errors
+/// `Unsupported` for every other mode, the queues only know when readiness starts and not when it ends.
+pub fn is_level(mode: PollMode) -> io::Result<bool> {
+    match mode {
+        PollMode::Oneshot => Ok(false),
+        PollMode::Level => Ok(true),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only PollMode::Oneshot and PollMode::Level are supported",
+        )),
+    }
+}
+
+/// Registers a waker that posts `event` on `queue`, see `RustTlsDuplexStream::attach_poller`.
+/// `register` wakes it right away if the queue is ready, `rearm` registers it again after every wake if `level` is set.
+pub fn attach<T: Element + Send + 'static>(
+    queue: &Arc<Queue<T>>,
+    sink: &Arc<PollerSink>,
+    event: Event,
+    level: bool,
+    attached: &Arc<AtomicBool>,
+    register: fn(&Queue<T>, Waker) -> io::Result<()>,
+    rearm: Rearm<T>,
+) -> io::Result<()> {
+    let rearm = level.then(|| (Arc::downgrade(queue), rearm));
+    let waker = Arc::new(PostWaker {
+        sink: Arc::clone(sink),
+        event,
+        attached: Arc::clone(attached),
+        rearm,
+    });
+    register(queue, Waker::from(waker))
+}
@@ -1,106 +1,689 @@
 //! Poor man's channel with quirks.
-use crate::unwrap_poison;
+use crate::sync::{Condvar, Mutex, MutexGuard};
+use crate::error::queue_dead;
+#[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+use crate::readiness::Readiness;
+#[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+use crate::readiness::Room;
+use crate::waker::WakerSlot;
+use crate::{remaining, sync, unwrap_poison};
 use std::collections::VecDeque;
 use std::io;
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering::SeqCst;
-use std::sync::{Condvar, Mutex, MutexGuard};
-use std::time::Duration;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize};
+use std::ptr;
+use std::sync::Arc;
+#[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+use std::sync::OnceLock;
+use std::task::Waker;
+use std::time::{Duration, Instant};
 
-/// Max size of elements in the channel
+/// Default max size of elements in the channel
 const HIGH_WATERMARK: usize = 8096;
 
-/// Max size of elements in the channel until we only allow rust tls control messages to be queued and not actual user data.
+/// Default max size of elements in the channel until we only allow rust tls control messages to be queued and not actual user data.
 const LOW_WATERMARK: usize = 4096;
 
+/// How often a wait that is not notified about all changes it waits for re-checks its condition.
+/// This is the case for cancellation tokens and the memory shared with other queues.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Cancellation token that is never set, passed by the non-cancelable fns.
+static NEVER_CANCELED: AtomicBool = AtomicBool::new(false);
+
+/// Something that can be queued, its size counts towards the byte watermark and the memory limit.
+pub trait Element {
+    /// Amount of bytes this element accounts for.
+    fn byte_len(&self) -> usize;
+}
+
+impl Element for Vec<u8> {
+    fn byte_len(&self) -> usize {
+        self.len()
+    }
+}
+
+/// What a push does if the queue is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until there is room again.
+    #[default]
+    Block,
+    /// Discard the element that is pushed.
+    DropNewest,
+    /// Discard the oldest elements until there is room.
+    DropOldest,
+}
+
+/// Tuning knobs for a `Queue`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// Id of the stream the queue belongs to, only used to tell the queues of different streams apart in traces.
+    pub id: u64,
+    /// Max amount of bytes in the channel. Pushing blocks while this is exceeded. None means unbounded.
+    pub high_watermark_bytes: Option<usize>,
+    /// Max amount of bytes in all queues that share the memory counter.
+    /// `push`, `push_cancelable` and `flush_low` block while this is exceeded, the other pushes ignore it
+    /// so rust-tls control messages can always be queued. None means unbounded.
+    pub total_memory_limit: Option<usize>,
+    /// What pushing onto a full queue does. With the drop policies no push ever blocks and the memory limit is ignored.
+    pub overflow_policy: OverflowPolicy,
+}
+
 ///Poor man's channel with quirks.
-#[derive(Debug, Default)]
-pub struct Queue {
+#[derive(Debug)]
+pub struct Queue<T = Vec<u8>> {
     /// Flag to indicate that the thread or connection has died and all surrounding it should shut down.
     dead: AtomicBool,
+    /// Total amount of bytes in all elements of the buffer.
+    total_bytes: AtomicUsize,
+    /// Total amount of bytes in this queue and all queues it shares its memory with.
+    memory: Arc<AtomicUsize>,
+    /// Total amount of bytes ever pushed onto this queue.
+    received: AtomicU64,
+    /// Total amount of bytes discarded because the queue was full.
+    dropped: AtomicU64,
+    /// Incremented by `wake_all`, waiters that sampled an older value return early. Only modified while holding the buffer mutex.
+    epoch: AtomicU64,
+    /// Max size of elements in the channel. Only modified while holding the buffer mutex.
+    high_watermark: AtomicUsize,
+    /// Max size of elements in the channel for user data. Only modified while holding the buffer mutex.
+    low_watermark: AtomicUsize,
+    /// Configuration of this queue.
+    config: QueueConfig,
     /// the buffer for data an actual ring buffer would be better (and much faster) but would possibly require unsafe code.
-    buffer: Mutex<VecDeque<Vec<u8>>>,
-    /// Condition for when buffer changes.
-    cond: Condvar,
+    buffer: Mutex<VecDeque<T>>,
+    /// Condition for when an element was pushed, waited on by poppers.
+    read_cond: Condvar,
+    /// Condition for when an element was removed or the watermarks changed, waited on by pushers and flushers.
+    write_cond: Condvar,
+    /// Set while the queue holds elements or is dead, created on first use.
+    #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+    readiness: OnceLock<Readiness>,
+    /// Set once the reader saw the end of the stream, the readiness then stays set. Only modified while holding the buffer mutex.
+    #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+    ended: AtomicBool,
+    /// Set while the queue holds at most the low watermark of elements or is dead, created on first use.
+    #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+    room: OnceLock<Room>,
+    /// Woken once an element is pushed or the queue died.
+    push_waker: WakerSlot,
+    /// Woken once a pop leaves at most the low watermark of elements or the queue died.
+    room_waker: WakerSlot,
+    /// Set once a push found the queue empty, cleared by `take_refilled`.
+    refilled: AtomicBool,
+    /// Set once `try_push` or `try_flush_low` found the queue full, cleared by `take_room_regained`.
+    refused: AtomicBool,
 }
 
-impl Queue {
-    
-    /// Kills the queue and terminates the connection.
-    pub fn kill(&self) {
-        self.dead.store(true, SeqCst);
-        let guard = self.buffer.lock();
-        self.cond.notify_all();
+impl<T: Element> Default for Queue<T> {
+    fn default() -> Self {
+        Self::with_config(QueueConfig::default())
+    }
+}
+
+impl<T: Element> Queue<T> {
+
+    /// Constructor for a queue with the given configuration.
+    pub fn with_config(config: QueueConfig) -> Self {
+        Self::with_memory(config, Arc::default())
+    }
+
+    /// Constructor for a queue whose memory limit is shared with all other queues using the same counter.
+    pub const fn with_memory(config: QueueConfig, memory: Arc<AtomicUsize>) -> Self {
+        Self {
+            dead: AtomicBool::new(false),
+            total_bytes: AtomicUsize::new(0),
+            memory,
+            received: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            epoch: AtomicU64::new(0),
+            high_watermark: AtomicUsize::new(HIGH_WATERMARK),
+            low_watermark: AtomicUsize::new(LOW_WATERMARK),
+            config,
+            buffer: Mutex::new(VecDeque::new()),
+            read_cond: Condvar::new(),
+            write_cond: Condvar::new(),
+            #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+            readiness: OnceLock::new(),
+            #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+            ended: AtomicBool::new(false),
+            #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+            room: OnceLock::new(),
+            push_waker: WakerSlot::new(),
+            room_waker: WakerSlot::new(),
+            refilled: AtomicBool::new(false),
+            refused: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the total amount of bytes currently buffered in the queue.
+    pub fn byte_len(&self) -> usize {
+        // total_bytes is only modified while holding the buffer mutex, which orders it.
+        // Outside the mutex this is a statistic that may be stale, so nothing stronger is needed.
+        self.total_bytes.load(Relaxed)
+    }
+
+    /// Returns the total amount of bytes buffered in this queue and all queues sharing its memory counter.
+    pub fn memory_usage(&self) -> usize {
+        // Same as total_bytes, but modified under different mutexes so it's only ever a statistic.
+        self.memory.load(Relaxed)
+    }
+
+    /// Returns true if the shared memory limit is exceeded.
+    fn memory_exceeded(&self) -> bool {
+        self.config
+            .total_memory_limit
+            .is_some_and(|limit| self.memory_usage() > limit)
+    }
+
+    /// Sets the max amount of elements in the queue, pushing blocks while it is exceeded.
+    /// # Errors
+    /// `InvalidInput` if `n` is not above the low watermark
+    #[cfg_attr(not(test), allow(dead_code))] //The stream sets both at once.
+    pub fn set_high_watermark(&self, n: usize) -> io::Result<()> {
+        let guard = unwrap_poison(self.buffer.lock())?;
+        self.store_watermarks(self.low_watermark.load(Relaxed), n)?;
+        drop(guard);
+        self.wake_due();
+        Ok(())
+    }
+
+    /// Sets the max amount of elements in the queue until only rust-tls control messages are accepted.
+    /// # Errors
+    /// `InvalidInput` if `n` is not below the high watermark
+    #[cfg_attr(not(test), allow(dead_code))] //The stream sets both at once.
+    pub fn set_low_watermark(&self, n: usize) -> io::Result<()> {
+        let guard = unwrap_poison(self.buffer.lock())?;
+        self.store_watermarks(n, self.high_watermark.load(Relaxed))?;
+        #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+        self.room_changed(guard.len());
+        drop(guard);
+        self.wake_due();
+        Ok(())
+    }
+
+    /// Sets both watermarks at once, so they can be moved past each other.
+    /// # Errors
+    /// `InvalidInput` if `low` is not below `high`
+    pub fn set_watermarks(&self, low: usize, high: usize) -> io::Result<()> {
+        let guard = unwrap_poison(self.buffer.lock())?;
+        self.store_watermarks(low, high)?;
+        #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+        self.room_changed(guard.len());
         drop(guard);
+        self.wake_due();
+        Ok(())
+    }
+
+    /// Validates and stores the watermarks and wakes all waiters so they re-check. Caller must hold the buffer mutex.
+    fn store_watermarks(&self, low: usize, high: usize) -> io::Result<()> {
+        if low >= high {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "low watermark must be below the high watermark",
+            ));
+        }
+
+        self.low_watermark.store(low, Relaxed);
+        self.high_watermark.store(high, Relaxed);
+        self.room_waker.mark(); //The low watermark may have been raised.
+        self.write_cond.notify_all();
+        Ok(())
     }
 
-    /// Wait until the queue is dead, or there are less than n elements in the queue.
+    /// Wakes the wakers that became due. Must not be called while holding the buffer mutex,
+    /// a waker may poll the stream right away.
+    fn wake_due(&self) {
+        self.push_waker.wake_if_due();
+        self.room_waker.wake_if_due();
+    }
+
+    /// Registers the waker that is woken once an element is pushed or the queue died.
+    /// It is woken right away if the queue already holds elements or is dead. Only the most recent waker is woken, once.
+    /// # Errors
+    /// in case of poisoned mutex
+    pub fn register_push_waker(&self, waker: Waker) -> io::Result<()> {
+        self.push_waker.register(waker)?;
+        let guard = unwrap_poison(self.buffer.lock())?;
+        if !guard.is_empty() || self.is_dead() {
+            self.push_waker.mark();
+        }
+        drop(guard);
+        self.wake_due();
+        Ok(())
+    }
+
+    /// Registers the waker that is woken once a pop leaves at most the low watermark of elements or the queue died.
+    /// It is woken right away if that is already the case. Only the most recent waker is woken, once.
+    /// # Errors
+    /// in case of poisoned mutex
+    pub fn register_room_waker(&self, waker: Waker) -> io::Result<()> {
+        self.room_waker.register(waker)?;
+        let guard = unwrap_poison(self.buffer.lock())?;
+        if guard.len() <= self.low_watermark.load(Relaxed) || self.is_dead() {
+            self.room_waker.mark();
+        }
+        drop(guard);
+        self.wake_due();
+        Ok(())
+    }
+
+    /// Registers the push waker without waking it if the queue already holds elements, None removes it.
+    /// Used to re-arm a waker from within its own `wake`.
+    /// # Errors
+    /// in case of poisoned mutex
+    #[cfg(feature = "polling")]
+    pub fn replace_push_waker(&self, waker: Option<Waker>) -> io::Result<()> {
+        self.push_waker.replace(waker)
+    }
+
+    /// Registers the room waker without waking it if the queue already has room, None removes it.
+    /// Used to re-arm a waker from within its own `wake`.
+    /// # Errors
+    /// in case of poisoned mutex
+    #[cfg(feature = "polling")]
+    pub fn replace_room_waker(&self, waker: Option<Waker>) -> io::Result<()> {
+        self.room_waker.replace(waker)
+    }
+
+    /// Accounts for an element that was added to the buffer, `count` elements are queued with it. Caller must hold the buffer mutex.
+    fn added(&self, len: usize, count: usize) {
+        trace!(bytes = len, "push");
+        self.total_bytes.fetch_add(len, Relaxed);
+        self.memory.fetch_add(len, Relaxed);
+        self.received.fetch_add(len as u64, Relaxed);
+        self.push_waker.mark();
+        if count == 1 {
+            self.refilled.store(true, Release);
+        }
+        #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+        self.room_changed(count);
+        #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+        if let Some(readiness) = self.readiness.get() {
+            readiness.set();
+        }
+    }
+
+    /// Accounts for an element that was removed from the buffer, `left` elements remain. Caller must hold the buffer mutex.
+    fn removed(&self, len: usize, left: usize) {
+        trace!(bytes = len, "pop");
+        self.total_bytes.fetch_sub(len, Relaxed);
+        self.memory.fetch_sub(len, Relaxed);
+        if left <= self.low_watermark.load(Relaxed) {
+            self.room_waker.mark();
+        }
+        #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+        self.room_changed(left);
+        #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+        if left == 0 && !self.is_dead() && !self.ended.load(Relaxed) {
+            if let Some(readiness) = self.readiness.get() {
+                readiness.clear();
+            }
+        }
+    }
+
+    /// Returns the readiness signal of this queue, it is set while the queue holds elements, is dead or ended.
+    /// # Errors
+    /// if the signal could not be created, in case of poisoned mutex
+    #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+    pub fn readiness(&self) -> io::Result<&Readiness> {
+        if let Some(readiness) = self.readiness.get() {
+            return Ok(readiness);
+        }
+
+        //Created while holding the buffer mutex so no push or pop is missed.
+        let guard = unwrap_poison(self.buffer.lock())?;
+        let created = Readiness::new()?;
+        let readiness = self.readiness.get_or_init(|| created);
+        if !guard.is_empty() || self.is_dead() || self.ended.load(Relaxed) {
+            readiness.set();
+        }
+        drop(guard);
+        Ok(readiness)
+    }
+
+    /// Sets the room signal if at most the low watermark of elements is queued or the queue is dead, clears it otherwise.
+    /// Caller must hold the buffer mutex.
+    #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+    fn room_changed(&self, count: usize) {
+        if let Some(room) = self.room.get() {
+            if count <= self.low_watermark.load(Relaxed) || self.is_dead() {
+                room.set();
+            } else {
+                room.clear();
+            }
+        }
+    }
+
+    /// Returns the room signal of this queue, it is set while the queue holds at most the low watermark of elements or is dead.
+    /// # Errors
+    /// if the signal could not be created, in case of poisoned mutex
+    #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+    pub fn room(&self) -> io::Result<&Room> {
+        if let Some(room) = self.room.get() {
+            return Ok(room);
+        }
+
+        //Created while holding the buffer mutex so no push or pop is missed.
+        let guard = unwrap_poison(self.buffer.lock())?;
+        let created = Room::new()?;
+        let room = self.room.get_or_init(|| created);
+        self.room_changed(guard.len());
+        drop(guard);
+        Ok(room)
+    }
+
+    /// Accounts for bytes that were discarded because the queue was full. Caller must hold the buffer mutex.
+    fn discarded(&self, len: usize) {
+        trace!(bytes = len, "drop");
+        self.dropped.fetch_add(len as u64, Relaxed);
+    }
+
+    /// Returns the total amount of bytes that were discarded because the queue was full.
+    pub fn dropped_bytes(&self) -> u64 {
+        // Only modified while holding the buffer mutex, outside of it this is a statistic.
+        self.dropped.load(Relaxed)
+    }
+
+    /// Returns true if the queue holds more than the high watermark allows. Caller must hold the buffer mutex.
+    fn is_full(&self, buffer: &VecDeque<T>) -> bool {
+        buffer.len() > self.high_watermark.load(Relaxed)
+            || self
+                .config
+                .high_watermark_bytes
+                .is_some_and(|bytes| self.byte_len() > bytes)
+    }
+
+    /// Returns the amount of elements in the queue.
+    pub fn len(&self) -> io::Result<usize> {
+        Ok(unwrap_poison(self.buffer.lock())?.len())
+    }
+
+    /// Returns true if no element is in the queue.
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(unwrap_poison(self.buffer.lock())?.is_empty())
+    }
+
+    /// Removes and returns all elements in the queue, even if it is dead.
+    pub fn drain(&self) -> io::Result<Vec<T>> {
+        let mut guard = unwrap_poison(self.buffer.lock())?;
+        let drained: Vec<T> = guard.drain(..).collect();
+        for element in &drained {
+            self.removed(element.byte_len(), 0);
+        }
+        self.write_cond.notify_all();
+        drop(guard);
+        self.wake_due();
+        Ok(drained)
+    }
+
+    /// Removes the last element if `discard` returns true for it, even if the queue is dead.
+    /// Returns true if it was removed.
+    pub fn discard_last_if(&self, discard: impl FnOnce(&T) -> bool) -> io::Result<bool> {
+        let mut guard = unwrap_poison(self.buffer.lock())?;
+        if !guard.back().is_some_and(discard) {
+            return Ok(false);
+        }
+
+        if let Some(last) = guard.pop_back() {
+            self.removed(last.byte_len(), guard.len());
+        }
+        self.write_cond.notify_all();
+        drop(guard);
+        self.wake_due();
+        Ok(true)
+    }
+
+    /// Returns true if a push found the queue empty since the last call, so pushes in between are reported once.
+    pub fn take_refilled(&self) -> bool {
+        self.refilled.swap(false, AcqRel)
+    }
+
+    /// Returns true if `try_push` or `try_flush_low` found the queue full since the last call that returned true
+    /// and the queue is at or below the low watermark again, so every refusal is reported once.
+    pub fn take_room_regained(&self) -> bool {
+        if !self.refused.load(Acquire) {
+            return false;
+        }
+
+        let Ok(guard) = unwrap_poison(self.buffer.lock()) else {
+            return false;
+        };
+
+        if guard.len() > self.low_watermark.load(Relaxed) || self.memory_exceeded() {
+            return false;
+        }
+
+        drop(guard);
+        self.refused.swap(false, AcqRel)
+    }
+
+    /// Returns true if the queue was killed.
+    pub fn is_dead(&self) -> bool {
+        // Pairs with the swap in kill. Every waiter checks this while holding the buffer mutex and
+        // kill takes that mutex before notifying, so a waiter can never miss the wakeup.
+        self.dead.load(Acquire)
+    }
+
+    /// Returns the current wake epoch, sample it before checking the state that `wake_all` announces changes of.
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Acquire)
+    }
+
+    /// Keeps the readiness set from now on, like for a dead queue. Called once the reader saw the end of the stream,
+    /// pollers would otherwise wait for data that never comes.
+    #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+    pub fn end(&self) {
+        let guard = unwrap_poison(self.buffer.lock());
+        self.ended.store(true, Relaxed);
+        if let Some(readiness) = self.readiness.get() {
+            readiness.set();
+        }
+        drop(guard);
+    }
+
+    /// Wakes all waiters that take an epoch so they re-check state that is not part of this queue.
+    pub fn wake_all(&self) {
+        let guard = unwrap_poison(self.buffer.lock());
+        // Release pairs with the Acquire in epoch, state changed before this call is visible to woken waiters.
+        self.epoch.fetch_add(1, Release);
+        self.read_cond.notify_all();
+        self.write_cond.notify_all();
+        drop(guard);
+    }
+
+    /// Kills the queue and terminates the connection. The reason is traced if this is the first kill.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub fn kill(&self, reason: &'static str) {
+        // AcqRel so only the first kill logs and everything before the kill is visible to is_dead callers.
+        if !self.dead.swap(true, AcqRel) {
+            warn!(id = self.config.id, reason, "queue killed");
+        }
+        let guard = unwrap_poison(self.buffer.lock());
+        #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+        if let Some(readiness) = self.readiness.get() {
+            readiness.set();
+        }
+        #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+        if let Some(room) = self.room.get() {
+            room.set();
+        }
+        self.push_waker.mark();
+        self.room_waker.mark();
+        self.read_cond.notify_all();
+        self.write_cond.notify_all();
+        drop(guard);
+        self.wake_due();
+    }
+
+    /// Brings a killed queue back to life, the statistics and watermarks are kept.
+    /// Only call this once nothing that saw the queue die uses it anymore, elements left in it are kept as well.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn revive(&self) -> io::Result<()> {
+        let guard = unwrap_poison(self.buffer.lock())?;
+        self.dead.store(false, Release);
+        self.refilled.store(false, Release);
+        self.refused.store(false, Release);
+        #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+        self.ended.store(false, Relaxed);
+        #[cfg(any(all(unix, feature = "unix"), all(windows, feature = "windows")))]
+        if guard.is_empty() {
+            if let Some(readiness) = self.readiness.get() {
+                readiness.clear();
+            }
+        }
+        #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+        self.room_changed(guard.len());
+        drop(guard);
+        trace!(id = self.config.id, "queue revived");
+        Ok(())
+    }
+
+    /// Waits on `cond` until notified or the deadline passed.
+    /// If `poll` is set it also wakes up regularly so conditions we are not notified about are re-checked.
+    fn wait<'a>(
+        cond: &Condvar,
+        guard: MutexGuard<'a, VecDeque<T>>,
+        deadline: Option<Instant>,
+        poll: bool,
+    ) -> io::Result<MutexGuard<'a, VecDeque<T>>> {
+        let mut duration = match deadline {
+            Some(deadline) => Some(remaining(deadline)?),
+            None => None,
+        };
+        if poll {
+            duration = Some(duration.map_or(POLL_INTERVAL, |duration| duration.min(POLL_INTERVAL)));
+        }
+
+        match duration {
+            Some(duration) => Ok(unwrap_poison(sync::wait_timeout(cond, guard, duration))?.0),
+            None => unwrap_poison(sync::wait(cond, guard)),
+        }
+    }
+
+    /// Returns true if the token is not the never canceled sentinel.
+    fn is_cancelable(cancel: &AtomicBool) -> bool {
+        !ptr::eq(cancel, ptr::addr_of!(NEVER_CANCELED))
+    }
+
+    /// Wait until the queue is dead, or there are less than `count()` elements
+    /// and (if given) less than `bytes` bytes in the queue
+    /// and (if `limit_memory` is set) the shared memory limit is not exceeded.
+    /// Gives up with `Interrupted` once the cancellation token is set or (if given) `wake_all` was called since `epoch` was sampled.
     fn flush_count(
         &self,
-        count: usize,
-        timeout: Option<Duration>,
-    ) -> io::Result<MutexGuard<'_, VecDeque<Vec<u8>>>> {
+        count: impl Fn() -> usize,
+        bytes: Option<usize>,
+        limit_memory: bool,
+        deadline: Option<Instant>,
+        cancel: &AtomicBool,
+        epoch: Option<u64>,
+    ) -> io::Result<MutexGuard<'_, VecDeque<T>>> {
+        let limit_memory = limit_memory && self.config.total_memory_limit.is_some();
+        let poll = limit_memory || Self::is_cancelable(cancel);
         let mut guard = unwrap_poison(self.buffer.lock())?;
+        #[cfg(feature = "tracing")]
+        let mut waiting_since = None;
+
+        while guard.len() > count()
+            || bytes.is_some_and(|bytes| self.byte_len() > bytes)
+            || (limit_memory && self.memory_exceeded())
+        {
+            if self.is_dead() {
+                return Err(queue_dead());
+            }
+
+            if cancel.load(Relaxed) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "canceled"));
+            }
 
-        while guard.len() > count {
-            if self.dead.load(SeqCst) {
-                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "dead"));
+            if epoch.is_some_and(|epoch| epoch != self.epoch()) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "woken"));
             }
 
-            if let Some(dur) = timeout.as_ref().copied() {
-                let (grd, timeout) = unwrap_poison(self.cond.wait_timeout(guard, dur))?;
-                if timeout.timed_out() {
-                    return Err(io::Error::from(io::ErrorKind::TimedOut));
-                }
-                guard = grd;
-                continue;
+            #[cfg(feature = "tracing")]
+            if waiting_since.is_none() {
+                trace!(id = self.config.id, elements = guard.len(), "waiting for room");
+                waiting_since = Some(Instant::now());
             }
 
-            guard = unwrap_poison(self.cond.wait(guard))?;
+            guard = Self::wait(&self.write_cond, guard, deadline, poll)?;
         }
 
-        if self.dead.load(SeqCst) {
-            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "dead"));
+        #[cfg(feature = "tracing")]
+        if let Some(since) = waiting_since {
+            trace!(id = self.config.id, waited = ?since.elapsed(), "room available");
+        }
+
+        if self.is_dead() {
+            return Err(queue_dead());
         }
 
         Ok(guard)
     }
 
-    /// Flush until the low watermark is reached.
-    pub fn flush_low(&self, timeout: Option<Duration>) -> io::Result<()> {
-        drop(self.flush_count(LOW_WATERMARK, timeout)?);
+    /// Flush until the low watermark is reached, gives up with `TimedOut` once the deadline has passed.
+    /// Returns false if `wake_all` was called since `epoch` was sampled before the low watermark was reached.
+    pub fn flush_low(&self, deadline: Option<Instant>, epoch: u64) -> io::Result<bool> {
+        match self.flush_count(|| self.low_watermark.load(Relaxed), None, true, deadline, &NEVER_CANCELED, Some(epoch)) {
+            Ok(guard) => {
+                drop(guard);
+                Ok(true)
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Blocks until the queue is empty, gives up with `TimedOut` once the timeout elapsed
+    /// and with `BrokenPipe` if the queue is dead. A timeout that is too large blocks forever.
+    pub fn wait_until_empty(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        drop(self.flush_count(|| 0, None, false, deadline, &NEVER_CANCELED, None)?);
         Ok(())
     }
 
-    /// Flush until zero elements are in the queue.
-    pub fn flush_zero(&self) -> io::Result<()> {
-        drop(self.flush_count(0, None)?);
+    /// Returns the total amount of bytes ever pushed onto this queue.
+    pub fn received(&self) -> u64 {
+        // Only modified while holding the buffer mutex, outside of it this is a statistic.
+        self.received.load(Relaxed)
+    }
+
+    /// Waits until more than `since` bytes were ever pushed onto this queue,
+    /// gives up with `TimedOut` once the deadline has passed and with `BrokenPipe` if the queue died before that.
+    pub fn await_received(&self, since: u64, deadline: Option<Instant>) -> io::Result<()> {
+        let mut guard = unwrap_poison(self.buffer.lock())?;
+        while self.received() <= since {
+            if self.is_dead() {
+                return Err(queue_dead());
+            }
+
+            guard = Self::wait(&self.read_cond, guard, deadline, false)?;
+        }
+
+        drop(guard);
         Ok(())
     }
 
-    /// Wait until at least 1 element can be popped.
-    pub fn await_pop<T>(
+    /// Wait until at least 1 element can be popped or `wake_all` was called since `epoch` was sampled,
+    /// gives up with `TimedOut` once the deadline has passed.
+    /// Wakeups that do not make an element available do not extend the deadline.
+    pub fn await_pop<G>(
         &self,
-        oguard: MutexGuard<'_, T>,
-        duration: Option<Duration>,
+        oguard: MutexGuard<'_, G>,
+        deadline: Option<Instant>,
+        epoch: u64,
     ) -> io::Result<()> {
         let mut guard = unwrap_poison(self.buffer.lock())?;
         drop(oguard);
-        while guard.is_empty() {
-            if self.dead.load(SeqCst) {
-                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "dead"));
-            }
-
-            if let Some(dur) = duration.as_ref().copied() {
-                let (grd, timeout) = unwrap_poison(self.cond.wait_timeout(guard, dur))?;
-                if timeout.timed_out() {
-                    return Err(io::Error::from(io::ErrorKind::TimedOut));
-                }
-                guard = grd;
-                continue;
+        while guard.is_empty() && self.epoch() == epoch {
+            if self.is_dead() {
+                return Err(queue_dead());
             }
 
-            guard = unwrap_poison(self.cond.wait(guard))?;
+            guard = Self::wait(&self.read_cond, guard, deadline, false)?;
         }
 
         drop(guard);
@@ -108,15 +691,18 @@ impl Queue {
     }
 
     /// Try to pop 1 element immediately
-    pub fn try_pop(&self) -> io::Result<Option<Vec<u8>>> {
+    pub fn try_pop(&self) -> io::Result<Option<T>> {
         let mut guard = unwrap_poison(self.buffer.lock())?;
         if let Some(pop) = guard.pop_front() {
-            self.cond.notify_all();
+            self.removed(pop.byte_len(), guard.len());
+            self.write_cond.notify_all();
+            drop(guard);
+            self.wake_due();
             return Ok(Some(pop));
         }
 
-        if self.dead.load(SeqCst) {
-            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "dead"));
+        if self.is_dead() {
+            return Err(queue_dead());
         }
 
         drop(guard);
@@ -124,29 +710,701 @@ impl Queue {
     }
 
     /// Blocks (forever) until 1 element could be popped or the queue is dead.
-    pub fn pop(&self) -> io::Result<Vec<u8>> {
+    pub fn pop(&self) -> io::Result<T> {
+        self.pop_cancelable(&NEVER_CANCELED)
+    }
+
+    /// Blocks until 1 element could be popped, the queue is dead or the cancellation token is set.
+    /// A set token is reported as `Interrupted`.
+    pub fn pop_cancelable(&self, cancel: &AtomicBool) -> io::Result<T> {
         let mut guard = unwrap_poison(self.buffer.lock())?;
 
         loop {
             if let Some(pop) = guard.pop_front() {
-                self.cond.notify_all();
+                self.removed(pop.byte_len(), guard.len());
+                self.write_cond.notify_all();
+                drop(guard);
+                self.wake_due();
                 return Ok(pop);
             }
 
-            if self.dead.load(SeqCst) {
-                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "dead"));
+            if self.is_dead() {
+                return Err(queue_dead());
             }
 
-            guard = unwrap_poison(self.cond.wait(guard))?;
+            if cancel.load(Relaxed) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "canceled"));
+            }
+
+            guard = Self::wait(&self.read_cond, guard, None, Self::is_cancelable(cancel))?;
         }
     }
 
-    /// Push 1 element onto the queue.
-    pub fn push(&self, data: Vec<u8>) -> io::Result<()> {
-        let mut guard = self.flush_count(HIGH_WATERMARK, None)?; //TODO not sure if None would be a better choice here. This may block control messages.
+    /// Blocks until 1 element could be popped, the queue is dead or the deadline has passed.
+    /// Returns None once the deadline has passed.
+    pub fn pop_before(&self, deadline: Instant) -> io::Result<Option<T>> {
+        let mut guard = unwrap_poison(self.buffer.lock())?;
+
+        loop {
+            if let Some(pop) = guard.pop_front() {
+                self.removed(pop.byte_len(), guard.len());
+                self.write_cond.notify_all();
+                drop(guard);
+                self.wake_due();
+                return Ok(Some(pop));
+            }
+
+            if self.is_dead() {
+                return Err(queue_dead());
+            }
+
+            guard = match Self::wait(&self.read_cond, guard, Some(deadline), false) {
+                Ok(guard) => guard,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => return Ok(None),
+                Err(err) => return Err(err),
+            };
+        }
+    }
+
+    /// Returns true if the low watermark or the memory limit is exceeded, so `flush_low` would wait.
+    /// Unlike `try_flush_low` this is not remembered by `take_room_regained`.
+    pub fn over_low(&self) -> io::Result<bool> {
+        let guard = unwrap_poison(self.buffer.lock())?;
+        let over = guard.len() > self.low_watermark.load(Relaxed) || self.memory_exceeded();
+        drop(guard);
+        Ok(over)
+    }
+
+    /// Returns `WouldBlock` instead of waiting if the low watermark is exceeded.
+    pub fn try_flush_low(&self) -> io::Result<()> {
+        let guard = unwrap_poison(self.buffer.lock())?;
+        if self.is_dead() {
+            return Err(queue_dead());
+        }
+
+        if guard.len() > self.low_watermark.load(Relaxed) || self.memory_exceeded() {
+            self.refused.store(true, Release);
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        }
+
+        drop(guard);
+        Ok(())
+    }
+
+    /// Push 1 element onto the queue, blocks forever if the queue is full or the memory limit is exceeded.
+    pub fn push(&self, data: T) -> io::Result<()> {
+        self.push_cancelable(data, &NEVER_CANCELED)
+    }
+
+    /// Push 1 element onto the queue, gives up with `Interrupted` if the cancellation token is set while the queue is full.
+    pub fn push_cancelable(&self, data: T, cancel: &AtomicBool) -> io::Result<()> {
+        self.push_with(data, true, None, cancel)
+    }
+
+    /// Push 1 element onto the queue, gives up with `TimedOut` if the queue is still full once the deadline has passed.
+    /// This ignores the memory limit.
+    pub fn push_before(&self, data: T, deadline: Option<Instant>) -> io::Result<()> {
+        self.push_before_cancelable(data, deadline, &NEVER_CANCELED)
+    }
+
+    /// Like `push_before` but also gives up with `Interrupted` if the cancellation token is set while the queue is full.
+    pub fn push_before_cancelable(&self, data: T, deadline: Option<Instant>, cancel: &AtomicBool) -> io::Result<()> {
+        self.push_with(data, false, deadline, cancel)
+    }
+
+    /// Push 1 element onto the queue once it is not full anymore.
+    fn push_with(
+        &self,
+        data: T,
+        limit_memory: bool,
+        deadline: Option<Instant>,
+        cancel: &AtomicBool,
+    ) -> io::Result<()> {
+        if self.config.overflow_policy != OverflowPolicy::Block {
+            return self.push_or_discard(data, self.config.overflow_policy);
+        }
+
+        let mut guard = self.flush_count(
+            || self.high_watermark.load(Relaxed),
+            self.config.high_watermark_bytes,
+            limit_memory,
+            deadline,
+            cancel,
+            None,
+        )?;
+        self.added(data.byte_len(), guard.len() + 1);
         guard.push_back(data);
-        self.cond.notify_all();
+        self.read_cond.notify_all();
         drop(guard);
+        self.wake_due();
         Ok(())
     }
+
+    /// Push all elements onto the queue at once, gives up with `TimedOut` if there is still no room for all of them
+    /// once the timeout elapsed. Either all or none of the elements are queued and no other push or pop can interleave.
+    /// Waits until the queue holds at most `high watermark + 1 - count` elements, so a batch that does not fit
+    /// into an empty queue waits until it is empty. This ignores the memory limit. A timeout that is too large blocks forever.
+    #[cfg_attr(not(test), allow(dead_code))] //The write pipe always pushes cancelable.
+    pub fn batch_push(&self, items: impl IntoIterator<Item = T>, timeout: Option<Duration>) -> io::Result<()> {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        self.batch_push_before_cancelable(items, deadline, &NEVER_CANCELED)
+    }
+
+    /// Like `batch_push` but with a deadline and also gives up with `Interrupted` if the cancellation token is set while there is no room.
+    pub fn batch_push_before_cancelable(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        deadline: Option<Instant>,
+        cancel: &AtomicBool,
+    ) -> io::Result<()> {
+        let items: Vec<T> = items.into_iter().collect();
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        if self.config.overflow_policy != OverflowPolicy::Block {
+            let mut guard = unwrap_poison(self.buffer.lock())?;
+            if self.is_dead() {
+                return Err(queue_dead());
+            }
+
+            for data in items {
+                self.discard_locked(&mut guard, data, self.config.overflow_policy);
+            }
+            self.read_cond.notify_all();
+            self.write_cond.notify_all(); //Dropping the oldest elements may lower the amount of bytes.
+            drop(guard);
+            self.wake_due();
+            return Ok(());
+        }
+
+        let mut guard = self.flush_count(
+            || (self.high_watermark.load(Relaxed) + 1).saturating_sub(items.len()),
+            self.config.high_watermark_bytes,
+            false,
+            deadline,
+            cancel,
+            None,
+        )?;
+        for (index, data) in items.iter().enumerate() {
+            self.added(data.byte_len(), guard.len() + index + 1);
+        }
+        guard.extend(items);
+        self.read_cond.notify_all();
+        drop(guard);
+        self.wake_due();
+        Ok(())
+    }
+
+    /// Push 1 element onto the queue, returns `WouldBlock` immediately if the queue is full.
+    /// This ignores the memory limit.
+    pub fn try_push(&self, data: T) -> io::Result<()> {
+        let mut guard = unwrap_poison(self.buffer.lock())?;
+        if self.is_dead() {
+            return Err(queue_dead());
+        }
+
+        if self.is_full(&guard) {
+            self.refused.store(true, Release);
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        }
+
+        self.added(data.byte_len(), guard.len() + 1);
+        guard.push_back(data);
+        self.read_cond.notify_all();
+        drop(guard);
+        self.wake_due();
+        Ok(())
+    }
+
+    /// Pushes 1 element with `policy` instead of the configured overflow policy.
+    /// With a drop policy this never blocks and ignores the memory limit.
+    pub fn push_overflowing(&self, data: T, policy: OverflowPolicy) -> io::Result<()> {
+        if policy == OverflowPolicy::Block {
+            return self.push(data);
+        }
+
+        self.push_or_discard(data, policy)
+    }
+
+    /// Pushes according to a drop overflow policy, never blocks.
+    fn push_or_discard(&self, data: T, policy: OverflowPolicy) -> io::Result<()> {
+        let mut guard = unwrap_poison(self.buffer.lock())?;
+        if self.is_dead() {
+            return Err(queue_dead());
+        }
+
+        self.discard_locked(&mut guard, data, policy);
+        self.read_cond.notify_all();
+        self.write_cond.notify_all(); //Dropping the oldest elements may lower the amount of bytes.
+        drop(guard);
+        self.wake_due();
+        Ok(())
+    }
+
+    /// Queues or discards 1 element according to a drop overflow policy. Caller must hold the buffer mutex and notify.
+    fn discard_locked(&self, buffer: &mut VecDeque<T>, data: T, policy: OverflowPolicy) {
+        if policy == OverflowPolicy::DropNewest && self.is_full(buffer) {
+            self.discarded(data.byte_len());
+            return;
+        }
+
+        //Makes the same room a blocking push would wait for.
+        while self.is_full(buffer) {
+            let Some(oldest) = buffer.pop_front() else {
+                break;
+            };
+
+            self.removed(oldest.byte_len(), buffer.len());
+            self.discarded(oldest.byte_len());
+        }
+
+        self.added(data.byte_len(), buffer.len() + 1);
+        buffer.push_back(data);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{OverflowPolicy, Queue, QueueConfig};
+    use std::io;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn byte_len() {
+        let queue = Queue::default();
+        queue.push(vec![0; 16]).unwrap();
+        queue.push(vec![0; 4]).unwrap();
+        assert_eq!(queue.byte_len(), 20);
+        assert_eq!(queue.pop().unwrap().len(), 16);
+        assert_eq!(queue.byte_len(), 4);
+        assert_eq!(queue.try_pop().unwrap().unwrap().len(), 4);
+        assert_eq!(queue.byte_len(), 0);
+    }
+
+    #[test]
+    fn byte_watermark_backpressure() {
+        let queue = Arc::new(Queue::with_config(QueueConfig {
+            high_watermark_bytes: Some(10),
+            ..QueueConfig::default()
+        }));
+        queue.push(vec![0; 20]).unwrap();
+
+        let pusher = Arc::clone(&queue);
+        let handle = thread::spawn(move || pusher.push(vec![0; 5]).unwrap());
+        thread::sleep(Duration::from_millis(100));
+        assert!(!handle.is_finished());
+        assert_eq!(queue.byte_len(), 20);
+
+        assert_eq!(queue.pop().unwrap().len(), 20);
+        handle.join().unwrap();
+        assert_eq!(queue.byte_len(), 5);
+    }
+
+    #[test]
+    fn push_before() {
+        let queue = Queue::with_config(QueueConfig {
+            high_watermark_bytes: Some(10),
+            ..QueueConfig::default()
+        });
+        queue.push(vec![0; 20]).unwrap();
+        let err = queue
+            .push_before(vec![0; 5], Some(Instant::now() + Duration::from_millis(50)))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(queue.byte_len(), 20);
+
+        queue.pop().unwrap();
+        queue
+            .push_before(vec![0; 5], Some(Instant::now() + Duration::from_millis(50)))
+            .unwrap();
+    }
+
+    fn overflowing(policy: OverflowPolicy) -> Queue {
+        let queue = Queue::with_config(QueueConfig {
+            high_watermark_bytes: Some(10),
+            overflow_policy: policy,
+            ..QueueConfig::default()
+        });
+        let start = Instant::now();
+        for i in 0..100 {
+            queue.push(vec![i; 6]).unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        queue
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let queue = overflowing(OverflowPolicy::DropOldest);
+        assert_eq!(queue.dropped_bytes(), 98 * 6);
+        assert_eq!(queue.pop().unwrap(), vec![98; 6]);
+        assert_eq!(queue.pop().unwrap(), vec![99; 6]);
+        assert!(queue.is_empty().unwrap());
+    }
+
+    #[test]
+    fn drop_newest() {
+        let queue = overflowing(OverflowPolicy::DropNewest);
+        assert_eq!(queue.dropped_bytes(), 98 * 6);
+        assert_eq!(queue.pop().unwrap(), vec![0; 6]);
+        assert_eq!(queue.pop().unwrap(), vec![1; 6]);
+        assert!(queue.is_empty().unwrap());
+
+        queue.push(vec![100; 6]).unwrap();
+        assert_eq!(queue.pop().unwrap(), vec![100; 6]);
+        queue.kill("test");
+        assert!(queue.push(vec![0; 6]).is_err());
+    }
+
+    #[test]
+    fn batch_push() {
+        let queue = Arc::new(Queue::default());
+        queue.set_watermarks(1, 4).unwrap();
+        queue.push(vec![0]).unwrap();
+        queue.push(vec![1]).unwrap();
+
+        //Needs 3 free slots, only 2 are left until the watermark is exceeded.
+        let items = vec![vec![2], vec![3], vec![4], vec![5]];
+        let err = queue.batch_push(items.clone(), Some(Duration::from_millis(50))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(queue.byte_len(), 2);
+
+        let pusher = Arc::clone(&queue);
+        let handle = thread::spawn(move || pusher.batch_push(items, None).unwrap());
+        thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_finished());
+        assert_eq!(queue.pop().unwrap(), vec![0]);
+        handle.join().unwrap();
+        for i in 1..6 {
+            assert_eq!(queue.pop().unwrap(), vec![i]);
+        }
+
+        //A batch that never fits waits for an empty queue instead of forever.
+        queue.push(vec![0]).unwrap();
+        let pusher = Arc::clone(&queue);
+        let handle = thread::spawn(move || pusher.batch_push((0..10).map(|i| vec![i]), None).unwrap());
+        thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_finished());
+        queue.pop().unwrap();
+        handle.join().unwrap();
+        assert_eq!(queue.byte_len(), 10);
+
+        queue.kill("test");
+        assert!(queue.batch_push(vec![vec![0]], None).is_err());
+        queue.batch_push(Vec::new(), None).unwrap();
+    }
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Relaxed);
+        }
+    }
+
+    fn counting_waker() -> (Arc<Counter>, Waker) {
+        let counter = Arc::new(Counter::default());
+        (Arc::clone(&counter), Waker::from(counter))
+    }
+
+    #[test]
+    fn wakers() {
+        let queue = Queue::default();
+        queue.set_watermarks(1, 4).unwrap();
+        let (replaced, waker) = counting_waker();
+        queue.register_push_waker(waker).unwrap();
+        let (pushed, waker) = counting_waker();
+        queue.register_push_waker(waker).unwrap();
+        for i in 0..3 {
+            queue.push(vec![i]).unwrap();
+        }
+        assert_eq!(replaced.0.load(Relaxed), 0);
+        assert_eq!(pushed.0.load(Relaxed), 1);
+
+        let (room, waker) = counting_waker();
+        queue.register_room_waker(waker).unwrap();
+        queue.pop().unwrap();
+        assert_eq!(room.0.load(Relaxed), 0);
+        queue.pop().unwrap();
+        assert_eq!(room.0.load(Relaxed), 1);
+
+        //Woken right away if the condition already holds.
+        let (pushed, waker) = counting_waker();
+        queue.register_push_waker(waker).unwrap();
+        assert_eq!(pushed.0.load(Relaxed), 1);
+
+        queue.pop().unwrap();
+        let (pushed, waker) = counting_waker();
+        queue.register_push_waker(waker).unwrap();
+        assert_eq!(pushed.0.load(Relaxed), 0);
+        queue.kill("test");
+        assert_eq!(pushed.0.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn try_push() {
+        let queue = Queue::with_config(QueueConfig {
+            high_watermark_bytes: Some(10),
+            ..QueueConfig::default()
+        });
+        queue.try_push(vec![0; 20]).unwrap();
+        let err = queue.try_push(vec![0; 5]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(queue.byte_len(), 20);
+
+        queue.pop().unwrap();
+        queue.try_push(vec![0; 5]).unwrap();
+        assert_eq!(queue.byte_len(), 5);
+
+        queue.kill("test");
+        let err = queue.try_push(vec![0; 5]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn cancel() {
+        let queue = Arc::new(Queue::with_config(QueueConfig {
+            high_watermark_bytes: Some(10),
+            ..QueueConfig::default()
+        }));
+        let cancel = Arc::new(AtomicBool::new(false));
+        queue.push(vec![0; 20]).unwrap();
+
+        let pusher = Arc::clone(&queue);
+        let token = Arc::clone(&cancel);
+        let handle = thread::spawn(move || pusher.push_cancelable(vec![0; 5], &token));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_finished());
+        cancel.store(true, Relaxed);
+        let err = handle.join().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(queue.byte_len(), 20);
+
+        queue.pop().unwrap();
+        let err = queue.pop_cancelable(&cancel).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        cancel.store(false, Relaxed);
+        queue.push_cancelable(vec![1], &cancel).unwrap();
+        assert_eq!(queue.pop_cancelable(&cancel).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn watermarks() {
+        let queue = Arc::new(Queue::default());
+        assert_eq!(
+            queue.set_low_watermark(usize::MAX).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            queue.set_high_watermark(0).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        queue.set_watermarks(0, 1).unwrap();
+        queue.push(vec![0]).unwrap();
+        queue.push(vec![1]).unwrap();
+        assert_eq!(queue.try_push(vec![2]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(queue.try_flush_low().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+        let pusher = Arc::clone(&queue);
+        let handle = thread::spawn(move || pusher.push(vec![2]));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_finished());
+        queue.set_high_watermark(2).unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(queue.drain().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn wait_until_empty() {
+        let queue = Arc::new(Queue::default());
+        queue.wait_until_empty(Some(Duration::ZERO)).unwrap();
+        queue.push(vec![0; 4]).unwrap();
+        let err = queue.wait_until_empty(Some(Duration::from_millis(20))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let popper = Arc::clone(&queue);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            popper.pop().unwrap()
+        });
+        queue.wait_until_empty(None).unwrap();
+        assert_eq!(handle.join().unwrap().len(), 4);
+
+        queue.kill("test");
+        assert_eq!(queue.wait_until_empty(None).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn pop_before() {
+        let queue = Arc::new(Queue::default());
+        assert!(queue.pop_before(Instant::now() + Duration::from_millis(20)).unwrap().is_none());
+        queue.push(vec![1]).unwrap();
+        assert_eq!(queue.pop_before(Instant::now()).unwrap(), Some(vec![1]));
+
+        let killer = Arc::clone(&queue);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            killer.kill("test");
+        });
+        let err = queue.pop_before(Instant::now() + Duration::from_secs(30)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn wake_all_ends_await_pop() {
+        let queue: Arc<Queue> = Arc::default();
+        let outer = crate::sync::Mutex::new(());
+        let epoch = queue.epoch();
+        let waker = Arc::clone(&queue);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            waker.wake_all();
+        });
+        queue.await_pop(crate::unwrap_poison(outer.lock()).unwrap(), None, epoch).unwrap();
+        handle.join().unwrap();
+
+        //A wake that happened before the wait started is not missed.
+        queue.await_pop(crate::unwrap_poison(outer.lock()).unwrap(), None, epoch).unwrap();
+        let err = queue
+            .await_pop(crate::unwrap_poison(outer.lock()).unwrap(), Some(Instant::now()), queue.epoch())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn await_received() {
+        let queue = Arc::new(Queue::default());
+        queue.push(vec![1; 3]).unwrap();
+        queue.pop().unwrap();
+        queue.push(Vec::new()).unwrap();
+        assert_eq!(queue.received(), 3);
+        let err = queue.await_received(3, Some(Instant::now())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let pusher = Arc::clone(&queue);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            pusher.push(vec![2]).unwrap();
+        });
+        queue.await_received(3, Some(Instant::now() + Duration::from_secs(30))).unwrap();
+        handle.join().unwrap();
+
+        queue.kill("test");
+        let err = queue.await_received(4, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn drain() {
+        let queue = Queue::default();
+        queue.push(vec![1; 3]).unwrap();
+        queue.push(vec![2; 2]).unwrap();
+        queue.kill("test");
+        assert_eq!(queue.drain().unwrap(), vec![vec![1; 3], vec![2; 2]]);
+        assert_eq!(queue.byte_len(), 0);
+        assert_eq!(queue.memory_usage(), 0);
+        assert!(queue.drain().unwrap().is_empty());
+        assert_eq!(queue.try_pop().unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn shared_memory_limit() {
+        let config = QueueConfig {
+            total_memory_limit: Some(10),
+            ..QueueConfig::default()
+        };
+        let memory = Arc::default();
+        let first = Arc::new(Queue::with_memory(config, Arc::clone(&memory)));
+        let second = Queue::with_memory(config, memory);
+        second.push(vec![0; 20]).unwrap();
+        assert_eq!(first.memory_usage(), 20);
+
+        let pusher = Arc::clone(&first);
+        let handle = thread::spawn(move || pusher.push(vec![0; 5]).unwrap());
+        thread::sleep(Duration::from_millis(100));
+        assert!(!handle.is_finished());
+        let err = first.flush_low(Some(Instant::now() + Duration::from_millis(50)), first.epoch()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        first.push_before(vec![0; 1], None).unwrap();
+
+        assert_eq!(second.pop().unwrap().len(), 20);
+        handle.join().unwrap();
+        assert_eq!(second.memory_usage(), 6);
+        assert_eq!(second.byte_len(), 0);
+        assert_eq!(first.byte_len(), 6);
+    }
+
+    #[cfg(all(unix, feature = "unix"))]
+    #[test]
+    fn readiness() {
+        use std::io::ErrorKind;
+        use std::os::fd::BorrowedFd;
+        use std::net::TcpStream;
+
+        fn readable(fd: BorrowedFd<'_>) -> bool {
+            //recv with MSG_PEEK works on unix sockets as well, UnixStream::peek is unstable.
+            let socket = TcpStream::from(fd.try_clone_to_owned().unwrap());
+            match socket.peek(&mut [0u8; 1]) {
+                Ok(count) => count > 0,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => false,
+                Err(err) => panic!("{err}"),
+            }
+        }
+
+        let queue = Queue::default();
+        queue.push(vec![1]).unwrap();
+        //Data that was pushed before the signal existed counts.
+        let fd = queue.readiness().unwrap().fd();
+        assert!(readable(fd));
+        queue.push(vec![2]).unwrap();
+        queue.pop().unwrap();
+        assert!(readable(fd));
+        queue.pop().unwrap();
+        assert!(!readable(fd));
+        queue.push(vec![3]).unwrap();
+        assert!(readable(fd));
+        queue.pop().unwrap();
+        assert!(!readable(fd));
+        queue.kill("test");
+        assert!(readable(fd));
+    }
+
+    #[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+    #[test]
+    fn room() {
+        use std::io::{ErrorKind, Write};
+        use std::os::unix::net::UnixStream;
+
+        let queue = Queue::default();
+        queue.set_watermarks(1, 4).unwrap();
+        queue.push(vec![0]).unwrap();
+        queue.push(vec![1]).unwrap();
+        //Data that was pushed before the signal existed counts.
+        let room = UnixStream::from(queue.room().unwrap().fd().try_clone_to_owned().unwrap());
+        let writable = || match (&room).write(&[0]) {
+            Ok(_) => true,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => false,
+            Err(err) => panic!("{err}"),
+        };
+        assert!(!writable());
+        queue.pop().unwrap();
+        assert!(writable());
+        queue.push(vec![2]).unwrap();
+        assert!(!writable());
+        queue.set_watermarks(2, 4).unwrap();
+        assert!(writable());
+        queue.push(vec![3]).unwrap();
+        assert!(!writable());
+        queue.kill("test");
+        assert!(writable());
+    }
 }
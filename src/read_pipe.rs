@@ -1,51 +1,342 @@
 //! Background queued reader.
-use crate::queue::Queue;
+use crate::events::EventSlot;
+use crate::queue::{OverflowPolicy, Queue};
+use crate::sync::Mutex;
+use crate::{unwrap_poison, READ_BUFFER_SIZE};
 use defer_heavy::defer;
+use std::fmt::{Debug, Formatter};
 use std::io;
 use std::io::{Cursor, ErrorKind, Read};
-use std::sync::{Arc, OnceLock};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest time the idle watchdog sleeps before it checks whether the pipe was dropped.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Longest time the background reader waits for room in the queue for EOF before it gives up and ends.
+#[cfg(not(test))]
+const EOF_PUSH_TIMEOUT: Duration = Duration::from_secs(30);
+#[cfg(test)]
+const EOF_PUSH_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Callback that is invoked once received data waits to be read, see `RustTlsDuplexStream::set_data_callback`.
+pub type DataCallback = Box<dyn Fn() + Send + Sync>;
+
+/// A data callback that can be invoked without holding the lock of its slot.
+type SharedCallback = Arc<dyn Fn() + Send + Sync>;
+
+/// Slot for an optional data callback.
+#[derive(Default)]
+pub struct DataCallbackSlot {
+    /// The callback, cleared once it panicked.
+    callback: Mutex<Option<SharedCallback>>,
+}
+
+impl Debug for DataCallbackSlot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let set = unwrap_poison(self.callback.lock()).map(|callback| callback.is_some());
+        f.debug_struct("DataCallbackSlot").field("set", &set.ok()).finish()
+    }
+}
+
+impl DataCallbackSlot {
+    /// Replaces the callback.
+    pub fn set(&self, callback: Option<DataCallback>) {
+        if let Ok(mut guard) = unwrap_poison(self.callback.lock()) {
+            *guard = callback.map(SharedCallback::from);
+        }
+    }
+
+    /// Invokes the callback if one is set, without holding any lock. A callback that panics is cleared.
+    pub fn call(&self) {
+        let Ok(guard) = unwrap_poison(self.callback.lock()) else {
+            return;
+        };
+
+        let Some(callback) = guard.as_ref().map(Arc::clone) else {
+            return;
+        };
+        drop(guard);
+
+        if panic::catch_unwind(AssertUnwindSafe(|| callback())).is_err() {
+            warn!("data callback panicked, it was removed");
+            if let Ok(mut guard) = unwrap_poison(self.callback.lock()) {
+                //It may have been replaced while it ran.
+                if guard.as_ref().is_some_and(|current| Arc::ptr_eq(current, &callback)) {
+                    *guard = None;
+                }
+            }
+        }
+    }
+}
+
+/// Settings of a `ReadPipe`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReadPipeConfig {
+    /// The connection is killed once nothing was received for this long, if set the idle watchdog is spawned.
+    pub idle_timeout: Option<Duration>,
+    /// What the background reader does with received data while the queue is full.
+    /// EOF is always queued, the background reader waits up to 30 seconds for room.
+    pub on_full: OverflowPolicy,
+}
 
 /// Read pipe inner state
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct ReadPipeInner {
+    /// Id of the stream this pipe belongs to.
+    id: u64,
     /// The actual data queue.
     queue: Arc<Queue>,
-    /// Async error
-    error: OnceLock<ErrorKind>,
+    /// Async error, this is the original error of the underlying read.
+    error: OnceLock<Arc<io::Error>>,
+    /// Set once the underlying read has signaled EOF, as opposed to failing or dying.
+    closed_cleanly: AtomicBool,
+    /// When bytes were last received from the underlying read, or when the idle timeout was last set.
+    last_read_at: Mutex<Instant>,
+    /// The connection is killed once nothing was received for this long. Only used if the watchdog runs.
+    idle_timeout: Mutex<Option<Duration>>,
+    /// What to do with received data while the queue is full.
+    on_full: OverflowPolicy,
+    /// Invoked once a push found the queue empty, shared with the stream.
+    data_callback: Arc<DataCallbackSlot>,
+    /// Receives EOF and errors of the underlying read, shared with the stream and the write pipe.
+    events: Arc<EventSlot>,
+    /// Set once the pipe was restarted with another read, the background threads of this read leave the queue alone from then on.
+    retired: AtomicBool,
+    /// Held by the background threads while they push onto or kill the queue, so the restarted pipe can wait for them.
+    gate: Mutex<()>,
 }
 impl ReadPipeInner {
-    
+
+    /// Spawns the background thread and, if an idle timeout is given, the idle watchdog.
+    /// If a retired `previous` pipe is given the background thread waits until its threads left the queue before it reads.
+    /// The returned channel disconnects once both ended. The queue is killed if a spawn fails.
+    #[allow(clippy::too_many_arguments)]
+    fn start<R: Read + Send + 'static, T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>>(
+        id: u64,
+        queue: Arc<Queue>,
+        data_callback: Arc<DataCallbackSlot>,
+        events: Arc<EventSlot>,
+        config: ReadPipeConfig,
+        read: R,
+        previous: Option<Arc<Self>>,
+        spawner: &mut T,
+    ) -> io::Result<(Arc<Self>, mpsc::Receiver<()>)> {
+        let ReadPipeConfig { idle_timeout, on_full } = config;
+        let wp = Arc::new(Self {
+            id,
+            queue,
+            error: OnceLock::new(),
+            closed_cleanly: AtomicBool::new(false),
+            last_read_at: Mutex::new(Instant::now()),
+            idle_timeout: Mutex::new(idle_timeout),
+            on_full,
+            data_callback,
+            events,
+            retired: AtomicBool::new(false),
+            gate: Mutex::new(()),
+        });
+        let (running, finished) = mpsc::channel::<()>();
+        let wpc = Arc::clone(&wp);
+        let reader_running = running.clone();
+        if let Err(err) = spawner(Box::new(move || {
+            let _running = reader_running;
+            if let Some(previous) = previous {
+                wpc.take_over(&previous);
+            }
+            wpc.handle(read);
+        })) {
+            wp.queue.kill("spawning the background reader failed");
+            return Err(err);
+        }
+        if idle_timeout.is_some() {
+            let wpc = Arc::clone(&wp);
+            if let Err(err) = spawner(Box::new(move || {
+                let _running = running;
+                wpc.watch();
+            })) {
+                wp.queue.kill("spawning the idle watchdog failed"); //Ends the reader that was already spawned once its read returns.
+                return Err(err);
+            }
+        }
+        Ok((wp, finished))
+    }
+
+    /// Restarts the pipe with another read and the same settings, elements left in the queue are kept.
+    /// This pipe is retired, its background threads never touch the queue again. A push they started before
+    /// is finished first, the new background thread only starts reading once it was.
+    /// A dead queue is revived, an EOF the old read queued is discarded then.
+    /// # Errors
+    /// propagated from the spawner fn, the queue is dead then. In case of poisoned mutex
+    fn restart<R: Read + Send + 'static, T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>>(
+        self: &Arc<Self>,
+        read: R,
+        spawner: &mut T,
+    ) -> io::Result<(Arc<Self>, mpsc::Receiver<()>)> {
+        self.retired.store(true, Release);
+        if self.queue.is_dead() {
+            //Pushes onto a dead queue fail, so the gate is only held briefly.
+            let gate = unwrap_poison(self.gate.lock())?;
+            self.queue.discard_last_if(Vec::is_empty)?;
+            self.queue.revive()?;
+            drop(gate);
+        }
+
+        let config = ReadPipeConfig {
+            idle_timeout: *unwrap_poison(self.idle_timeout.lock())?,
+            on_full: self.on_full,
+        };
+        Self::start(
+            self.id,
+            Arc::clone(&self.queue),
+            Arc::clone(&self.data_callback),
+            Arc::clone(&self.events),
+            config,
+            read,
+            Some(Arc::clone(self)),
+            spawner,
+        )
+    }
+
+    /// Waits until the background threads of the retired `previous` pipe finished their last push,
+    /// then discards an EOF they queued as the last element.
+    fn take_over(&self, previous: &Self) {
+        //A poisoned gate means the previous thread panicked, it does not push anymore either.
+        drop(previous.gate.lock());
+        _ = self.queue.discard_last_if(Vec::is_empty);
+    }
+
+    /// Runs `f` while holding the gate unless this pipe was retired, returns None if it was.
+    fn unless_retired<X>(&self, f: impl FnOnce() -> X) -> Option<X> {
+        let gate = unwrap_poison(self.gate.lock()).ok()?;
+        if self.retired.load(Acquire) {
+            return None;
+        }
+
+        let result = f();
+        drop(gate);
+        Some(result)
+    }
+
     /// Background read handler thread loop.
     fn handle<T: Read + Send>(&self, mut read: T) {
+        enter_span!("tls-read", id = self.id);
+        debug!(id = self.id, "background reader started");
         defer! {
              // This also happens on panic!
-            self.queue.kill();
+            self.unless_retired(|| self.queue.kill("background reader ended"));
+            debug!(id = self.id, "background reader stopped");
+            if self.error.get().is_some() {
+                //Without this a callback driven reader would never learn that the connection failed.
+                self.data_callback.call();
+            }
         }
-        let mut buffer = vec![0u8; 0x1_00_00];
+        //Zeroed once per thread and reused for every read. Handing uninitialized memory to `read` needs
+        //`Read::read_buf`, which is not stable, so there is nothing to gain from skipping this memset.
+        let mut buffer = vec![0u8; READ_BUFFER_SIZE];
         loop {
             let packet = match read.read(buffer.as_mut_slice()) {
                 Ok(count) => buffer[0..count].to_vec(),
                 Err(err) => {
-                    _ = self.error.set(err.kind());
+                    //The read of a retired pipe is expected to fail once its transport is closed.
+                    self.unless_retired(|| {
+                        error!(id = self.id, kind = ?err.kind(), error = %err, "background read failed");
+                        self.events.error(&err);
+                        _ = self.error.set(Arc::new(err));
+                    });
                     return;
                 }
             };
 
             if packet.is_empty() {
-                if let Err(err) = self.queue.push(packet) {
-                    _ = self.error.set(err.kind());
-                }
+                // Published before the EOF sentinel is pushed, pairs with the load in fetch_err.
+                self.closed_cleanly.store(true, Release);
+                self.unless_retired(|| {
+                    //Nobody may ever read again, waiting for room forever would keep this thread alive.
+                    match self.queue.push_before(packet, Instant::now().checked_add(EOF_PUSH_TIMEOUT)) {
+                        Ok(()) => {}
+                        Err(err) if err.kind() == ErrorKind::TimedOut => {
+                            warn!(id = self.id, timeout = ?EOF_PUSH_TIMEOUT, "queue stayed full, EOF was not queued");
+                        }
+                        Err(err) => _ = self.error.set(Arc::new(err)),
+                    }
+                    self.notify_data();
+                    self.events.peer_closed();
+                });
                 return;
             }
-            if let Err(err) = self.queue.push(packet) {
-                _ = self.error.set(err.kind());
+            if let Ok(mut last_read_at) = unwrap_poison(self.last_read_at.lock()) {
+                *last_read_at = Instant::now();
+            }
+            let pushed = self.unless_retired(|| {
+                if let Err(err) = self.queue.push_overflowing(packet, self.on_full) {
+                    _ = self.error.set(Arc::new(err));
+                }
+                self.notify_data();
+            });
+            if pushed.is_none() {
+                //Data the old transport received after it was replaced is dropped.
+                return;
             }
         }
     }
+
+    /// Invokes the data callback if a push found the queue empty since the last time, so a burst only invokes it once.
+    fn notify_data(&self) {
+        if self.queue.take_refilled() {
+            self.events.readable();
+            self.data_callback.call();
+        }
+    }
+
+    /// Idle watchdog thread loop, kills the queue with `TimedOut` once nothing was received within the idle timeout.
+    /// Ends once the queue is dead for any reason.
+    fn watch(&self) {
+        enter_span!("tls-idle-watchdog", id = self.id);
+        while !self.queue.is_dead() && !self.retired.load(Acquire) {
+            let (Ok(timeout), Ok(last_read_at)) = (
+                unwrap_poison(self.idle_timeout.lock()).map(|guard| *guard),
+                unwrap_poison(self.last_read_at.lock()).map(|guard| *guard),
+            ) else {
+                return;
+            };
+
+            let Some(timeout) = timeout else {
+                return;
+            };
+
+            let idle = last_read_at.elapsed();
+            let Some(left) = timeout.checked_sub(idle).filter(|left| !left.is_zero()) else {
+                self.unless_retired(|| {
+                    error!(id = self.id, idle = ?idle, "idle read timeout elapsed");
+                    let err = io::Error::new(ErrorKind::TimedOut, "nothing was received within the idle read timeout");
+                    self.events.error(&err);
+                    _ = self.error.set(Arc::new(err));
+                    self.queue.kill("idle read timeout elapsed");
+                });
+                return;
+            };
+
+            thread::sleep(left.min(WATCHDOG_INTERVAL));
+        }
+    }
 }
 
 /// fake read impl that will pop from a queue and try to return immediately. 
 /// Read are deferred to a background thread.
+///
+/// EOF of the underlying read is reported as `Ok(0)`. An error of the underlying read is reported with its kind.
+/// If the background thread died without either (i.e. it panicked) `ConnectionReset` is reported.
+///
+/// Data that was buffered before the underlying read closed or failed is still returned by reads
+/// until this pipe is dropped, the error or EOF is only reported once the buffer is exhausted.
+/// If the buffer stays full for 30 seconds after the underlying read reached EOF the background thread ends
+/// without queueing EOF, `BrokenPipe` is reported instead of EOF once the buffer is exhausted.
 #[derive(Debug)]
 pub struct ReadPipe {
     /// Eof marker
@@ -56,53 +347,158 @@ pub struct ReadPipe {
     pipe: Arc<ReadPipeInner>,
     /// current data cursor. We may pop more data from the queue than we can consume. Excess is pushed into this cursor which is read before popping the queue.
     cursor: Cursor<Vec<u8>>,
+    /// Disconnects once the background threads ended.
+    finished: mpsc::Receiver<()>,
 }
 
 impl Drop for ReadPipe {
     fn drop(&mut self) {
-        self.pipe.queue.kill();
+        self.pipe.queue.kill("stream dropped");
+        // The background thread may stay blocked in read for a long time, release what it buffered now.
+        _ = self.pipe.queue.drain();
+        self.pipe.events.close_subscribers();
     }
 }
 
 impl ReadPipe {
     
     /// Constructor that spawns the background thread.
+    /// If an idle timeout is given the idle watchdog is spawned as well.
     pub fn new<R: Read + Send + 'static, T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>>(
         write: R,
+        id: u64,
+        queue: Queue,
+        events: Arc<EventSlot>,
+        config: ReadPipeConfig,
         spawner: &mut T,
     ) -> io::Result<Self> {
-        let wp = Arc::new(ReadPipeInner::default());
-        let wpc = Arc::clone(&wp);
-        spawner(Box::new(move || {
-            wpc.handle(write);
-        }))?;
+        let (pipe, finished) = ReadPipeInner::start(id, Arc::new(queue), Arc::default(), events, config, write, None, spawner)?;
         Ok(Self {
             nb: false,
             eof: false,
-            pipe: wp,
+            pipe,
             cursor: Cursor::default(),
+            finished,
         })
     }
 
+    /// Replaces the underlying read, the queue is reused.
+    /// Kills the queue and waits until the background threads of the old read ended, then discards
+    /// whatever they buffered and spawns them again for the new read with the same settings.
+    /// # Errors
+    /// propagated from the spawner fn, the queue stays dead then. In case of poisoned mutex
+    pub fn replace_transport<R: Read + Send + 'static, T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>>(
+        &mut self,
+        read: R,
+        spawner: &mut T,
+    ) -> io::Result<()> {
+        self.pipe.queue.kill("transport replaced");
+        //Nothing is ever sent, this returns once every task dropped its sender.
+        _ = self.finished.recv();
+        drop(self.pipe.queue.drain()?);
+        self.cursor = Cursor::default();
+        self.eof = false;
+
+        let (pipe, finished) = self.pipe.restart(read, spawner)?;
+        self.pipe = pipe;
+        self.finished = finished;
+        Ok(())
+    }
+
+    /// Replaces the underlying read without discarding anything, the queue and the cursor are kept.
+    /// Reads return what the old read queued first, then what the new read receives, see `ReadPipeInner::restart`.
+    /// The background thread of the old read ends once its read returns, it queues nothing after this was called.
+    /// # Errors
+    /// `UnexpectedEof` if EOF of the old read was already returned, the old read is kept then.
+    /// propagated from the spawner fn, the queue is dead then. In case of poisoned mutex
+    pub fn swap_transport<R: Read + Send + 'static, T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>>(
+        &mut self,
+        read: R,
+        spawner: &mut T,
+    ) -> io::Result<()> {
+        if self.eof {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "EOF of the old transport was already read, use reconnect instead",
+            ));
+        }
+
+        let (pipe, finished) = self.pipe.restart(read, spawner)?;
+        self.pipe = pipe;
+        self.finished = finished;
+        Ok(())
+    }
+
     /// is nb on?
-    pub fn nb(&mut self, value: bool) {
+    pub const fn nb(&mut self, value: bool) {
         self.nb = value;
     }
 
+    /// Copies popped data into buf, only the part that does not fit is kept in the cursor.
+    /// The cursor must be exhausted.
+    fn consume(&mut self, data: Vec<u8>, buf: &mut [u8]) -> usize {
+        if data.len() <= buf.len() {
+            buf[..data.len()].copy_from_slice(&data);
+            return data.len();
+        }
+
+        buf.copy_from_slice(&data[..buf.len()]);
+        self.cursor = Cursor::new(data);
+        self.cursor.set_position(buf.len() as u64);
+        buf.len()
+    }
+
+    /// Changes the idle timeout, the idle window starts over.
+    /// # Errors
+    /// `Unsupported` if the pipe was created without an idle timeout, in case of poisoned mutex
+    pub fn idle_timeout(&self, timeout: Duration) -> io::Result<()> {
+        let mut guard = unwrap_poison(self.pipe.idle_timeout.lock())?;
+        if guard.is_none() {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "the idle watchdog only runs if the stream was created with an idle read timeout",
+            ));
+        }
+
+        *unwrap_poison(self.pipe.last_read_at.lock())? = Instant::now();
+        *guard = Some(timeout);
+        drop(guard);
+        Ok(())
+    }
+
+    /// get a handle to the slot of the data callback.
+    pub fn dup_data_callback(&self) -> Arc<DataCallbackSlot> {
+        Arc::clone(&self.pipe.data_callback)
+    }
+
+    /// get a handle to the slot of the event handler.
+    pub fn dup_events(&self) -> Arc<EventSlot> {
+        Arc::clone(&self.pipe.events)
+    }
+
     /// get a handle to the internal queue.
     pub fn dup_queue(&self) -> Arc<Queue> {
         Arc::clone(&self.pipe.queue)
     }
 
     /// util to get the error. All errors are treated as fatal.
-    /// if this fn is called when there is no error it will set the error to `BrokenPipe`.
+    /// Returns the original error of the underlying read if there is one.
+    /// if this fn is called when there is no error it will set the error to `BrokenPipe`
+    /// if the underlying read signaled EOF or to `ConnectionReset` if it died without doing so.
     /// and kill the background thread.
-    fn fetch_err(&self) -> io::Error {
-        self.pipe.queue.kill();
-        if let Some(err) = self.pipe.error.get().copied() {
-            return io::Error::from(err);
-        }
-        io::Error::from(ErrorKind::BrokenPipe)
+    pub fn fetch_err(&self) -> io::Error {
+        self.pipe.queue.kill("connection failed");
+        // Only read after the queue died, which happens after the background thread stored the flag.
+        let kind = if self.pipe.closed_cleanly.load(Acquire) {
+            ErrorKind::BrokenPipe
+        } else {
+            ErrorKind::ConnectionReset
+        };
+        let err = self
+            .pipe
+            .error
+            .get_or_init(|| Arc::new(io::Error::from(kind)));
+        io::Error::new(err.kind(), Arc::clone(err))
     }
 }
 
@@ -112,48 +508,254 @@ impl Read for ReadPipe {
             return Ok(0);
         }
 
+        let size = self.cursor.read(buf)?;
+        if size != 0 {
+            return Ok(size);
+        }
+
+        let popped = if self.nb {
+            match self.pipe.queue.try_pop() {
+                Ok(Some(data)) => Ok(data),
+                Ok(None) => {
+                    return Err(io::Error::from(ErrorKind::WouldBlock)); //Will be cought.
+                }
+                Err(err) => Err(err),
+            }
+        } else {
+            self.pipe.queue.pop()
+        };
+
+        let Ok(data) = popped else {
+            return Err(self.fetch_err());
+        };
+
+        if data.is_empty() {
+            self.eof = true;
+            return Ok(0);
+        }
+
+        Ok(self.consume(data, buf))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{ReadPipe, ReadPipeConfig};
+    use crate::queue::{OverflowPolicy, Queue};
+    use std::io;
+    use std::io::{Cursor, ErrorKind, Read};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::SeqCst;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Reader that yields some data and then fails or panics.
+    struct FailingRead(Option<ErrorKind>);
+
+    impl Read for FailingRead {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            let kind = self.0.expect("reader died");
+            Err(io::Error::from(kind))
+        }
+    }
+
+    /// Slow transport that yields one byte after every delay.
+    struct SlowRead(Duration);
+
+    impl Read for SlowRead {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(self.0);
+            buf[0] = 1;
+            Ok(1)
+        }
+    }
+
+    fn pipe<R: Read + Send + 'static>(read: R) -> ReadPipe {
+        watched_pipe(read, None)
+    }
+
+    fn watched_pipe<R: Read + Send + 'static>(read: R, idle_timeout: Option<Duration>) -> ReadPipe {
+        let config = ReadPipeConfig {
+            idle_timeout,
+            ..ReadPipeConfig::default()
+        };
+        ReadPipe::new(read, 0, Queue::default(), Arc::default(), config, &mut |task| {
+            thread::Builder::new().spawn(task).map(|_| {})
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn clean_close() {
+        let mut pipe = pipe(Cursor::new(b"data".to_vec()));
+        let mut buf = Vec::new();
+        pipe.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"data");
+        assert_eq!(pipe.read(&mut [0u8; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn read_error() {
+        let mut pipe = pipe(FailingRead(Some(ErrorKind::TimedOut)));
+        let err = pipe.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn died_without_eof() {
+        let mut pipe = pipe(FailingRead(None));
+        let err = pipe.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+        let err = pipe.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn buffered_data_survives_error() {
+        let mut pipe = pipe(Cursor::new(b"data".to_vec()).chain(FailingRead(Some(ErrorKind::TimedOut))));
+        let queue = pipe.dup_queue();
+        while queue.is_empty().unwrap() {
+            thread::yield_now();
+        }
+        let mut buf = [0u8; 4];
+        pipe.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"data");
+        let err = pipe.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn drop_releases_buffered_data() {
+        let pipe = pipe(Cursor::new(b"data".to_vec()));
+        let queue = pipe.dup_queue();
+        while queue.is_empty().unwrap() {
+            thread::yield_now();
+        }
+        drop(pipe);
+        assert_eq!(queue.byte_len(), 0);
+    }
+
+    #[test]
+    fn partial_reads() {
+        let data: Vec<u8> = (0..=255u8).collect();
+        let mut pipe = pipe(Cursor::new(data.clone()));
+        let mut buf = [0u8; 100];
+        let mut received = Vec::new();
         loop {
-            let size = self.cursor.read(buf)?;
-            if size != 0 {
-                return Ok(size);
+            let count = pipe.read(&mut buf).unwrap();
+            if count == 0 {
+                break;
             }
+            received.extend_from_slice(&buf[..count]);
+        }
+        assert_eq!(received, data);
+    }
 
-            if self.nb {
-                match self.pipe.queue.try_pop() {
-                    Ok(Some(data)) => {
-                        if data.is_empty() {
-                            self.eof = true;
-                            return Ok(0);
-                        }
+    #[test]
+    fn idle_timeout() {
+        let mut pipe = watched_pipe(SlowRead(Duration::from_secs(2)), Some(Duration::from_millis(200)));
+        let start = Instant::now();
+        let err = pipe.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
 
-                        self.cursor = Cursor::new(data);
-                        continue;
-                    }
-                    Ok(None) => {
-                        return Err(io::Error::from(ErrorKind::WouldBlock)); //Will be cought.
-                    }
-                    Err(err) => {
-                        _ = self.pipe.error.set(err.kind());
-                        return Err(self.fetch_err());
-                    }
-                }
+    #[test]
+    fn idle_timeout_spares_slow_connection() {
+        let mut watched = watched_pipe(SlowRead(Duration::from_millis(20)), Some(Duration::from_millis(500)));
+        let start = Instant::now();
+        let mut received = 0;
+        while start.elapsed() < Duration::from_secs(1) {
+            received += watched.read(&mut [0u8; 4]).unwrap();
+        }
+        assert!(received > 0);
+
+        let unwatched = pipe(SlowRead(Duration::from_millis(20)));
+        assert_eq!(unwatched.idle_timeout(Duration::from_secs(1)).unwrap_err().kind(), ErrorKind::Unsupported);
+    }
+
+    /// Transport that yields the given amount of 1 byte packets numbered from 0 and then EOF, counting every read.
+    struct Datagrams {
+        /// Amount of packets.
+        count: usize,
+        /// Amount of reads so far, shared with the test.
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl Read for Datagrams {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.reads.fetch_add(1, SeqCst);
+            if read >= self.count {
+                return Ok(0);
             }
+            buf[0] = u8::try_from(read).unwrap();
+            Ok(1)
+        }
+    }
 
-            match self.pipe.queue.pop() {
-                Ok(data) => {
-                    if data.is_empty() {
-                        self.eof = true;
-                        return Ok(0);
-                    }
+    #[test]
+    fn on_full_drops_instead_of_blocking() {
+        for (policy, expected) in [
+            (OverflowPolicy::DropNewest, [0u8, 1, 2, 3, 4]),
+            (OverflowPolicy::DropOldest, [5u8, 6, 7, 8, 9]),
+        ] {
+            let reads = Arc::new(AtomicUsize::new(0));
+            let queue = Queue::default();
+            queue.set_watermarks(1, 4).unwrap();
+            let config = ReadPipeConfig {
+                on_full: policy,
+                ..ReadPipeConfig::default()
+            };
+            let read = Datagrams {
+                count: 10,
+                reads: Arc::clone(&reads),
+            };
+            let mut pipe = ReadPipe::new(read, 0, queue, Arc::default(), config, &mut |task| {
+                thread::Builder::new().spawn(task).map(|_| {})
+            })
+            .unwrap();
 
-                    self.cursor = Cursor::new(data);
-                    continue;
-                }
-                Err(err) => {
-                    _ = self.pipe.error.set(err.kind());
-                    return Err(self.fetch_err());
-                }
+            //Nothing is read from the pipe, the background reader still reaches EOF.
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while reads.load(SeqCst) <= 10 {
+                assert!(Instant::now() < deadline, "background reader blocked");
+                thread::sleep(Duration::from_millis(1));
             }
+
+            let mut received = Vec::new();
+            pipe.read_to_end(&mut received).unwrap();
+            assert_eq!(received, expected);
+            assert_eq!(pipe.dup_queue().dropped_bytes(), 5);
         }
     }
+
+    #[test]
+    fn eof_push_gives_up() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let queue = Queue::default();
+        queue.set_watermarks(0, 1).unwrap();
+        let config = ReadPipeConfig {
+            on_full: OverflowPolicy::DropNewest,
+            ..ReadPipeConfig::default()
+        };
+        let read = Datagrams {
+            count: 4,
+            reads: Arc::clone(&reads),
+        };
+        let mut pipe = ReadPipe::new(read, 0, queue, Arc::default(), config, &mut |task| {
+            thread::Builder::new().spawn(task).map(|_| {})
+        })
+        .unwrap();
+
+        //Nothing is read from the pipe, so there is never room for EOF and the background reader ends without it.
+        assert!(pipe.finished.recv_timeout(Duration::from_secs(10)).is_err());
+        assert!(reads.load(SeqCst) > 4);
+
+        let mut buf = [0u8; 2];
+        pipe.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1]);
+        assert_eq!(pipe.read(&mut buf).unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
 }
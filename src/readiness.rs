@@ -0,0 +1,209 @@
+//! Signal that lets native event loops wait for a queue.
+//! On unix this is a self-pipe for `poll(2)`, on windows a manual-reset event for `WaitForMultipleObjects`.
+use std::io;
+#[cfg(unix)]
+use std::io::{ErrorKind, Read, Write};
+#[cfg(unix)]
+use std::os::fd::{AsFd, BorrowedFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(windows)]
+use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle, RawHandle};
+#[cfg(unix)]
+use std::sync::atomic::AtomicBool;
+#[cfg(unix)]
+use std::sync::atomic::Ordering::Relaxed;
+
+/// Socket pair whose reading end is readable while the signal is set.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct Readiness {
+    /// End that is handed out to be polled.
+    reader: UnixStream,
+    /// End that is written to set the signal.
+    writer: UnixStream,
+    /// Mirrors whether a byte is pending in the pair. Only modified while holding the buffer mutex of the queue.
+    signaled: AtomicBool,
+}
+
+#[cfg(unix)]
+impl Readiness {
+    /// Creates a cleared signal.
+    /// # Errors
+    /// if the socket pair could not be created
+    pub fn new() -> io::Result<Self> {
+        let (reader, writer) = UnixStream::pair()?;
+        reader.set_nonblocking(true)?;
+        writer.set_nonblocking(true)?;
+        Ok(Self {
+            reader,
+            writer,
+            signaled: AtomicBool::new(false),
+        })
+    }
+
+    /// Makes the fd readable.
+    pub fn set(&self) {
+        if !self.signaled.swap(true, Relaxed) {
+            //A full socket buffer is readable anyway.
+            _ = (&self.writer).write(&[1]);
+        }
+    }
+
+    /// Makes the fd not readable anymore.
+    pub fn clear(&self) {
+        if !self.signaled.swap(false, Relaxed) {
+            return;
+        }
+
+        let mut buf = [0u8; 16];
+        loop {
+            match (&self.reader).read(&mut buf) {
+                Ok(0) => return,
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return, //WouldBlock, everything was read.
+            }
+        }
+    }
+
+    /// The fd to poll for readability.
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.reader.as_fd()
+    }
+}
+
+/// Filler for `Room::clear`, the socket buffer is filled in chunks of this size.
+#[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+static FILLER: [u8; 0x1_0000] = [0; 0x1_0000];
+
+/// Socket pair whose writing end is writable while the signal is set.
+/// Clearing fills the socket buffer, which costs a few hundred KiB of kernel memory while cleared.
+#[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+#[derive(Debug)]
+pub struct Room {
+    /// End that is handed out to be polled.
+    writer: UnixStream,
+    /// End that is drained to set the signal.
+    reader: UnixStream,
+    /// Mirrors whether the socket buffer is full. Only modified while holding the buffer mutex of the queue.
+    cleared: AtomicBool,
+}
+
+#[cfg(all(unix, any(feature = "mio", feature = "async-io")))]
+impl Room {
+    /// Creates a set signal.
+    /// # Errors
+    /// if the socket pair could not be created
+    pub fn new() -> io::Result<Self> {
+        let (writer, reader) = UnixStream::pair()?;
+        writer.set_nonblocking(true)?;
+        reader.set_nonblocking(true)?;
+        Ok(Self {
+            writer,
+            reader,
+            cleared: AtomicBool::new(false),
+        })
+    }
+
+    /// Makes the fd writable.
+    pub fn set(&self) {
+        if !self.cleared.swap(false, Relaxed) {
+            return;
+        }
+
+        let mut buf = [0u8; 0x1000];
+        loop {
+            match (&self.reader).read(&mut buf) {
+                Ok(0) => return,
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return, //WouldBlock, everything was read.
+            }
+        }
+    }
+
+    /// Makes the fd not writable anymore.
+    pub fn clear(&self) {
+        if self.cleared.swap(true, Relaxed) {
+            return;
+        }
+
+        loop {
+            match (&self.writer).write(&FILLER) {
+                Ok(0) => return,
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return, //WouldBlock, the buffer is full.
+            }
+        }
+    }
+
+    /// The fd to poll for writability.
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.writer.as_fd()
+    }
+}
+
+/// The parts of the win32 api that std does not wrap.
+#[cfg(windows)]
+#[allow(non_snake_case, clippy::upper_case_acronyms)]
+mod win32 {
+    use std::ffi::c_void;
+
+    /// win32 `BOOL`
+    pub type BOOL = i32;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        /// see `CreateEventW` in the win32 docs
+        pub fn CreateEventW(attributes: *const c_void, manual_reset: BOOL, initial_state: BOOL, name: *const u16) -> *mut c_void;
+        /// see `SetEvent` in the win32 docs
+        pub fn SetEvent(event: *mut c_void) -> BOOL;
+        /// see `ResetEvent` in the win32 docs
+        pub fn ResetEvent(event: *mut c_void) -> BOOL;
+    }
+}
+
+/// Manual-reset event that is signaled while the signal is set.
+#[cfg(windows)]
+#[derive(Debug)]
+pub struct Readiness {
+    /// The event, closed on drop.
+    event: OwnedHandle,
+}
+
+#[cfg(windows)]
+impl Readiness {
+    /// Creates a cleared signal.
+    /// # Errors
+    /// if the event could not be created
+    pub fn new() -> io::Result<Self> {
+        // SAFETY: all pointers are null, which asks for an unnamed event with default security.
+        let event = unsafe { win32::CreateEventW(std::ptr::null(), 1, 0, std::ptr::null()) };
+        if event.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the handle was just created and is owned by nothing else.
+        let event = unsafe { OwnedHandle::from_raw_handle(event) };
+        Ok(Self { event })
+    }
+
+    /// Signals the event.
+    pub fn set(&self) {
+        // SAFETY: the handle is a valid event until self is dropped. Failing leaves the event as it was.
+        _ = unsafe { win32::SetEvent(self.event.as_raw_handle()) };
+    }
+
+    /// Resets the event.
+    pub fn clear(&self) {
+        // SAFETY: the handle is a valid event until self is dropped. Failing leaves the event as it was.
+        _ = unsafe { win32::ResetEvent(self.event.as_raw_handle()) };
+    }
+
+    /// The event to wait for.
+    pub fn handle(&self) -> RawHandle {
+        self.event.as_raw_handle()
+    }
+}
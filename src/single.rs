@@ -0,0 +1,141 @@
+//! Duplex stream that does all its io on the calling thread.
+use crate::connection::TlsStream;
+use crate::TlsConnection;
+use std::io;
+use std::io::{IoSlice, IoSliceMut, Read, Write};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// Separate read and write halves of a transport joined into one `Read + Write` object.
+#[derive(Debug)]
+struct Transport<R, W> {
+    /// Where ciphertext is read from.
+    read: R,
+    /// Where ciphertext is written to.
+    write: W,
+}
+
+impl<R: Read, W> Read for Transport<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        self.read.read_vectored(bufs)
+    }
+}
+
+impl<R, W: Write> Write for Transport<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.write.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write.flush()
+    }
+}
+
+///
+/// Duplex stream that spawns no threads and performs all transport io on the thread that reads or writes.
+///
+/// The connection buffers outgoing and incoming ciphertext separately. Every read and write first hands
+/// outgoing records to `W`, so a read never waits for the peer while the peer waits for us,
+/// and only then reads from `R` for as long as no plaintext is available.
+///
+/// The trade-offs compared to `RustTlsDuplexStream`:
+/// * No background threads, queues or locks, so nothing is spawned per connection and nothing is copied twice.
+/// * Reading and writing need `&mut self` and this is not `Send`, so both directions are driven from one thread.
+///   A blocking read blocks writes until the peer sends something, use a read timeout on `R` or only read
+///   once your own io loop knows that data arrived.
+/// * Control messages like key updates are only answered while the caller reads or writes.
+/// * None of the timeouts, watermarks, cancellation or readiness notifications of `RustTlsDuplexStream` exist,
+///   configure `R` and `W` directly instead.
+///
+#[derive(Debug)]
+pub struct SingleThreadedDuplexStream<C, R, W>
+where
+    C: TlsConnection,
+    R: Read,
+    W: Write,
+{
+    /// The connection together with both transport halves.
+    inner: TlsStream<C, Transport<R, W>>,
+    /// Makes this `!Send` and `!Sync`, it is meant to stay on the thread that drives it.
+    _not_send: PhantomData<Rc<()>>,
+}
+
+impl<C, R, W> SingleThreadedDuplexStream<C, R, W>
+where
+    C: TlsConnection,
+    R: Read,
+    W: Write,
+{
+    /// Constructor for `SingleThreadedDuplexStream`. The handshake is driven by the first read, write or flush.
+    pub const fn new(con: C, read: R, write: W) -> Self {
+        Self {
+            inner: TlsStream::new(con, Transport { read, write }),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Returns the rust-tls connection.
+    pub const fn connection(&self) -> &C {
+        &self.inner.conn
+    }
+
+    /// Returns the rust-tls connection mutably.
+    /// Records queued through it, i.e. by `send_close_notify`, are written by the next write or flush.
+    pub const fn connection_mut(&mut self) -> &mut C {
+        &mut self.inner.conn
+    }
+
+    /// Returns the transport halves.
+    pub const fn get_ref(&self) -> (&R, &W) {
+        (&self.inner.sock.read, &self.inner.sock.write)
+    }
+
+    /// Returns the transport halves mutably, reading or writing them directly corrupts the tls session.
+    pub const fn get_mut(&mut self) -> (&mut R, &mut W) {
+        (&mut self.inner.sock.read, &mut self.inner.sock.write)
+    }
+
+    /// Returns the connection and both transport halves. Records that were not written yet stay in the connection.
+    pub fn into_inner(self) -> (C, R, W) {
+        (self.inner.conn, self.inner.sock.read, self.inner.sock.write)
+    }
+}
+
+impl<C, R, W> Read for SingleThreadedDuplexStream<C, R, W>
+where
+    C: TlsConnection,
+    R: Read,
+    W: Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<C, R, W> Write for SingleThreadedDuplexStream<C, R, W>
+where
+    C: TlsConnection,
+    R: Read,
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.inner.sock.flush()
+    }
+}
@@ -0,0 +1,220 @@
+//! Owned read and write halves of a stream.
+use crate::{check_timeout, deadline_after, RustTlsDuplexStream, TlsConnection};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::io::{IoSlice, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Read half of a `RustTlsDuplexStream`, see `RustTlsDuplexStream::split`.
+pub struct OwnedReadHalf<C>
+where
+    C: TlsConnection,
+{
+    /// The shared stream.
+    stream: Arc<RustTlsDuplexStream<C>>,
+    /// Read timeout of this half.
+    timeout: Option<Duration>,
+}
+
+impl<C> Debug for OwnedReadHalf<C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OwnedReadHalf")
+            .field("stream", &self.stream)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Write half of a `RustTlsDuplexStream`, see `RustTlsDuplexStream::split`.
+pub struct OwnedWriteHalf<C>
+where
+    C: TlsConnection,
+{
+    /// The shared stream.
+    stream: Arc<RustTlsDuplexStream<C>>,
+    /// Write timeout of this half.
+    timeout: Option<Duration>,
+}
+
+impl<C> Debug for OwnedWriteHalf<C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OwnedWriteHalf")
+            .field("stream", &self.stream)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Error returned by `OwnedReadHalf::reunite` if the halves do not belong to the same stream.
+/// Both halves are returned unchanged.
+pub struct ReuniteError<C>(pub OwnedReadHalf<C>, pub OwnedWriteHalf<C>)
+where
+    C: TlsConnection;
+
+impl<C> Debug for ReuniteError<C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ReuniteError")
+            .field(&self.0.stream.connection_id())
+            .field(&self.1.stream.connection_id())
+            .finish()
+    }
+}
+
+impl<C> Display for ReuniteError<C>
+where
+    C: TlsConnection,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("tried to reunite halves that are not from the same stream")
+    }
+}
+
+impl<C> Error for ReuniteError<C> where C: TlsConnection {}
+
+/// Splits the stream, the stored timeouts move into the halves.
+pub fn split<C>(stream: RustTlsDuplexStream<C>) -> io::Result<(OwnedReadHalf<C>, OwnedWriteHalf<C>)>
+where
+    C: TlsConnection,
+{
+    let read_timeout = stream.read_timeout()?;
+    let write_timeout = stream.write_timeout()?;
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    let stream = Arc::new(stream);
+    Ok((
+        OwnedReadHalf {
+            stream: Arc::clone(&stream),
+            timeout: read_timeout,
+        },
+        OwnedWriteHalf {
+            stream,
+            timeout: write_timeout,
+        },
+    ))
+}
+
+impl<C> OwnedReadHalf<C>
+where
+    C: TlsConnection,
+{
+    /// Joins both halves back into the original stream, the timeouts of the halves become the stored timeouts.
+    /// # Errors
+    /// `ReuniteError` if the halves belong to different streams.
+    pub fn reunite(self, write: OwnedWriteHalf<C>) -> Result<RustTlsDuplexStream<C>, ReuniteError<C>> {
+        if !Arc::ptr_eq(&self.stream, &write.stream) {
+            return Err(ReuniteError(self, write));
+        }
+
+        let Self { stream, timeout } = self;
+        let write_timeout = write.timeout;
+        drop(write);
+        let Ok(stream) = Arc::try_unwrap(stream) else {
+            unreachable!("both halves were given, no other reference can exist");
+        };
+        //The mutexes were never poisoned by us, if someone else did it the stream is not usable anyway.
+        _ = stream.set_read_timeout(timeout);
+        _ = stream.set_write_timeout(write_timeout);
+        Ok(stream)
+    }
+
+    /// Returns the underlying stream.
+    #[must_use]
+    pub fn get_ref(&self) -> &RustTlsDuplexStream<C> {
+        &self.stream
+    }
+
+    /// sets the read timeout of this half, see `RustTlsDuplexStream::set_read_timeout`
+    /// # Errors
+    /// `InvalidInput` if the timeout is zero
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the read timeout of this half.
+    #[must_use]
+    pub const fn read_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// see `RustTlsDuplexStream::set_read_non_block`
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn set_read_non_block(&self, on: bool) -> io::Result<()> {
+        self.stream.set_read_non_block(on)
+    }
+}
+
+impl<C> Read for OwnedReadHalf<C>
+where
+    C: TlsConnection,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.timeout {
+            Some(timeout) => self.stream.read_before(buf, deadline_after(timeout)?),
+            None => self.stream.read(buf),
+        }
+    }
+}
+
+impl<C> OwnedWriteHalf<C>
+where
+    C: TlsConnection,
+{
+    /// Returns the underlying stream.
+    #[must_use]
+    pub fn get_ref(&self) -> &RustTlsDuplexStream<C> {
+        &self.stream
+    }
+
+    /// sets the write timeout of this half, see `RustTlsDuplexStream::set_write_timeout`
+    /// # Errors
+    /// `InvalidInput` if the timeout is zero
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.timeout = check_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Returns the write timeout of this half.
+    #[must_use]
+    pub const fn write_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+impl<C> Write for OwnedWriteHalf<C>
+where
+    C: TlsConnection,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.timeout {
+            Some(timeout) => self.stream.write_before(buf, deadline_after(timeout)?),
+            None => self.stream.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match self.timeout {
+            Some(_) => {
+                let buf = bufs.iter().find(|buf| !buf.is_empty()).map_or(&[][..], |buf| &**buf);
+                self.write(buf)
+            }
+            None => self.stream.write_vectored(bufs),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush_with_timeout(self.timeout)
+    }
+}
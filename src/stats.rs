@@ -0,0 +1,130 @@
+//! Cumulative plaintext counters of a stream.
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+use std::time::{Duration, Instant, SystemTime};
+
+///
+/// Snapshot of the cumulative counters of a stream, see `RustTlsDuplexStream::stats`.
+///
+/// The byte counters count plaintext as it was taken from and handed to rust-tls,
+/// unlike `ConnectionInfo` which counts ciphertext. The counters are not reset by `reconnect`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStats {
+    /// Total amount of plaintext bytes read, peeked bytes count once they were peeked.
+    pub bytes_read: u64,
+    /// Total amount of plaintext bytes rust-tls accepted from writes.
+    pub bytes_written: u64,
+    /// Amount of reads, helpers like `read_exact` count every read they make.
+    pub reads: u64,
+    /// Amount of writes, helpers like `write_all` count every write they make.
+    pub writes: u64,
+    /// When the stream was created.
+    pub created_at: SystemTime,
+}
+
+/// Counters behind `StreamStats`, updated on the hot paths without taking any lock.
+#[derive(Debug)]
+pub struct StatCounters {
+    /// See `StreamStats::bytes_read`.
+    bytes_read: AtomicU64,
+    /// See `StreamStats::bytes_written`.
+    bytes_written: AtomicU64,
+    /// See `StreamStats::reads`.
+    reads: AtomicU64,
+    /// See `StreamStats::writes`.
+    writes: AtomicU64,
+    /// See `StreamStats::created_at`.
+    created_at: SystemTime,
+    /// Monotonic counterpart of `created_at`, the activity timestamps are relative to it.
+    started: Instant,
+    /// Milliseconds after `started` when plaintext was last read.
+    last_read: AtomicU64,
+    /// Milliseconds after `started` when plaintext was last written.
+    last_write: AtomicU64,
+}
+
+impl Default for StatCounters {
+    fn default() -> Self {
+        Self {
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            reads: AtomicU64::new(0),
+            writes: AtomicU64::new(0),
+            created_at: SystemTime::now(),
+            started: Instant::now(),
+            last_read: AtomicU64::new(0),
+            last_write: AtomicU64::new(0),
+        }
+    }
+}
+
+impl StatCounters {
+    /// Counts 1 read.
+    pub fn read_call(&self) {
+        self.reads.fetch_add(1, Relaxed);
+    }
+
+    /// Counts 1 write.
+    pub fn write_call(&self) {
+        self.writes.fetch_add(1, Relaxed);
+    }
+
+    /// Counts plaintext that was read, reading any of it is read activity.
+    pub fn read_bytes(&self, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        self.bytes_read.fetch_add(count as u64, Relaxed);
+        self.last_read.fetch_max(self.now(), Relaxed);
+    }
+
+    /// Counts plaintext that was written, writing any of it is write activity.
+    pub fn written_bytes(&self, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        self.bytes_written.fetch_add(count as u64, Relaxed);
+        self.last_write.fetch_max(self.now(), Relaxed);
+    }
+
+    /// Records read and write activity without plaintext, for the handshake.
+    pub fn handshake(&self) {
+        let now = self.now();
+        self.last_read.fetch_max(now, Relaxed);
+        self.last_write.fetch_max(now, Relaxed);
+    }
+
+    /// When plaintext was last read, creation counts as activity.
+    pub fn last_read(&self) -> Instant {
+        self.at(self.last_read.load(Relaxed))
+    }
+
+    /// When plaintext was last written, creation counts as activity.
+    pub fn last_write(&self) -> Instant {
+        self.at(self.last_write.load(Relaxed))
+    }
+
+    /// Milliseconds since `started`.
+    fn now(&self) -> u64 {
+        u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX)
+    }
+
+    /// The instant `millis` after `started`.
+    fn at(&self, millis: u64) -> Instant {
+        self.started + Duration::from_millis(millis)
+    }
+
+    /// Returns the current values. Each counter is read on its own, so they may be from slightly different moments.
+    pub fn snapshot(&self) -> StreamStats {
+        StreamStats {
+            bytes_read: self.bytes_read.load(Relaxed),
+            bytes_written: self.bytes_written.load(Relaxed),
+            reads: self.reads.load(Relaxed),
+            writes: self.writes.load(Relaxed),
+            created_at: self.created_at,
+        }
+    }
+}
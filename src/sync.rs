@@ -0,0 +1,79 @@
+//! Lock primitives used by the queues and the stream.
+//! With the `parking_lot` feature these are backed by `parking_lot` which never poisons,
+//! otherwise by `std::sync`. The fns in here paper over the api differences of the two.
+
+use std::io;
+use std::time::Duration;
+
+#[cfg(not(feature = "parking_lot"))]
+pub use std::sync::{Condvar, LockResult, Mutex, MutexGuard};
+
+#[cfg(feature = "parking_lot")]
+pub use parking_lot::{Condvar, Mutex, MutexGuard};
+
+/// `parking_lot` locks cannot be poisoned.
+#[cfg(feature = "parking_lot")]
+pub type LockResult<T> = T;
+
+/// Tries to acquire the lock without blocking. Returns None if it is held elsewhere.
+/// # Errors
+/// In case of poisoned mutex
+#[cfg(not(feature = "parking_lot"))]
+pub fn try_lock<T: ?Sized>(mutex: &Mutex<T>) -> io::Result<Option<MutexGuard<'_, T>>> {
+    match mutex.try_lock() {
+        Ok(guard) => Ok(Some(guard)),
+        Err(std::sync::TryLockError::WouldBlock) => Ok(None),
+        Err(std::sync::TryLockError::Poisoned(_)) => Err(crate::error::poisoned()),
+    }
+}
+
+/// Tries to acquire the lock without blocking. Returns None if it is held elsewhere.
+/// # Errors
+/// never
+#[cfg(feature = "parking_lot")]
+#[allow(clippy::unnecessary_wraps)]
+pub fn try_lock<T: ?Sized>(mutex: &Mutex<T>) -> io::Result<Option<MutexGuard<'_, T>>> {
+    Ok(mutex.try_lock())
+}
+
+/// Blocks on the condvar until notified.
+#[cfg(not(feature = "parking_lot"))]
+pub fn wait<'a, T>(cond: &Condvar, guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+    cond.wait(guard)
+}
+
+/// Blocks on the condvar until notified.
+#[cfg(feature = "parking_lot")]
+pub fn wait<'a, T>(cond: &Condvar, mut guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+    cond.wait(&mut guard);
+    guard
+}
+
+/// Blocks on the condvar until notified or the duration has elapsed.
+/// The returned bool is true if the wait timed out.
+#[cfg(not(feature = "parking_lot"))]
+pub fn wait_timeout<'a, T>(
+    cond: &Condvar,
+    guard: MutexGuard<'a, T>,
+    duration: Duration,
+) -> LockResult<(MutexGuard<'a, T>, bool)> {
+    match cond.wait_timeout(guard, duration) {
+        Ok((guard, result)) => Ok((guard, result.timed_out())),
+        Err(err) => {
+            let (guard, result) = err.into_inner();
+            Err(std::sync::PoisonError::new((guard, result.timed_out())))
+        }
+    }
+}
+
+/// Blocks on the condvar until notified or the duration has elapsed.
+/// The returned bool is true if the wait timed out.
+#[cfg(feature = "parking_lot")]
+pub fn wait_timeout<'a, T>(
+    cond: &Condvar,
+    mut guard: MutexGuard<'a, T>,
+    duration: Duration,
+) -> LockResult<(MutexGuard<'a, T>, bool)> {
+    let result = cond.wait_for(&mut guard, duration);
+    (guard, result.timed_out())
+}
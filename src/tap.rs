@@ -0,0 +1,66 @@
+//! Callbacks that observe the plaintext of a stream.
+use crate::sync::Mutex;
+use crate::unwrap_poison;
+use std::fmt::{Debug, Formatter};
+use std::io::IoSlice;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+
+/// Callback that is handed plaintext, see `RustTlsDuplexStream::set_read_tap` and `RustTlsDuplexStream::set_write_tap`.
+pub type Tap = Box<dyn Fn(&[u8]) + Send + Sync>;
+
+/// Slot for an optional tap.
+#[derive(Default)]
+pub struct TapSlot {
+    /// The tap, cleared once it panicked.
+    tap: Mutex<Option<Tap>>,
+}
+
+impl Debug for TapSlot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let set = unwrap_poison(self.tap.lock()).map(|tap| tap.is_some());
+        f.debug_struct("TapSlot").field("set", &set.ok()).finish()
+    }
+}
+
+impl TapSlot {
+    /// Replaces the tap.
+    pub fn set(&self, tap: Option<Tap>) {
+        if let Ok(mut guard) = unwrap_poison(self.tap.lock()) {
+            *guard = tap;
+        }
+    }
+
+    /// Hands the data to the tap if one is set. A tap that panics is cleared.
+    pub fn call(&self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        let Ok(mut guard) = unwrap_poison(self.tap.lock()) else {
+            return;
+        };
+
+        let Some(tap) = guard.as_ref() else {
+            return;
+        };
+
+        if panic::catch_unwind(AssertUnwindSafe(|| tap(data))).is_err() {
+            warn!("tap panicked, it was removed");
+            *guard = None;
+        }
+    }
+
+    /// Hands the first `count` bytes of the slices to the tap, one call per slice.
+    pub fn call_vectored(&self, bufs: &[IoSlice<'_>], mut count: usize) {
+        for buf in bufs {
+            if count == 0 {
+                return;
+            }
+
+            let len = buf.len().min(count);
+            self.call(&buf[..len]);
+            count -= len;
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! In memory transport for tests that should not depend on real sockets.
+use crate::sync::{Condvar, Mutex};
+use crate::{sync, unwrap_poison};
+use std::collections::VecDeque;
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::Arc;
+
+/// Creates 2 connected in memory streams.
+///
+/// Everything written to one stream can be read from the other one.
+/// A stream reads EOF and fails to write once all clones of the other stream have been dropped or `shutdown` was called.
+#[must_use]
+pub fn in_memory_pair() -> (MemoryStream, MemoryStream) {
+    let left = Arc::new(MemoryPipe::default());
+    let right = Arc::new(MemoryPipe::default());
+    (
+        MemoryStream {
+            read: Arc::new(PipeHandle(Arc::clone(&left))),
+            write: Arc::new(PipeHandle(Arc::clone(&right))),
+        },
+        MemoryStream {
+            read: Arc::new(PipeHandle(right)),
+            write: Arc::new(PipeHandle(left)),
+        },
+    )
+}
+
+/// One direction of the in memory transport.
+#[derive(Debug, Default)]
+struct MemoryPipe {
+    /// Buffered bytes and closed flag.
+    state: Mutex<(VecDeque<u8>, bool)>,
+    /// Condition for when state changes.
+    cond: Condvar,
+}
+
+impl MemoryPipe {
+    /// Marks the pipe as closed, readers will see EOF once the buffer is drained.
+    fn close(&self) {
+        if let Ok(mut guard) = unwrap_poison(self.state.lock()) {
+            guard.1 = true;
+            self.cond.notify_all();
+        }
+    }
+}
+
+/// Closes the pipe once the last clone of a stream is dropped.
+#[derive(Debug)]
+struct PipeHandle(Arc<MemoryPipe>);
+
+impl Drop for PipeHandle {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Blocking in memory stream, cloning it is cheap and behaves like `TcpStream::try_clone`.
+#[derive(Debug, Clone)]
+pub struct MemoryStream {
+    /// The pipe we read from.
+    read: Arc<PipeHandle>,
+    /// The pipe we write to.
+    write: Arc<PipeHandle>,
+}
+
+impl MemoryStream {
+    /// Closes both directions, like `TcpStream::shutdown(Shutdown::Both)`.
+    pub fn shutdown(&self) {
+        self.read.0.close();
+        self.write.0.close();
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut guard = unwrap_poison(self.read.0.state.lock())?;
+        while guard.0.is_empty() {
+            if guard.1 {
+                return Ok(0);
+            }
+            guard = unwrap_poison(sync::wait(&self.read.0.cond, guard))?;
+        }
+
+        let count = guard.0.len().min(buf.len());
+        for (dst, src) in buf.iter_mut().zip(guard.0.drain(..count)) {
+            *dst = src;
+        }
+        drop(guard);
+        Ok(count)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut guard = unwrap_poison(self.write.0.state.lock())?;
+        if guard.1 {
+            return Err(io::Error::from(ErrorKind::BrokenPipe));
+        }
+        guard.0.extend(buf);
+        self.write.0.cond.notify_all();
+        drop(guard);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+//! Wakers that bridge the blocking queues to async executors.
+use crate::sync::Mutex;
+use crate::unwrap_poison;
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::task::Waker;
+
+/// Slot for the most recently registered waker.
+#[derive(Debug)]
+pub struct WakerSlot {
+    /// The waker, taken once it is woken.
+    waker: Mutex<Option<Waker>>,
+    /// Set while holding the buffer mutex of the queue, the waker is woken once that mutex was released.
+    due: AtomicBool,
+}
+
+impl WakerSlot {
+    /// Constructor for an empty slot.
+    pub const fn new() -> Self {
+        Self {
+            waker: Mutex::new(None),
+            due: AtomicBool::new(false),
+        }
+    }
+
+    /// Replaces the waker, only the most recent one is woken.
+    pub fn register(&self, waker: Waker) -> io::Result<()> {
+        let mut guard = unwrap_poison(self.waker.lock())?;
+        if !guard.as_ref().is_some_and(|old| old.will_wake(&waker)) {
+            *guard = Some(waker);
+        }
+        drop(guard);
+        Ok(())
+    }
+
+    /// Replaces the waker without waking it, even if it is due.
+    #[cfg(feature = "polling")]
+    pub fn replace(&self, waker: Option<Waker>) -> io::Result<()> {
+        *unwrap_poison(self.waker.lock())? = waker;
+        Ok(())
+    }
+
+    /// Marks the waker to be woken by the next `wake_if_due`.
+    pub fn mark(&self) {
+        self.due.store(true, SeqCst);
+    }
+
+    /// Wakes and removes the waker if it was marked. Must not be called while holding any lock.
+    pub fn wake_if_due(&self) {
+        if !self.due.swap(false, SeqCst) {
+            return;
+        }
+
+        let Ok(mut guard) = unwrap_poison(self.waker.lock()) else {
+            return;
+        };
+
+        let waker = guard.take();
+        drop(guard);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
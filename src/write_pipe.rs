@@ -1,39 +1,323 @@
 //! Background queued writer.
-use crate::queue::Queue;
+use crate::cancel::canceled;
+use crate::error::queue_dead;
+use crate::events::EventSlot;
+use crate::observer::ObserverSlot;
+use crate::queue::{Element, Queue, POLL_INTERVAL};
+use crate::remaining;
+use crate::waker::WakerSlot;
 use defer_heavy::defer;
 use std::io;
-use std::io::{ErrorKind, Write};
-use std::sync::{Arc, OnceLock};
+use std::io::{ErrorKind, IoSlice, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{mpsc, Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Max amount of bytes that are coalesced into a single write of the underlying writer.
+const MAX_COALESCED: usize = 0x1_0000;
+
+/// Element of the write queue.
+/// There is only one queue on purpose: every record carries the next sequence number of the connection,
+/// so a record that overtakes queued ones (i.e. a `close_notify` alert) would fail to decrypt at the peer.
+#[derive(Debug)]
+pub enum WriteOp {
+    /// Ciphertext for the underlying writer.
+    Data(Vec<u8>),
+    /// Flush the underlying writer, the waiter (if any) is notified once that succeeded.
+    Flush(Option<Done>),
+    /// Notify the sender once everything queued before was written.
+    Sync(mpsc::Sender<()>),
+}
+
+impl Element for WriteOp {
+    fn byte_len(&self) -> usize {
+        match self {
+            Self::Data(data) => data.len(),
+            Self::Flush(_) | Self::Sync(_) => 0,
+        }
+    }
+}
+
+/// Waiter of a marker. Dropping it without `send` tells the waiter that the background writer died.
+#[derive(Debug)]
+pub enum Done {
+    /// A thread that blocks on the receiving end.
+    Thread(mpsc::Sender<()>),
+    /// A task that polls the flush.
+    #[cfg_attr(not(feature = "futures-io"), allow(dead_code))]
+    Task(Arc<TaskFlush>),
+}
+
+impl Done {
+    /// Notifies the waiter that the marker was handled.
+    fn send(self) {
+        match &self {
+            //The caller may have given up already.
+            Self::Thread(sender) => _ = sender.send(()),
+            Self::Task(flush) => flush.handled.store(true, SeqCst),
+        }
+    }
+}
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        if let Self::Task(flush) = self {
+            flush.finished.store(true, SeqCst);
+            flush.waker.mark();
+            flush.waker.wake_if_due();
+        }
+    }
+}
+
+/// Flush of the underlying writer that is awaited by a task instead of a thread.
+#[derive(Debug)]
+pub struct TaskFlush {
+    /// Set once the background writer flushed.
+    handled: AtomicBool,
+    /// Set once the marker is gone, either handled or dropped because the background writer died.
+    finished: AtomicBool,
+    /// Woken once finished is set.
+    waker: WakerSlot,
+}
+
+#[cfg_attr(not(feature = "futures-io"), allow(dead_code))]
+impl TaskFlush {
+    /// Constructor for a flush that was not queued yet.
+    pub const fn new() -> Self {
+        Self {
+            handled: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            waker: WakerSlot::new(),
+        }
+    }
+
+    /// Returns whether the flush succeeded once it finished, registers the waker of `cx` until then.
+    /// # Errors
+    /// In case of poisoned mutex
+    pub fn poll(&self, cx: &Context<'_>) -> io::Result<Poll<bool>> {
+        if !self.finished.load(SeqCst) {
+            self.waker.register(cx.waker().clone())?;
+            //The marker may have finished before the waker was registered.
+            if !self.finished.load(SeqCst) {
+                return Ok(Poll::Pending);
+            }
+        }
+
+        Ok(Poll::Ready(self.handled.load(SeqCst)))
+    }
+}
+
+/// Queues a flush of the underlying writer behind everything that is already queued and waits until it succeeded.
+/// The flush stays queued if this gives up with `TimedOut`.
+/// Returns `BrokenPipe` if the background writer died before that.
+pub fn flush_transport(queue: &Queue<WriteOp>, deadline: Option<Instant>, cancel: &AtomicBool) -> io::Result<()> {
+    await_marker(queue, |done| WriteOp::Flush(Some(Done::Thread(done))), deadline, cancel)
+}
+
+/// Waits until everything that is already queued was written to the underlying writer.
+/// Returns `BrokenPipe` if the background writer died before that.
+pub fn sync(queue: &Queue<WriteOp>, deadline: Option<Instant>) -> io::Result<()> {
+    await_marker(queue, WriteOp::Sync, deadline, &AtomicBool::new(false))
+}
+
+/// Queues a marker and waits until the background writer handled it.
+/// Gives up with `DuplexStreamError::Canceled` once the cancellation token is set.
+fn await_marker(
+    queue: &Queue<WriteOp>,
+    marker: impl FnOnce(mpsc::Sender<()>) -> WriteOp,
+    deadline: Option<Instant>,
+    cancel: &AtomicBool,
+) -> io::Result<()> {
+    let (done, handled) = mpsc::channel();
+    queue
+        .push_before_cancelable(marker(done), deadline, cancel)
+        .map_err(cancel_err)?;
+    loop {
+        //The channel cannot be woken by the token, so it is polled.
+        let wait = match deadline {
+            Some(deadline) => remaining(deadline)?.min(POLL_INTERVAL),
+            None => POLL_INTERVAL,
+        };
+
+        match handled.recv_timeout(wait) {
+            Ok(()) => return Ok(()),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::new(ErrorKind::BrokenPipe, "background writer died"))
+            }
+        }
+
+        if cancel.load(SeqCst) {
+            return Err(canceled());
+        }
+    }
+}
+
+/// The queue reports a set cancellation token as `Interrupted`, callers of the stream get `Canceled` instead.
+fn cancel_err(err: io::Error) -> io::Error {
+    if err.kind() == ErrorKind::Interrupted {
+        return canceled();
+    }
+
+    err
+}
+
+/// Settings of a `WritePipe`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WritePipeConfig {
+    /// How long to wait for more data before writing, None writes every element on its own.
+    pub coalesce_window: Option<Duration>,
+    /// Flush the underlying writer after every write.
+    pub auto_flush: bool,
+    /// Flush the underlying writer after this many writes, 0 and 1 flush after every write.
+    pub flush_every_n_messages: Option<usize>,
+}
+
+impl WritePipeConfig {
+    /// Returns true if the underlying writer has to be flushed after `unflushed` writes since the last flush.
+    fn flush_due(&self, unflushed: usize) -> bool {
+        self.auto_flush || self.flush_every_n_messages.is_some_and(|every| unflushed >= every)
+    }
+}
 
 /// Write pipe inner state
 #[derive(Debug, Default)]
 struct WritePipeInner {
+    /// Id of the stream this pipe belongs to.
+    id: u64,
     /// The actual data queue.
-    queue: Arc<Queue>,
-    /// Async error
-    error: OnceLock<ErrorKind>,
+    queue: Arc<Queue<WriteOp>>,
+    /// Async error, this is the original error of the underlying write.
+    error: OnceLock<Arc<io::Error>>,
+    /// Coalescing and flushing of the background thread.
+    config: WritePipeConfig,
+    /// Receives errors of the underlying writer, shared with the stream and the read pipe.
+    events: Arc<EventSlot>,
+    /// Receives the depth of the queue after every pop, shared with the stream.
+    observer: Arc<ObserverSlot>,
 }
 
 impl WritePipeInner {
 
+    /// Spawns the background thread, the returned channel disconnects once it ended.
+    /// The queue is killed if the spawn fails.
+    fn start<W: Write + Send + 'static, T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>>(
+        id: u64,
+        queue: Arc<Queue<WriteOp>>,
+        config: WritePipeConfig,
+        events: Arc<EventSlot>,
+        observer: Arc<ObserverSlot>,
+        write: W,
+        spawner: &mut T,
+    ) -> io::Result<(Arc<Self>, mpsc::Receiver<()>)> {
+        let wp = Arc::new(Self {
+            id,
+            queue,
+            config,
+            events,
+            observer,
+            ..Self::default()
+        });
+        let (running, finished) = mpsc::channel::<()>();
+        let wpc = Arc::clone(&wp);
+        if let Err(err) = spawner(Box::new(move || {
+            let _running = running;
+            wpc.handle(write);
+        })) {
+            wp.queue.kill("spawning the background writer failed");
+            return Err(err);
+        }
+        Ok((wp, finished))
+    }
+
+    /// Collects data that is queued within the coalesce window into `data`.
+    /// Returns a marker that ended the collection, it must be handled after `data` was written.
+    /// A dead queue ends the collection, the next pop reports it.
+    fn coalesce(&self, data: &mut Vec<u8>, window: Duration) -> Option<WriteOp> {
+        let deadline = Instant::now().checked_add(window)?;
+        while data.len() < MAX_COALESCED {
+            match self.queue.pop_before(deadline) {
+                Ok(Some(WriteOp::Data(more))) => data.extend_from_slice(&more),
+                Ok(Some(marker)) => return Some(marker),
+                Ok(None) | Err(_) => return None,
+            }
+        }
+        None
+    }
+
     /// Background write handler thread loop.
     fn handle<T: Write + Send>(&self, mut write: T) {
+        enter_span!("tls-write", id = self.id);
+        debug!(id = self.id, "background writer started");
         defer! {
             // This also happens on panic!
-            self.queue.kill();
+            self.queue.kill("background writer ended");
+            debug!(id = self.id, "background writer stopped");
+            // Dropping queued markers wakes their waiters, the error is already stored at this point.
+            drop(self.queue.drain());
         }
+        let mut next = None;
+        //Writes since the underlying writer was last flushed.
+        let mut unflushed = 0;
         loop {
-            let pop = match self.queue.pop() {
+            let pop = match next.take().map_or_else(|| self.queue.pop(), Ok) {
                 Ok(guard) => guard,
                 Err(e) => {
-                    _ = self.error.set(e.kind());
+                    _ = self.error.set(Arc::new(e));
                     return;
                 }
             };
+            if self.queue.take_room_regained() {
+                self.events.write_capacity();
+            }
+            if self.observer.is_set() {
+                if let Ok(depth) = self.queue.len() {
+                    self.observer.queue_depth(depth);
+                }
+            }
 
-            if let Err(err) = write.write_all(pop.as_slice()) {
-                _ = self.error.set(err.kind());
-                return;
+            // The marker of a failed flush is dropped only after the error is stored, its waiter then reads it.
+            let mut done = None;
+            let res = match pop {
+                WriteOp::Data(mut data) => {
+                    if let Some(window) = self.config.coalesce_window {
+                        next = self.coalesce(&mut data, window);
+                    }
+                    unflushed += 1;
+                    let res = write.write_all(data.as_slice());
+                    if res.is_ok() && self.config.flush_due(unflushed) {
+                        unflushed = 0;
+                        write.flush()
+                    } else {
+                        res
+                    }
+                }
+                WriteOp::Flush(marker) => {
+                    done = marker;
+                    unflushed = 0;
+                    write.flush()
+                }
+                WriteOp::Sync(marker) => {
+                    done = Some(Done::Thread(marker));
+                    Ok(())
+                }
+            };
+
+            match res {
+                Ok(()) => {
+                    if let Some(done) = done {
+                        done.send();
+                    }
+                }
+                Err(err) => {
+                    error!(id = self.id, kind = ?err.kind(), error = %err, "background write failed");
+                    self.events.error(&err);
+                    _ = self.error.set(Arc::new(err));
+                    return;
+                }
             }
         }
     }
@@ -43,11 +327,24 @@ impl WritePipeInner {
 /// fake write impl that will push to a queue and try to return immediately. 
 /// Writes are deferred to a background thread.
 #[derive(Debug)]
-pub struct WritePipe(Arc<WritePipeInner>);
+pub struct WritePipe {
+    /// The background queue part.
+    pipe: Arc<WritePipeInner>,
+    /// Deadline for pushing onto a full queue, None means block forever.
+    deadline: Option<Instant>,
+    /// Non-blocking marker, if the queue is full then we do not block on it.
+    nb: bool,
+    /// Set to make pushes onto a full queue give up, shared with the stream.
+    canceled: Arc<AtomicBool>,
+    /// Set if data was queued since the last flush.
+    dirty: bool,
+    /// Disconnects once the background thread ended.
+    finished: mpsc::Receiver<()>,
+}
 
 impl Drop for WritePipe {
     fn drop(&mut self) {
-        self.0.queue.kill();
+        self.pipe.queue.kill("stream dropped");
     }
 }
 
@@ -55,46 +352,435 @@ impl WritePipe {
     /// Constructor that starts the background thread.
     pub fn new<W: Write + Send + 'static, T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>>(
         write: W,
+        id: u64,
+        queue: Queue<WriteOp>,
+        config: WritePipeConfig,
+        events: Arc<EventSlot>,
+        observer: Arc<ObserverSlot>,
         spawner: &mut T,
     ) -> io::Result<Self> {
-        let wp = Arc::new(WritePipeInner::default());
-        let wpc = Arc::clone(&wp);
-        spawner(Box::new(move || {
-            wpc.handle(write);
-        }))?;
-        Ok(Self(wp))
+        let (pipe, finished) = WritePipeInner::start(id, Arc::new(queue), config, events, observer, write, spawner)?;
+        Ok(Self {
+            pipe,
+            deadline: None,
+            nb: false,
+            canceled: Arc::default(),
+            dirty: false,
+            finished,
+        })
+    }
+
+    /// Replaces the underlying writer, the queue is reused.
+    /// Kills the queue and waits until the background thread of the old writer ended, then discards
+    /// whatever it did not write and spawns it again for the new writer.
+    /// # Errors
+    /// propagated from the spawner fn, the queue stays dead then. In case of poisoned mutex
+    pub fn replace_transport<W: Write + Send + 'static, T: FnMut(Box<dyn FnOnce() + Send>) -> io::Result<()>>(
+        &mut self,
+        write: W,
+        spawner: &mut T,
+    ) -> io::Result<()> {
+        self.pipe.queue.kill("transport replaced");
+        //Nothing is ever sent, this returns once the task dropped its sender.
+        _ = self.finished.recv();
+        //Dropping queued markers wakes their waiters.
+        drop(self.pipe.queue.drain()?);
+        self.dirty = false;
+
+        let queue = Arc::clone(&self.pipe.queue);
+        queue.revive()?;
+        let events = Arc::clone(&self.pipe.events);
+        let observer = Arc::clone(&self.pipe.observer);
+        let (pipe, finished) =
+            WritePipeInner::start(self.pipe.id, queue, self.pipe.config, events, observer, write, spawner)?;
+        self.pipe = pipe;
+        self.finished = finished;
+        Ok(())
+    }
+
+    /// sets the deadline for pushing onto a full queue.
+    /// All pushes until it is reset share it, so a single write that produces several records cannot exceed it.
+    pub const fn deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// is nb on?
+    pub const fn nb(&mut self, value: bool) {
+        self.nb = value;
+    }
+
+    /// get a handle to the cancellation token of pushes onto a full queue.
+    pub fn dup_cancel(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.canceled)
+    }
+
+    /// Returns the observer slot shared with the background thread.
+    pub fn dup_observer(&self) -> Arc<ObserverSlot> {
+        Arc::clone(&self.pipe.observer)
     }
 
     /// get a handle to the internal queue.
-    pub fn dup_queue(&self) -> Arc<Queue> {
-        Arc::clone(&self.0.queue)
+    pub fn dup_queue(&self) -> Arc<Queue<WriteOp>> {
+        Arc::clone(&self.pipe.queue)
     }
 
     /// util to get the error. All errors are treated as fatal.
-    /// if this fn is called when there is no error it will set the error to `BrokenPipe`.
+    /// Returns the original error of the underlying write if there is one, otherwise `BrokenPipe`.
     /// and kill the background thread.
-    fn fetch_err(&self) -> io::Error {
-        self.0.queue.kill();
-        if let Some(err) = self.0.error.get().copied() {
-            return io::Error::from(err);
+    pub fn fetch_err(&self) -> io::Error {
+        self.pipe.queue.kill("connection failed");
+        if let Some(err) = self.pipe.error.get() {
+            return io::Error::new(err.kind(), Arc::clone(err));
         }
-        io::Error::from(ErrorKind::BrokenPipe)
+        queue_dead()
     }
-}
 
-impl Write for WritePipe {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match self.0.queue.push(buf.to_vec()) {
-            Ok(()) => Ok(buf.len()),
+    /// Pushes onto the queue honoring the non-blocking marker and the deadline.
+    fn push(&mut self, data: WriteOp) -> io::Result<()> {
+        let res = if self.nb {
+            self.pipe.queue.try_push(data)
+        } else {
+            self.pipe
+                .queue
+                .push_before_cancelable(data, self.deadline, &self.canceled)
+                .map_err(cancel_err)
+        };
+
+        self.pushed(res)
+    }
+
+    /// Pushes all elements at once honoring the deadline, the queue must not be non-blocking.
+    fn push_batch(&mut self, data: Vec<WriteOp>) -> io::Result<()> {
+        let res = self
+            .pipe
+            .queue
+            .batch_push_before_cancelable(data, self.deadline, &self.canceled)
+            .map_err(cancel_err);
+        self.pushed(res)
+    }
+
+    /// Handles the result of a push.
+    fn pushed(&mut self, res: io::Result<()>) -> io::Result<()> {
+        match res {
+            Ok(()) => {
+                self.dirty = true;
+                Ok(())
+            }
+            //Not fatal, rust-tls retains the data.
+            Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => Err(err),
+            Err(err) if self.canceled.load(SeqCst) => Err(err),
             Err(err) => {
-                _ = self.0.error.set(err.kind());
+                _ = self.pipe.error.set(Arc::new(err));
                 Err(self.fetch_err())
             }
         }
     }
+}
+
+impl Write for WritePipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.push(WriteOp::Data(buf.to_vec()))?;
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        if len == 0 {
+            return Ok(0);
+        }
+
+        //rust-tls hands out one record per slice. Large amounts are queued as one element per record in a single batch
+        //instead of being copied into one huge allocation, the batch is all or nothing so rust-tls can retain it on failure.
+        if len > MAX_COALESCED && !self.nb {
+            let records = bufs
+                .iter()
+                .filter(|buf| !buf.is_empty())
+                .map(|buf| WriteOp::Data(buf.to_vec()))
+                .collect();
+            self.push_batch(records)?;
+            return Ok(len);
+        }
+
+        //Single element with a single allocation so the order is preserved and the writer thread does one write.
+        let mut data = Vec::with_capacity(len);
+        for buf in bufs {
+            data.extend_from_slice(buf);
+        }
+
+        self.push(WriteOp::Data(data))?;
+        Ok(len)
+    }
 
     fn flush(&mut self) -> io::Result<()> {
-        //Not implemented on purpose as this would just stall reads.
+        //Waiting for the flush would just stall reads, so it is only queued behind the data.
+        //rust-tls flushes after every write, only queue one if there is something to flush.
+        if !self.dirty {
+            return Ok(());
+        }
+
+        self.push(WriteOp::Flush(None))?;
+        self.dirty = false;
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{flush_transport, WriteOp, WritePipe, WritePipeConfig};
+    use crate::queue::Queue;
+    use std::io;
+    use std::io::{BufWriter, IoSlice, Write};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Writer that records everything into a shared buffer.
+    #[derive(Default, Clone)]
+    struct Sink(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_vectored_order() {
+        let sink = Sink::default();
+        let mut pipe = WritePipe::new(
+            sink.clone(),
+            0,
+            Queue::default(),
+            WritePipeConfig::default(),
+            Arc::default(),
+            Arc::default(),
+            &mut |task| thread::Builder::new().spawn(task).map(|_| {}),
+        )
+        .unwrap();
+
+        pipe.write_all(b"first ").unwrap();
+        let count = pipe
+            .write_vectored(&[IoSlice::new(b"header "), IoSlice::new(b""), IoSlice::new(b"body ")])
+            .unwrap();
+        assert_eq!(count, 12);
+        assert_eq!(pipe.write_vectored(&[IoSlice::new(b"")]).unwrap(), 0);
+        pipe.write_all(b"last").unwrap();
+        pipe.dup_queue().wait_until_empty(None).unwrap();
+        for _ in 0..100 {
+            if sink.0.lock().unwrap().len() == 22 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(sink.0.lock().unwrap().as_slice(), b"first header body last");
+    }
+
+    #[test]
+    fn write_vectored_batch() {
+        let sink = Sink::default();
+        let mut pipe = WritePipe::new(
+            sink.clone(),
+            0,
+            Queue::default(),
+            WritePipeConfig::default(),
+            Arc::default(),
+            Arc::default(),
+            &mut |task| thread::Builder::new().spawn(task).map(|_| {}),
+        )
+        .unwrap();
+
+        let records: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 0x4000]).collect();
+        let slices: Vec<IoSlice<'_>> = records.iter().map(|record| IoSlice::new(record)).collect();
+        assert_eq!(pipe.write_vectored(&slices).unwrap(), 8 * 0x4000);
+        pipe.write_all(b"tail").unwrap();
+        pipe.dup_queue().wait_until_empty(None).unwrap();
+        for _ in 0..100 {
+            if sink.0.lock().unwrap().len() == 8 * 0x4000 + 4 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut expected = records.concat();
+        expected.extend_from_slice(b"tail");
+        assert_eq!(sink.0.lock().unwrap().as_slice(), expected.as_slice());
+    }
+
+    /// Writer that records every write on its own.
+    #[derive(Default, Clone)]
+    struct Recorder(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Recorder {
+        /// Waits until `len` bytes were written and returns the writes.
+        fn wait_for(&self, len: usize) -> Vec<Vec<u8>> {
+            for _ in 0..200 {
+                let writes = self.0.lock().unwrap().clone();
+                if writes.iter().map(Vec::len).sum::<usize>() >= len {
+                    return writes;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("data was not written");
+        }
+    }
+
+    fn coalescing_pipe(recorder: &Recorder, window: Duration) -> WritePipe {
+        WritePipe::new(
+            recorder.clone(),
+            0,
+            Queue::default(),
+            WritePipeConfig { coalesce_window: Some(window), ..WritePipeConfig::default() },
+            Arc::default(),
+            Arc::default(),
+            &mut |task| thread::Builder::new().spawn(task).map(|_| {}),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn coalesce() {
+        let recorder = Recorder::default();
+        let mut pipe = coalescing_pipe(&recorder, Duration::from_millis(300));
+        for byte in 0..10u8 {
+            pipe.write_all(&[byte; 4]).unwrap();
+        }
+
+        let writes = recorder.wait_for(40);
+        assert!(writes.len() < 10);
+        assert_eq!(writes.concat(), (0..10u8).flat_map(|byte| [byte; 4]).collect::<Vec<u8>>());
+        drop(pipe);
+    }
+
+    #[test]
+    fn coalesce_ends_on_flush_and_kill() {
+        let recorder = Recorder::default();
+        let mut pipe = coalescing_pipe(&recorder, Duration::from_secs(30));
+        pipe.write_all(b"abc").unwrap();
+        flush_transport(&pipe.dup_queue(), None, &AtomicBool::new(false)).unwrap();
+        assert_eq!(recorder.wait_for(3), vec![b"abc".to_vec()]);
+
+        pipe.write_all(b"def").unwrap();
+        drop(pipe);
+        assert_eq!(recorder.wait_for(6).concat(), b"abcdef");
+    }
+
+    fn buffered_pipe(sink: &Sink, config: WritePipeConfig) -> WritePipe {
+        WritePipe::new(
+            BufWriter::new(sink.clone()),
+            0,
+            Queue::default(),
+            config,
+            Arc::default(),
+            Arc::default(),
+            &mut |task| thread::Builder::new().spawn(task).map(|_| {}),
+        )
+        .unwrap()
+    }
+
+    /// Waits until the background thread took everything from the queue and handed it to the writer.
+    fn written(pipe: &WritePipe) {
+        let (sender, receiver) = mpsc::channel();
+        pipe.dup_queue().push(WriteOp::Sync(sender)).unwrap();
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    }
+
+    #[test]
+    fn buffered_writer_needs_flush() {
+        let sink = Sink::default();
+        let mut pipe = buffered_pipe(&sink, WritePipeConfig::default());
+        pipe.write_all(b"data").unwrap();
+        written(&pipe);
+        assert!(sink.0.lock().unwrap().is_empty());
+
+        flush_transport(&pipe.dup_queue(), None, &AtomicBool::new(false)).unwrap();
+        assert_eq!(sink.0.lock().unwrap().as_slice(), b"data");
+    }
+
+    #[test]
+    fn auto_flush() {
+        let sink = Sink::default();
+        let config = WritePipeConfig {
+            auto_flush: true,
+            ..WritePipeConfig::default()
+        };
+        let mut pipe = buffered_pipe(&sink, config);
+        pipe.write_all(b"data").unwrap();
+        written(&pipe);
+        assert_eq!(sink.0.lock().unwrap().as_slice(), b"data");
+    }
+
+    #[test]
+    fn flush_every_n_messages() {
+        let sink = Sink::default();
+        let config = WritePipeConfig {
+            flush_every_n_messages: Some(3),
+            ..WritePipeConfig::default()
+        };
+        let mut pipe = buffered_pipe(&sink, config);
+        for message in [b"one", b"two"] {
+            pipe.write_all(message).unwrap();
+        }
+        written(&pipe);
+        assert!(sink.0.lock().unwrap().is_empty());
+
+        pipe.write_all(b"six").unwrap();
+        written(&pipe);
+        assert_eq!(sink.0.lock().unwrap().as_slice(), b"onetwosix");
+
+        //The count starts over after every flush.
+        pipe.write_all(b"ten").unwrap();
+        written(&pipe);
+        assert_eq!(sink.0.lock().unwrap().len(), 9);
+    }
+
+    /// Writer that accepts everything but fails to flush.
+    struct Unflushable;
+
+    impl Write for Unflushable {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "no flushing allowed"))
+        }
+    }
+
+    #[test]
+    fn flush_error() {
+        let mut pipe = WritePipe::new(
+            Unflushable,
+            0,
+            Queue::default(),
+            WritePipeConfig::default(),
+            Arc::default(),
+            Arc::default(),
+            &mut |task| thread::Builder::new().spawn(task).map(|_| {}),
+        )
+        .unwrap();
+
+        pipe.write_all(b"data").unwrap();
+        let err = flush_transport(&pipe.dup_queue(), None, &AtomicBool::new(false)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(pipe.fetch_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -0,0 +1,105 @@
+#![cfg(all(unix, feature = "async-io"))]
+
+mod common;
+
+use async_io::{block_on, Timer};
+use futures_lite::future;
+use rust_tls_duplex_stream::AsyncIoDuplexStream;
+use std::thread;
+use std::time::Duration;
+
+/// Returns true if `readable` completes within the timeout.
+async fn readable_within<C: rust_tls_duplex_stream::TlsConnection>(
+    stream: &AsyncIoDuplexStream<C>,
+    timeout: Duration,
+) -> bool {
+    future::or(async { stream.readable().await.map(|()| true).unwrap() }, async {
+        Timer::after(timeout).await;
+        false
+    })
+    .await
+}
+
+#[test]
+fn async_io_echo() {
+    let (client, server) = common::stream_pair();
+    let echo = thread::spawn(move || {
+        let mut buf = [0u8; 0x1000];
+        loop {
+            let count = server.read(&mut buf).unwrap();
+            if count == 0 {
+                break;
+            }
+            server.write_all(&buf[..count]).unwrap();
+            server.flush().unwrap();
+        }
+        server.send_close_notify().unwrap();
+    });
+
+    let client = AsyncIoDuplexStream::new(client).unwrap();
+    client.get_ref().set_write_watermarks(4, 16).unwrap();
+    let message: Vec<u8> = (0..0x4_0000u32).map(|i| (i % 251) as u8).collect();
+    let received = block_on(future::zip(
+        async {
+            client.write_all(&message).await.unwrap();
+        },
+        async {
+            let mut received = Vec::new();
+            let mut buf = [0u8; 0x1000];
+            while received.len() < message.len() {
+                let count = client.read(&mut buf).await.unwrap();
+                assert_ne!(count, 0, "unexpected EOF");
+                received.extend_from_slice(&buf[..count]);
+            }
+            received
+        },
+    ))
+    .1;
+    assert_eq!(received, message);
+
+    client.get_ref().send_close_notify().unwrap();
+    let mut buf = [0u8; 1];
+    assert_eq!(block_on(client.read(&mut buf)).unwrap(), 0);
+    echo.join().unwrap();
+}
+
+#[test]
+fn async_io_spurious_readiness() {
+    let (client, server) = common::connected_pair();
+    let mut buf = [0u8; 4];
+
+    //Session tickets of the handshake make the fd readable without any plaintext, the read keeps waiting.
+    let client = AsyncIoDuplexStream::new(client).unwrap();
+    let read = block_on(future::or(
+        async { Some(client.read(&mut buf).await.unwrap()) },
+        async {
+            Timer::after(Duration::from_millis(200)).await;
+            None
+        },
+    ));
+    assert_eq!(read, None);
+
+    server.write_all(b"pong").unwrap();
+    server.flush().unwrap();
+    let mut received = Vec::new();
+    while received.len() < 4 {
+        let count = block_on(client.read(&mut buf)).unwrap();
+        received.extend_from_slice(&buf[..count]);
+    }
+    assert_eq!(received, b"pong");
+    assert!(!block_on(readable_within(&client, Duration::from_millis(100))));
+}
+
+#[test]
+fn async_io_eof() {
+    let (client, server) = common::connected_pair();
+    let mut buf = [0u8; 4];
+
+    let client = AsyncIoDuplexStream::new(client).unwrap();
+    server.send_close_notify().unwrap();
+    assert_eq!(block_on(client.read(&mut buf)).unwrap(), 0);
+    //EOF keeps the fd readable, every further wait returns right away.
+    assert!(block_on(readable_within(&client, Duration::from_secs(10))));
+    assert!(block_on(readable_within(&client, Duration::from_secs(10))));
+    assert_eq!(block_on(client.read(&mut buf)).unwrap(), 0);
+}
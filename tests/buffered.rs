@@ -0,0 +1,119 @@
+mod common;
+
+use std::io::{BufRead, ErrorKind, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn read_lines() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"220 ready\r\n250 ok\r\nrest").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut buffered = client.buffered();
+    let mut line = String::new();
+    buffered.read_line(&mut line).unwrap();
+    assert_eq!(line, "220 ready\r\n");
+
+    let mut until = Vec::new();
+    buffered.read_until(b'\n', &mut until).unwrap();
+    assert_eq!(until, b"250 ok\r\n");
+
+    buffered.write_all(b"QUIT\r\n").unwrap();
+    buffered.flush().unwrap();
+    drop(buffered);
+
+    let mut rest = [0u8; 4];
+    client.read_exact(&mut rest).unwrap();
+    assert_eq!(&rest, b"rest");
+
+    let server = handle.join().unwrap();
+    let mut quit = [0u8; 6];
+    server.read_exact(&mut quit).unwrap();
+    assert_eq!(&quit, b"QUIT\r\n");
+}
+
+#[test]
+fn read_after_line() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"a\nb\nc").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut buffered = client.buffered();
+    let mut a = String::new();
+    buffered.read_line(&mut a).unwrap();
+    assert_eq!(a, "a\n");
+    let mut rest = Vec::new();
+    let mut chunk = [0u8; 3];
+    while rest.len() < 3 {
+        let count = buffered.read(&mut chunk).unwrap();
+        rest.extend_from_slice(&chunk[..count]);
+    }
+    assert_eq!(rest, b"b\nc");
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn stream_read_line() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"crlf\r\nlf\n\xff\nlast").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut line = String::new();
+    assert_eq!(client.read_line(&mut line).unwrap(), 6);
+    assert_eq!(line, "crlf\r\n");
+    assert_eq!(client.read_line(&mut line).unwrap(), 3);
+    assert_eq!(line, "crlf\r\nlf\n");
+    assert_eq!(
+        client.read_line(&mut line).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+
+    client
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let mut last = String::new();
+    assert_eq!(
+        client.read_line(&mut last).unwrap_err().kind(),
+        ErrorKind::TimedOut
+    );
+    assert!(last.is_empty());
+
+    let server = handle.join().unwrap();
+    server.write_all(b" line\n").unwrap();
+    server.flush().unwrap();
+    assert_eq!(client.read_line(&mut last).unwrap(), 10);
+    assert_eq!(last, "last line\n");
+
+    server.send_close_notify().unwrap();
+    let mut eof = String::new();
+    assert_eq!(client.read_line(&mut eof).unwrap(), 0);
+}
+
+#[test]
+fn stream_write_line() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_line("hello").unwrap();
+        server.write_lines(["a", "", "b"]).unwrap();
+        server.write_lines([]).unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut lines = String::new();
+    for _ in 0..4 {
+        client.read_line(&mut lines).unwrap();
+    }
+    assert_eq!(lines, "hello\r\na\r\n\r\nb\r\n");
+    drop(handle.join().unwrap());
+}
@@ -0,0 +1,22 @@
+#![cfg(feature = "bytes")]
+
+mod common;
+
+use bytes::Bytes;
+use std::thread;
+
+#[test]
+fn write_bytes_round_trip() {
+    let (client, server) = common::stream_pair();
+    let data: Vec<u8> = (0..4_000_000u32).map(|i| (i % 253) as u8).collect();
+    let expected = data.clone();
+    let handle = thread::spawn(move || {
+        let mut received = vec![0u8; expected.len()];
+        server.read_exact(&mut received).unwrap();
+        assert!(received == expected);
+    });
+
+    client.write_bytes(Bytes::from(data)).unwrap();
+    client.flush().unwrap();
+    handle.join().unwrap();
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::StallingWriter;
+use rust_tls_duplex_stream::{ClientDuplexStream, DuplexStreamError, ServerDuplexStream};
+use std::io::ErrorKind;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn cancel_blocked_read() {
+    let (client, server) = common::stream_pair();
+    let client = Arc::new(client);
+    let token = client.read_cancel_token();
+    let reader = Arc::clone(&client);
+    let handle = thread::spawn(move || reader.read(&mut [0u8; 16]).unwrap_err());
+
+    thread::sleep(Duration::from_millis(200));
+    assert!(!handle.is_finished());
+    token.cancel();
+    token.cancel();
+    assert!(token.is_canceled());
+    let err = handle.join().unwrap();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert!(matches!(DuplexStreamError::from(err), DuplexStreamError::Canceled));
+
+    //Writing still works and so do reads that do not block.
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 7];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"goodbye");
+        server.write_all(b"reply").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.write_all(b"goodbye").unwrap();
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let mut buf = [0u8; 5];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"reply");
+    assert!(matches!(
+        DuplexStreamError::from(client.read(&mut [0u8; 16]).unwrap_err()),
+        DuplexStreamError::Canceled
+    ));
+
+    token.reset();
+    server.write_all(b"again").unwrap();
+    server.flush().unwrap();
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"again");
+}
+
+#[test]
+fn cancel_pending_writes() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let stall = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        StallingWriter {
+            inner: client_socket,
+            stall: Arc::clone(&stall),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+
+    stall.store(true, SeqCst);
+    let client = Arc::new(client);
+    let writer = Arc::clone(&client);
+    let handle = thread::spawn(move || {
+        let mut accepted = 0;
+        loop {
+            match writer.write(&[7u8; 64]) {
+                Ok(n) => accepted += n,
+                Err(err) => return (err, accepted),
+            }
+        }
+    });
+    thread::sleep(Duration::from_millis(500));
+    assert!(!handle.is_finished());
+    client.cancel_pending_writes().unwrap();
+    let (err, accepted) = handle.join().unwrap();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert!(matches!(DuplexStreamError::from(err), DuplexStreamError::Canceled));
+
+    let flusher = Arc::clone(&client);
+    let handle = thread::spawn(move || flusher.flush().unwrap_err());
+    thread::sleep(Duration::from_millis(200));
+    assert!(!handle.is_finished());
+    client.cancel_pending_writes().unwrap();
+    assert!(matches!(DuplexStreamError::from(handle.join().unwrap()), DuplexStreamError::Canceled));
+
+    //Nothing that was accepted got lost and the connection is still intact.
+    let handle = thread::spawn(move || {
+        let mut data = Vec::new();
+        server.read_to_end(&mut data).unwrap();
+        data
+    });
+    stall.store(false, SeqCst);
+    client.write_all(b"end").unwrap();
+    client.send_close_notify().unwrap();
+    let data = handle.join().unwrap();
+    assert_eq!(data.len(), accepted + 3);
+    assert!(data[..accepted].iter().all(|b| *b == 7));
+    assert!(data.ends_with(b"end"));
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use rust_tls_duplex_stream::ClientDuplexStream;
+use rustls::StreamOwned;
+use std::io::{ErrorKind, Write};
+use std::net::Shutdown;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn ends_on_clean_eof() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut tls = StreamOwned::new(common::server_connection(), server_socket);
+        tls.write_all(b"first").unwrap();
+        tls.flush().unwrap();
+        tls.write_all(b"second").unwrap();
+        tls.conn.send_close_notify();
+        tls.flush().unwrap();
+        tls.sock.shutdown(Shutdown::Write).unwrap();
+        tls
+    });
+
+    let mut received = Vec::new();
+    for chunk in client.chunks() {
+        received.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(received, b"firstsecond");
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn yields_error_then_ends() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut tls = StreamOwned::new(common::server_connection(), server_socket);
+        tls.write_all(b"data").unwrap();
+        tls.flush().unwrap();
+        tls.sock.shutdown(Shutdown::Both).unwrap();
+    });
+
+    let mut chunks = client.chunks();
+    assert_eq!(chunks.next().unwrap().unwrap(), b"data");
+    assert!(chunks.next().unwrap().is_err());
+    assert!(chunks.next().is_none());
+    handle.join().unwrap();
+}
+
+#[test]
+fn timeout_and_non_blocking_do_not_end() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"hello").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut chunks = client.chunks();
+    assert_eq!(chunks.next().unwrap().unwrap(), b"hello");
+    let server = handle.join().unwrap();
+
+    client
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+    let err = chunks.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    client.set_read_non_block(true).unwrap();
+    let err = chunks.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    server.write_all(b"again").unwrap();
+    server.flush().unwrap();
+    client.set_read_non_block(false).unwrap();
+    client.set_read_timeout(None).unwrap();
+    assert_eq!(chunks.next().unwrap().unwrap(), b"again");
+}
+
+#[test]
+fn concurrent_readers_get_disjoint_chunks() {
+    let (client, server) = common::stream_pair();
+    let client = Arc::new(client);
+    client
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let client = Arc::clone(&client);
+            thread::spawn(move || {
+                let mut received = Vec::new();
+                for chunk in client.chunks() {
+                    match chunk {
+                        Ok(chunk) => received.extend_from_slice(&chunk),
+                        Err(err) if err.kind() == ErrorKind::TimedOut => break,
+                        Err(err) => panic!("{err}"),
+                    }
+                }
+                received
+            })
+        })
+        .collect();
+
+    let data: Vec<u8> = (0..200_000u32).map(|i| (i % 7) as u8).collect();
+    server.write_all(&data).unwrap();
+    server.flush().unwrap();
+
+    let total: usize = readers
+        .into_iter()
+        .map(|reader| reader.join().unwrap().len())
+        .sum();
+    assert_eq!(total, data.len());
+}
@@ -0,0 +1,230 @@
+#![allow(dead_code)]
+
+use rust_tls_duplex_stream::{ClientDuplexStream, ServerDuplexStream};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, Error, ServerConfig, ServerConnection,
+    SignatureScheme,
+};
+use std::io;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::thread;
+use std::time::Duration;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct VeryGoodVerifier();
+
+impl ServerCertVerifier for VeryGoodVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        vec![
+            SignatureScheme::RSA_PKCS1_SHA1,
+            SignatureScheme::ECDSA_SHA1_Legacy,
+            SignatureScheme::RSA_PKCS1_SHA256,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            SignatureScheme::RSA_PKCS1_SHA384,
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            SignatureScheme::RSA_PKCS1_SHA512,
+            SignatureScheme::ECDSA_NISTP521_SHA512,
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::RSA_PSS_SHA384,
+            SignatureScheme::RSA_PSS_SHA512,
+            SignatureScheme::ED25519,
+            SignatureScheme::ED448,
+        ]
+    }
+}
+
+pub fn client_config() -> Arc<ClientConfig> {
+    Arc::new(
+        ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(VeryGoodVerifier()))
+            .with_no_client_auth(),
+    )
+}
+
+pub fn server_config() -> Arc<ServerConfig> {
+    let cert = CertificateDer::from_pem_slice(include_bytes!("../data/cert.pem")).unwrap();
+    let key = PrivateKeyDer::from_pem_slice(include_bytes!("../data/key.pem")).unwrap();
+    Arc::new(
+        ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap(),
+    )
+}
+
+pub fn client_connection() -> ClientConnection {
+    let dns_name = ServerName::try_from("localhost").unwrap();
+    ClientConnection::new(client_config(), dns_name).unwrap()
+}
+
+pub fn server_connection() -> ServerConnection {
+    ServerConnection::new(server_config()).unwrap()
+}
+
+/// Returns a connected (client, server) pair of loopback sockets.
+pub fn tcp_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (client, server)
+}
+
+/// Returns a connected (client, server) pair of duplex streams over loopback sockets.
+pub fn stream_pair() -> (ClientDuplexStream, ServerDuplexStream) {
+    let (client_socket, server_socket) = tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    (client, server)
+}
+
+/// Completes the handshake with a ping from the client and a pong from the server, returns the server.
+/// The session tickets the server sends with the handshake are read by the client as well.
+pub fn ping(client: &ClientDuplexStream, server: ServerDuplexStream) -> ServerDuplexStream {
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        server.write_all(b"pong").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.write_all(b"ping").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+    handle.join().unwrap()
+}
+
+/// Returns a pair of duplex streams over loopback sockets that completed the handshake, see `ping`.
+pub fn connected_pair() -> (ClientDuplexStream, ServerDuplexStream) {
+    let (client, server) = stream_pair();
+    let server = ping(&client, server);
+    (client, server)
+}
+
+/// Writer that blocks every write for as long as the flag is set, simulating a slow consumer.
+pub struct StallingWriter<W> {
+    pub inner: W,
+    pub stall: Arc<AtomicBool>,
+}
+
+impl<W: Write> Write for StallingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        while self.stall.load(SeqCst) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writer that fails with `PermissionDenied` once `fail` is set.
+pub struct FailingWriter<W> {
+    pub inner: W,
+    pub fail: Arc<AtomicBool>,
+}
+
+impl<W: Write> Write for FailingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.fail.load(SeqCst) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "no writing allowed"));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader that hands out one byte every 20ms while `dribble` is set.
+/// Data that was already read from `inner` is dribbled too.
+pub struct DribblingReader<R> {
+    inner: R,
+    dribble: Arc<AtomicBool>,
+    pending: VecDeque<u8>,
+}
+
+impl<R> DribblingReader<R> {
+    pub fn new(inner: R, dribble: Arc<AtomicBool>) -> Self {
+        Self {
+            inner,
+            dribble,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for DribblingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let mut chunk = [0u8; 0x4000];
+            let count = self.inner.read(&mut chunk)?;
+            self.pending.extend(&chunk[..count]);
+        }
+
+        let mut limit = buf.len();
+        if self.dribble.load(SeqCst) {
+            thread::sleep(Duration::from_millis(20));
+            limit = limit.min(1);
+        }
+
+        let count = limit.min(self.pending.len());
+        for (dst, src) in buf.iter_mut().zip(self.pending.drain(..count)) {
+            *dst = src;
+        }
+        Ok(count)
+    }
+}
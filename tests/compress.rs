@@ -0,0 +1,82 @@
+#![cfg(feature = "compress")]
+
+mod common;
+
+use rust_tls_duplex_stream::CompressedDuplexStream;
+use std::io::{Read, Write};
+use std::thread;
+
+/// Deterministic random bytes, compression must cope with data it cannot shrink.
+fn random_bytes(len: usize, mut seed: u64) -> Vec<u8> {
+    (0..len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed.to_be_bytes()[0]
+        })
+        .collect()
+}
+
+#[test]
+fn round_trip() {
+    let (client, server) = common::stream_pair();
+    let client = CompressedDuplexStream::new(client);
+    let server = CompressedDuplexStream::new(server);
+    let random = random_bytes(300_000, 0x2545_f491_4f6c_dd1d);
+    let repetitive = b"compress me ".repeat(10_000);
+
+    let expected_random = random.clone();
+    let expected_repetitive = repetitive.clone();
+    let handle = thread::spawn(move || {
+        let mut buf = vec![0u8; expected_random.len()];
+        (&server).read_exact(&mut buf).unwrap();
+        assert_eq!(buf, expected_random);
+        let mut buf = vec![0u8; expected_repetitive.len()];
+        (&server).read_exact(&mut buf).unwrap();
+        assert_eq!(buf, expected_repetitive);
+
+        //Echo back in small pieces.
+        for chunk in buf.chunks(1000) {
+            (&server).write_all(chunk).unwrap();
+        }
+        server.flush().unwrap();
+        server
+    });
+
+    (&client).write_all(&random).unwrap();
+    client.flush().unwrap();
+    (&client).write_all(&repetitive).unwrap();
+    client.flush().unwrap();
+
+    let mut echoed = vec![0u8; repetitive.len()];
+    (&client).read_exact(&mut echoed).unwrap();
+    assert_eq!(echoed, repetitive);
+    let server = handle.join().unwrap();
+
+    server.get_ref().send_close_notify().unwrap();
+    assert_eq!(client.read(&mut [0u8; 16]).unwrap(), 0);
+}
+
+#[test]
+fn flush_sends_everything() {
+    let (client, server) = common::stream_pair();
+    let client = CompressedDuplexStream::new(client);
+    let server = CompressedDuplexStream::new(server);
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 5];
+        (&server).read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        (&server).write_all(b"world").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    //Without the sync flush the compressor would keep the few bytes to itself and both sides would hang.
+    (&client).write_all(b"hello").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 5];
+    (&client).read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+    drop(handle.join().unwrap());
+}
@@ -0,0 +1,875 @@
+mod common;
+
+use common::FailingWriter;
+use rust_tls_duplex_stream::{
+    ClientDuplexStream, DuplexStream, DuplexStreamError, ServerDuplexStream, SessionState, SpawnConfig,
+    StreamConfig,
+};
+use rustls::Connection;
+use std::io::{BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn client_and_server_aliases() {
+    let (client, server): (ClientDuplexStream, ServerDuplexStream) = common::stream_pair();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(&buf).unwrap();
+        server.flush().unwrap();
+    });
+
+    client.write_all(b"hello").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 5];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    handle.join().unwrap();
+}
+
+#[test]
+fn flush_reaches_buffered_transport() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        BufWriter::with_capacity(0x10_0000, client_socket),
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        server
+    });
+
+    client.write_all(b"hello").unwrap();
+    client.flush().unwrap();
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn coalesced_writes() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::with_config(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+        |task| thread::Builder::new().spawn(task).map(|_| {}),
+        &StreamConfig {
+            write_coalesce_window: Some(Duration::from_secs(30)),
+            ..StreamConfig::default()
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let data: Vec<u8> = (0..64_000u32).map(|i| (i % 251) as u8).collect();
+    let expected = data.clone();
+    let handle = thread::spawn(move || {
+        let mut buf = vec![0u8; expected.len()];
+        server.read_exact(&mut buf).unwrap();
+        assert!(buf == expected);
+        server
+    });
+
+    let start = Instant::now();
+    for chunk in data.chunks(64) {
+        client.write_all(chunk).unwrap();
+    }
+    client.flush().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(30));
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn auto_flush_buffered_transport() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::with_config(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        BufWriter::new(client_socket),
+        |task| thread::Builder::new().spawn(task).map(|_| {}),
+        &StreamConfig {
+            write_auto_flush: true,
+            ..StreamConfig::default()
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    server.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+
+    let data: Vec<u8> = (0..0x4_0000u32).map(|i| (i % 251) as u8).collect();
+    let expected = data.clone();
+    let handle = thread::spawn(move || {
+        let mut buf = vec![0u8; expected.len()];
+        server.read_exact(&mut buf).unwrap();
+        assert!(buf == expected);
+        server
+    });
+
+    client.write_all(&data).unwrap();
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn sync_waits_for_background_writer() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let fail = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        FailingWriter {
+            inner: client_socket,
+            fail: Arc::clone(&fail),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        server
+    });
+
+    client.write_all(b"hello").unwrap();
+    client.flush().unwrap();
+    client.sync(Some(Duration::from_secs(10))).unwrap();
+    let server = handle.join().unwrap();
+    client.write_all(b"more").unwrap();
+    client.wait_write_queue_empty(Some(Duration::from_secs(10))).unwrap();
+    assert_eq!(client.pending_write_bytes(), 0);
+
+    fail.store(true, SeqCst);
+    client.write_all(b"lost").unwrap();
+    let err = client.sync(None).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    let err = client.wait_write_queue_empty(None).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    drop(server);
+}
+
+#[test]
+fn unified_connection_type() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let streams: Vec<DuplexStream> = vec![
+        DuplexStream::new_unpooled(
+            Connection::Client(common::client_connection()),
+            client_socket.try_clone().unwrap(),
+            client_socket,
+        )
+        .unwrap(),
+        DuplexStream::new_unpooled(
+            Connection::Server(common::server_connection()),
+            server_socket.try_clone().unwrap(),
+            server_socket,
+        )
+        .unwrap(),
+    ];
+
+    let mut streams = streams.into_iter();
+    let client = streams.next().unwrap();
+    let server = streams.next().unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping!");
+        server.write_all(b"pong!").unwrap();
+        server.flush().unwrap();
+    });
+
+    client.write_all(b"ping!").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 5];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong!");
+    handle.join().unwrap();
+}
+
+#[test]
+fn boxed_connection() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = rust_tls_duplex_stream::RustTlsDuplexStream::new_unpooled(
+        Box::new(Connection::Client(common::client_connection())),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        server.write_all(b"boxed").unwrap();
+        server.flush().unwrap();
+    });
+
+    let mut buf = [0u8; 5];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"boxed");
+    handle.join().unwrap();
+}
+
+#[test]
+fn connection_id_and_label() {
+    let (client, server) = common::stream_pair();
+    assert_ne!(client.connection_id(), server.connection_id());
+    assert_eq!(client.label().unwrap(), None);
+    client.set_label("upstream").unwrap();
+    assert_eq!(client.label().unwrap().as_deref(), Some("upstream"));
+
+    let debug = format!("{client:?}");
+    assert!(debug.contains(&format!("id: {}", client.connection_id())));
+    assert!(debug.contains("upstream"));
+}
+
+#[test]
+fn write_owned_large_buffer() {
+    let (client, server) = common::stream_pair();
+    let data: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+    let expected = data.clone();
+    let handle = thread::spawn(move || {
+        let mut received = vec![0u8; expected.len()];
+        server.read_exact(&mut received).unwrap();
+        assert!(received == expected);
+    });
+
+    client.write_owned(data).unwrap();
+    client.flush().unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn background_write_error_is_surfaced() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let fail = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        FailingWriter {
+            inner: client_socket.try_clone().unwrap(),
+            fail: Arc::clone(&fail),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).unwrap();
+        server
+    });
+    client.write_all(b"hello").unwrap();
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+
+    fail.store(true, SeqCst);
+    let err = loop {
+        if let Err(err) = client.write(b"x").and_then(|_| client.flush()) {
+            break err;
+        }
+    };
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    assert_eq!(err.to_string(), "no writing allowed");
+    assert_eq!(client.flush().unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(client.write(b"x").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    drop(server);
+}
+
+#[test]
+fn tls_inside_tls() {
+    let (outer_client, outer_server) = common::stream_pair();
+    let outer_client = outer_client.into_handle();
+    let outer_server = outer_server.into_handle();
+
+    let inner_client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        outer_client.clone(),
+        outer_client.clone(),
+    )
+    .unwrap();
+    let inner_server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        outer_server.clone(),
+        outer_server,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 6];
+        inner_server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"nested");
+        inner_server.write_all(b"reply").unwrap();
+        inner_server.flush().unwrap();
+        inner_server
+    });
+
+    inner_client.write_all(b"nested").unwrap();
+    inner_client.flush().unwrap();
+    let mut buf = [0u8; 5];
+    inner_client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"reply");
+    assert_ne!(outer_client.connection_id(), inner_client.connection_id());
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn ping() {
+    let (client, server) = common::stream_pair();
+    assert!(matches!(
+        client.ping(Duration::from_secs(1)).unwrap_err(),
+        DuplexStreamError::Io(err) if err.kind() == ErrorKind::Unsupported
+    ));
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(&buf).unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 1];
+    client.read_exact(&mut buf).unwrap();
+    let server = handle.join().unwrap();
+
+    //Nobody reads on the server, so the request is never answered.
+    assert!(matches!(
+        client.ping(Duration::from_millis(200)).unwrap_err(),
+        DuplexStreamError::Timeout
+    ));
+
+    //rust-tls sends the response along with its next record.
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        server.write_all(b"pong").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.ping(Duration::from_secs(10)).unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn ping_tls12() {
+    let config = rustls::ClientConfig::builder_with_protocol_versions(&[&rustls::version::TLS12])
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(common::VeryGoodVerifier()))
+        .with_no_client_auth();
+    let name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        rustls::ClientConnection::new(Arc::new(config), name).unwrap(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+
+    //tls 1.2 has nothing the peer must answer, so there is no probe.
+    let err = client.ping(Duration::from_secs(1)).unwrap_err();
+    assert!(matches!(err, DuplexStreamError::Io(err) if err.kind() == ErrorKind::Unsupported));
+    drop(server);
+}
+
+#[test]
+fn keying_material() {
+    let (client, server) = common::stream_pair();
+    assert!(matches!(
+        client.keying_material(b"EXPORTER-test", None, 32).unwrap_err(),
+        DuplexStreamError::Tls(_)
+    ));
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(&buf).unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    client.read_exact(&mut [0u8; 1]).unwrap();
+    let server = handle.join().unwrap();
+
+    let key = client.keying_material(b"EXPORTER-test", None, 32).unwrap();
+    assert_eq!(key.len(), 32);
+    assert_eq!(client.keying_material(b"EXPORTER-test", None, 32).unwrap(), key);
+    assert_eq!(server.keying_material(b"EXPORTER-test", None, 32).unwrap(), key);
+    assert_ne!(
+        client.keying_material(b"EXPORTER-test", Some(b"ctx"), 32).unwrap(),
+        key
+    );
+    assert!(matches!(
+        client.keying_material(b"EXPORTER-test", None, 0).unwrap_err(),
+        DuplexStreamError::Io(err) if err.kind() == ErrorKind::InvalidInput
+    ));
+}
+
+#[test]
+#[allow(deprecated)]
+fn io_result_shims() {
+    let (client, _server) = common::stream_pair();
+    client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    assert_eq!(client.complete_handshake().unwrap_err().kind(), ErrorKind::TimedOut);
+    assert_eq!(
+        client.check_alive(Duration::from_secs(1)).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+    assert_eq!(
+        client
+            .export_keying_material(b"EXPORTER-test", None, 32)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::Unsupported
+    );
+    assert_eq!(
+        client
+            .export_keying_material(b"EXPORTER-test", None, 0)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn structured_errors() {
+    let (client_socket, mut server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+    server_socket.write_all(b"this is not a tls record").unwrap();
+
+    let err = client.read(&mut [0u8; 16]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(matches!(DuplexStreamError::from(err), DuplexStreamError::Tls(_)));
+
+    let (client, _server) = common::stream_pair();
+    client.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    let err = client.read(&mut [0u8; 16]).unwrap_err();
+    assert!(matches!(DuplexStreamError::from(err), DuplexStreamError::Timeout));
+}
+
+#[test]
+fn small_thread_stacks() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let spawn = SpawnConfig {
+        stack_size: Some(64 * 1024),
+    };
+    let client = ClientDuplexStream::new_unpooled_with_config(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+        &StreamConfig::default(),
+        spawn,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled_with_config(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+        &StreamConfig::default(),
+        spawn,
+    )
+    .unwrap();
+
+    let data: Vec<u8> = (0..500_000u32).map(|i| (i % 241) as u8).collect();
+    let expected = data.clone();
+    let handle = thread::spawn(move || {
+        let mut buf = vec![0u8; expected.len()];
+        server.read_exact(&mut buf).unwrap();
+        assert!(buf == expected);
+        server.write_all(b"done").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.write_all(&data).unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"done");
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn with_connection() {
+    let (client, server) = common::stream_pair();
+    assert!(client.with_connection(|conn| conn.is_handshaking()).unwrap());
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+
+    let (version, certificates) = client
+        .with_connection(|conn| (conn.protocol_version(), conn.peer_certificates().map(<[_]>::len)))
+        .unwrap();
+    assert_eq!(version, Some(rustls::ProtocolVersion::TLSv1_3));
+    assert_eq!(certificates, Some(1));
+
+    //Records queued inside the closure are sent by the next flush.
+    client.with_connection(|conn| conn.send_close_notify()).unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 1];
+    assert_eq!(server.read(&mut buf).unwrap(), 0);
+}
+
+trait SeekableStream: Read + Write + Seek {}
+
+impl<T: Read + Write + Seek> SeekableStream for T {}
+
+#[test]
+fn seek_unsupported() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(&buf).unwrap();
+        server.flush().unwrap();
+    });
+
+    let mut seekable: Box<dyn SeekableStream> = Box::new(client);
+    assert_eq!(seekable.seek(SeekFrom::Start(0)).unwrap_err().kind(), ErrorKind::Unsupported);
+    assert_eq!(seekable.stream_position().unwrap_err().kind(), ErrorKind::Unsupported);
+    assert_eq!(seekable.rewind().unwrap_err().kind(), ErrorKind::Unsupported);
+
+    seekable.write_all(b"echo").unwrap();
+    seekable.flush().unwrap();
+    let mut buf = [0u8; 4];
+    seekable.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"echo");
+    handle.join().unwrap();
+}
+
+#[test]
+fn connection_info() {
+    let (client, server) = common::stream_pair();
+    let info = client.connection_info().unwrap();
+    assert_eq!(info.id, client.connection_id());
+    assert!(info.cipher_suite.is_none());
+    assert!(info.protocol_version.is_none());
+    assert!(info.to_string().contains("handshaking"));
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(&buf).unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.write_all(b"ping").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).unwrap();
+    let _server = handle.join().unwrap();
+
+    let info = client.connection_info().unwrap();
+    assert_eq!(info.protocol_version, Some(rustls::ProtocolVersion::TLSv1_3));
+    let suite = info.cipher_suite.unwrap();
+    assert!(info.alpn_protocol.is_none());
+    assert!(info.bytes_read > 0);
+    assert!(info.bytes_written > 0);
+    assert_eq!(info.write_queue_depth, 0);
+
+    let line = info.to_string();
+    assert!(!line.contains('\n'));
+    assert!(line.contains(&format!("connection {}", info.id)));
+    assert!(line.contains("TLSv1_3"));
+    assert!(line.contains(&format!("{:?}", suite.suite())));
+}
+
+#[test]
+fn display_summary() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client =
+        ClientDuplexStream::new_unpooled(common::client_connection(), client_socket.try_clone().unwrap(), client_socket)
+            .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket.try_clone().unwrap(),
+    )
+    .unwrap();
+    assert_eq!(client.to_string(), "state=handshaking");
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        server
+    });
+    client.write_all(b"ping").unwrap();
+    client.flush().unwrap();
+    client.handshake().unwrap();
+    let server = handle.join().unwrap();
+
+    let suite = client.connection_info().unwrap().cipher_suite.unwrap();
+    assert_eq!(client.to_string(), format!("TLSv1_3 {:?} state=open", suite.suite()));
+    assert_eq!(server.to_string(), format!("TLSv1_3 {:?} sni=localhost state=open", suite.suite()));
+    let info = server.connection_info().unwrap();
+    assert_eq!(info.server_name.as_deref(), Some("localhost"));
+    assert_eq!(info.state, SessionState::Open);
+    assert!(info.to_string().contains("sni=localhost state=open"));
+
+    //Formatting does not wait for the connection.
+    let line = client.with_connection(|_| client.to_string()).unwrap();
+    assert_eq!(line, "state=handshaking");
+
+    server_socket.shutdown(std::net::Shutdown::Both).unwrap();
+    let mut rest = Vec::new();
+    _ = client.read_to_end(&mut rest);
+    assert!(client.to_string().ends_with("state=closed"), "{client}");
+    assert_eq!(client.connection_info().unwrap().state, SessionState::Closed);
+}
+
+#[test]
+fn buf_writer_flush_delivers_everything() {
+    let (client, server) = common::stream_pair();
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let len = data.len();
+    let handle = thread::spawn(move || {
+        let mut received = vec![0u8; len];
+        server.read_exact(&mut received).unwrap();
+        received
+    });
+
+    //The flush of the BufWriter reaches the flush of the stream, which waits until the queue was written.
+    let mut writer = BufWriter::with_capacity(4096, &client);
+    for chunk in data.chunks(1000) {
+        writer.write_all(chunk).unwrap();
+    }
+    writer.flush().unwrap();
+    assert_eq!(client.pending_write_bytes(), 0);
+    assert_eq!(handle.join().unwrap(), data);
+}
+
+#[test]
+fn read_raw_frame_fifo() {
+    let (client, server) = common::stream_pair();
+    let client = Arc::new(client);
+    assert_eq!(client.read_raw_frame(0).unwrap_err().kind(), ErrorKind::InvalidInput);
+
+    let mut readers = Vec::new();
+    for _ in 0..4 {
+        let client = Arc::clone(&client);
+        readers.push(thread::spawn(move || client.read_raw_frame(4).unwrap()));
+    }
+
+    for frame in [b"aaaa", b"bbbb", b"cccc", b"dddd"] {
+        server.write_all(frame).unwrap();
+        server.flush().unwrap();
+    }
+
+    let frames: Vec<Vec<u8>> = readers.into_iter().map(|reader| reader.join().unwrap()).collect();
+    let mut received = frames.concat();
+    assert!(frames.iter().all(|frame| !frame.is_empty() && frame.len() <= 4));
+    //Chunks may split across frames, every byte is still delivered exactly once.
+    while received.len() < 16 {
+        received.extend_from_slice(&client.read_raw_frame(4).unwrap());
+    }
+    received.sort_unstable();
+    assert_eq!(received, b"aaaabbbbccccdddd");
+
+    server.send_close_notify().unwrap();
+    assert!(client.read_raw_frame(4).unwrap().is_empty());
+}
+
+#[test]
+fn handshake_barrier() {
+    let mut client_config = (*common::client_config()).clone();
+    client_config.alpn_protocols = vec![b"h2".to_vec()];
+    let mut server_config = (*common::server_config()).clone();
+    server_config.alpn_protocols = vec![b"h2".to_vec()];
+    let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        rustls::ClientConnection::new(Arc::new(client_config), server_name).unwrap(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        rustls::ServerConnection::new(Arc::new(server_config)).unwrap(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        server.handshake().unwrap();
+        server
+    });
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    client.handshake().unwrap();
+    let server = handle.join().unwrap();
+
+    for info in [client.connection_info().unwrap(), server.connection_info().unwrap()] {
+        assert_eq!(info.protocol_version, Some(rustls::ProtocolVersion::TLSv1_3));
+        assert!(info.cipher_suite.is_some());
+        assert_eq!(info.alpn_protocol.as_deref(), Some(&b"h2"[..]));
+    }
+
+    //Done already, so this returns right away, and the connection still works afterwards.
+    client.handshake().unwrap();
+    client.write_all(b"ping").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 4];
+    server.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[test]
+fn handshake_times_out() {
+    //The server never drives its side of the handshake.
+    let (client, _server) = common::stream_pair();
+    client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let start = Instant::now();
+    assert!(matches!(client.handshake().unwrap_err(), DuplexStreamError::Timeout));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert!(client.connection_info().unwrap().protocol_version.is_none());
+}
+
+/// Client connection that does not implement `Debug`.
+struct OpaqueConnection(rustls::ClientConnection);
+
+impl rust_tls_duplex_stream::TlsConnection for OpaqueConnection {
+    fn common_state(&self) -> &rustls::CommonState {
+        &self.0
+    }
+
+    fn common_state_mut(&mut self) -> &mut rustls::CommonState {
+        &mut self.0
+    }
+
+    fn complete_io<T: Read + Write>(&mut self, io: &mut T) -> std::io::Result<(usize, usize)> {
+        self.0.complete_io(io)
+    }
+
+    fn reader(&mut self) -> rustls::Reader<'_> {
+        self.0.reader()
+    }
+
+    fn writer(&mut self) -> rustls::Writer<'_> {
+        self.0.writer()
+    }
+
+    fn process_new_packets(&mut self) -> Result<rustls::IoState, rustls::Error> {
+        self.0.process_new_packets()
+    }
+
+    fn refresh_traffic_keys(&mut self) -> Result<(), rustls::Error> {
+        self.0.refresh_traffic_keys()
+    }
+
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: Option<&[u8]>,
+    ) -> Result<(), rustls::Error> {
+        self.0.export_keying_material(output, label, context).map(|_| ())
+    }
+}
+
+#[test]
+fn debug_without_debug_connection() {
+    let (client_tcp, server_tcp) = common::tcp_pair();
+    let client = Arc::new(
+        rust_tls_duplex_stream::RustTlsDuplexStream::new_unpooled(
+            OpaqueConnection(common::client_connection()),
+            client_tcp.try_clone().unwrap(),
+            client_tcp,
+        )
+        .unwrap(),
+    );
+    let server =
+        ServerDuplexStream::new_unpooled(common::server_connection(), server_tcp.try_clone().unwrap(), server_tcp)
+            .unwrap();
+    client.set_label("debug-test").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        server
+    });
+    client.write_all(b"ping").unwrap();
+    client.flush().unwrap();
+    client.handshake().unwrap();
+    let _server = handle.join().unwrap();
+
+    let debug = format!("{client:?}");
+    assert!(debug.starts_with("RustTlsDuplexStream {"), "{debug}");
+    assert!(debug.contains("label: Some(\"debug-test\")"), "{debug}");
+    assert!(debug.contains("read_dead: false"), "{debug}");
+    assert!(debug.contains("write_dead: false"), "{debug}");
+    assert!(debug.contains("non_blocking_read: false"), "{debug}");
+    assert!(debug.contains("read_timeout: Some(5s)"), "{debug}");
+    assert!(debug.contains("write_timeout: None"), "{debug}");
+    assert!(debug.contains("handshake_complete: Some(true)"), "{debug}");
+
+    let half = client.clone_read_half().unwrap();
+    assert!(format!("{half:?}").contains("read_dead: false"));
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use rust_tls_duplex_stream::{
+    copy_bidirectional, ClientDuplexStream, PlainEndpoint, ServerDuplexStream,
+};
+use rustls::StreamOwned;
+use std::io::{Cursor, Read, Write};
+use std::net::Shutdown;
+use std::thread;
+
+#[test]
+fn proxy_between_tls_streams() {
+    let (outer_client, proxy_front) = common::tcp_pair();
+    let (proxy_back, outer_server) = common::tcp_pair();
+    let front = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        proxy_front.try_clone().unwrap(),
+        proxy_front,
+    )
+    .unwrap();
+    let back = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        proxy_back.try_clone().unwrap(),
+        proxy_back,
+    )
+    .unwrap();
+
+    let server = thread::spawn(move || {
+        let mut tls = StreamOwned::new(common::server_connection(), outer_server);
+        let mut buf = [0u8; 4];
+        tls.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        tls.write_all(b"pong!").unwrap();
+        tls.flush().unwrap();
+        let mut rest = Vec::new();
+        tls.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+        tls.conn.send_close_notify();
+        tls.flush().unwrap();
+        tls.sock.shutdown(Shutdown::Write).unwrap();
+        tls
+    });
+
+    let client = thread::spawn(move || {
+        let mut tls = StreamOwned::new(common::client_connection(), outer_client);
+        tls.write_all(b"ping").unwrap();
+        tls.flush().unwrap();
+        let mut buf = [0u8; 5];
+        tls.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong!");
+        tls.conn.send_close_notify();
+        tls.flush().unwrap();
+        tls.sock.shutdown(Shutdown::Write).unwrap();
+        let mut rest = Vec::new();
+        tls.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+        tls
+    });
+
+    let (to_server, to_client) = copy_bidirectional(&front, &back).unwrap();
+    assert_eq!(to_server, 4);
+    assert_eq!(to_client, 5);
+    drop(client.join().unwrap());
+    drop(server.join().unwrap());
+}
+
+#[test]
+fn proxy_to_plain_tcp() {
+    let (outer_client, proxy_front) = common::tcp_pair();
+    let (proxy_back, mut plain_server) = common::tcp_pair();
+    let front = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        proxy_front.try_clone().unwrap(),
+        proxy_front,
+    )
+    .unwrap();
+
+    let server = thread::spawn(move || {
+        let mut request = Vec::new();
+        plain_server.read_to_end(&mut request).unwrap();
+        assert_eq!(request, b"request");
+        plain_server.write_all(b"response").unwrap();
+        plain_server.shutdown(Shutdown::Write).unwrap();
+    });
+
+    let client = thread::spawn(move || {
+        let mut tls = StreamOwned::new(common::client_connection(), outer_client);
+        tls.write_all(b"request").unwrap();
+        tls.conn.send_close_notify();
+        tls.flush().unwrap();
+        let mut response = Vec::new();
+        tls.read_to_end(&mut response).unwrap();
+        assert_eq!(response, b"response");
+        tls
+    });
+
+    let (to_server, to_client) = copy_bidirectional(&front, &proxy_back).unwrap();
+    assert_eq!(to_server, 7);
+    assert_eq!(to_client, 8);
+    drop(client.join().unwrap());
+    server.join().unwrap();
+}
+
+#[test]
+fn proxy_to_plain_endpoint() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"from plain");
+        server.write_all(b"from tls").unwrap();
+        server.send_close_notify().unwrap();
+        server
+    });
+
+    let plain = PlainEndpoint::new(Cursor::new(b"from plain".to_vec()), Vec::new());
+    let (to_tls, to_plain) = copy_bidirectional(&plain, &client).unwrap();
+    assert_eq!(to_tls, 10);
+    assert_eq!(to_plain, 8);
+    drop(handle.join().unwrap());
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn data_callback_data_before_registration() {
+    let (client, server) = common::connected_pair();
+    server.write_all(b"early").unwrap();
+    server.flush().unwrap();
+    client.wait_readable(Some(Duration::from_secs(10))).unwrap();
+
+    let client = Arc::new(client);
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let (called, calls) = mpsc::channel();
+    let client_copy = Arc::clone(&client);
+    let received_copy = Arc::clone(&received);
+    let called = Mutex::new(called);
+    client.set_data_callback(Some(Box::new(move || {
+        client_copy.read_available(&mut received_copy.lock().unwrap()).unwrap();
+        _ = called.lock().unwrap().send(());
+    })));
+
+    calls.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(received.lock().unwrap().as_slice(), b"early");
+
+    //Invoked again from the background reader once more data arrives after the drain.
+    server.write_all(b"late").unwrap();
+    server.flush().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while received.lock().unwrap().len() < 9 {
+        calls.recv_timeout(deadline - Instant::now()).unwrap();
+    }
+    assert_eq!(received.lock().unwrap().as_slice(), b"earlylate");
+
+    client.set_data_callback(None);
+    while calls.recv_timeout(Duration::from_millis(100)).is_ok() {}
+    server.write_all(b"ignored").unwrap();
+    server.flush().unwrap();
+    client.wait_readable(Some(Duration::from_secs(10))).unwrap();
+    assert!(calls.recv_timeout(Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn data_callback_coalesces_bursts() {
+    let (client, server) = common::connected_pair();
+
+    let (called, calls) = mpsc::channel();
+    let called = Mutex::new(called);
+    client.set_data_callback(Some(Box::new(move || {
+        _ = called.lock().unwrap().send(());
+    })));
+    //The call made while setting it, nothing was received yet.
+    calls.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    //Nothing is drained, so the whole burst invokes the callback once.
+    for _ in 0..16 {
+        server.write_all(b"burst").unwrap();
+        server.flush().unwrap();
+    }
+    calls.recv_timeout(Duration::from_secs(10)).unwrap();
+    let mut received = Vec::new();
+    while received.len() < 16 * 5 {
+        client.read_available(&mut received).unwrap();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(calls.recv_timeout(Duration::from_millis(100)).is_err());
+
+    server.send_close_notify().unwrap();
+    calls.recv_timeout(Duration::from_secs(10)).unwrap();
+    let mut buf = [0u8; 1];
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+}
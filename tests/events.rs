@@ -0,0 +1,148 @@
+mod common;
+
+use common::FailingWriter;
+use rust_tls_duplex_stream::{ClientDuplexStream, ConnectionInfo, ServerDuplexStream, StreamEvents};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore};
+use std::io;
+use std::io::ErrorKind;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Event {
+    Handshake,
+    Closed,
+    Error(ErrorKind),
+}
+
+/// Handler that forwards every event to a channel.
+struct Recorder(Mutex<Sender<Event>>);
+
+impl StreamEvents for Recorder {
+    fn on_handshake_complete(&self, info: &ConnectionInfo) {
+        assert!(info.protocol_version.is_some());
+        assert!(info.cipher_suite.is_some());
+        self.0.lock().unwrap().send(Event::Handshake).unwrap();
+    }
+
+    fn on_peer_closed(&self) {
+        self.0.lock().unwrap().send(Event::Closed).unwrap();
+    }
+
+    fn on_error(&self, err: &io::Error) {
+        self.0.lock().unwrap().send(Event::Error(err.kind())).unwrap();
+    }
+}
+
+fn recorder() -> (Arc<Recorder>, Receiver<Event>) {
+    let (sender, receiver) = mpsc::channel();
+    (Arc::new(Recorder(Mutex::new(sender))), receiver)
+}
+
+fn next(events: &Receiver<Event>) -> Event {
+    events.recv_timeout(Duration::from_secs(10)).unwrap()
+}
+
+fn assert_no_more(events: &Receiver<Event>) {
+    assert!(events.recv_timeout(Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn events_handshake_then_close() {
+    let (client, server) = common::stream_pair();
+    let (client_handler, client_events) = recorder();
+    let (server_handler, server_events) = recorder();
+    client.set_event_handler(client_handler);
+    server.set_event_handler(server_handler);
+
+    let server = common::ping(&client, server);
+    assert_eq!(next(&client_events), Event::Handshake);
+    assert_eq!(next(&server_events), Event::Handshake);
+
+    server.send_close_notify().unwrap();
+    let mut buf = [0u8; 4];
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+    assert_eq!(next(&client_events), Event::Closed);
+    assert_no_more(&client_events);
+    assert_no_more(&server_events);
+}
+
+#[test]
+fn events_error_before_handshake() {
+    //Trusts nothing, so the certificate of the server is refused.
+    let config = ClientConfig::builder()
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth();
+    let connection = ClientConnection::new(Arc::new(config), ServerName::try_from("localhost").unwrap()).unwrap();
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(connection, client_socket.try_clone().unwrap(), client_socket).unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    let (client_handler, client_events) = recorder();
+    let (server_handler, server_events) = recorder();
+    client.set_event_handler(client_handler);
+    server.set_event_handler(server_handler);
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        assert!(server.read(&mut buf).is_err());
+        server
+    });
+    //Whichever call drives the handshake fails.
+    let mut buf = [0u8; 4];
+    let err = client
+        .write_all(b"ping")
+        .and_then(|()| client.flush())
+        .and_then(|()| client.read(&mut buf))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let server = handle.join().unwrap();
+
+    assert_eq!(next(&client_events), Event::Error(ErrorKind::InvalidData));
+    assert_eq!(next(&server_events), Event::Error(ErrorKind::InvalidData));
+
+    //The handshake is never reported.
+    drop(server);
+    assert_no_more(&client_events);
+    assert_no_more(&server_events);
+}
+
+#[test]
+fn events_transport_error_after_handshake() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let fail = Arc::new(AtomicBool::new(false));
+    let writer = FailingWriter {
+        inner: client_socket.try_clone().unwrap(),
+        fail: Arc::clone(&fail),
+    };
+    let client = ClientDuplexStream::new_unpooled(common::client_connection(), client_socket, writer).unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    let (handler, events) = recorder();
+    client.set_event_handler(handler);
+
+    let _server = common::ping(&client, server);
+    assert_eq!(next(&events), Event::Handshake);
+
+    //Delivered by the background writer, nothing reads or writes after the failing flush.
+    fail.store(true, SeqCst);
+    client.write_all(b"lost").unwrap();
+    _ = client.flush();
+    assert_eq!(next(&events), Event::Error(ErrorKind::PermissionDenied));
+    assert!(client.write_all(b"lost").is_err());
+    assert_no_more(&events);
+}
@@ -0,0 +1,78 @@
+#![cfg(feature = "futures-io")]
+
+mod common;
+
+use futures::executor::block_on;
+use futures::io::{AsyncBufRead, AsyncRead, AsyncWrite};
+use rust_tls_duplex_stream::test_utils::in_memory_pair;
+use rust_tls_duplex_stream::{AsyncDuplexStream, ClientDuplexStream, ServerDuplexStream};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::thread;
+
+/// Writes all of `data` through the adapter.
+async fn write_all<T: AsyncWrite + Unpin>(io: &mut T, mut data: &[u8]) {
+    while !data.is_empty() {
+        let count = poll_fn(|cx| Pin::new(&mut *io).poll_write(cx, data)).await.unwrap();
+        data = &data[count..];
+    }
+}
+
+/// Reads until EOF through `poll_fill_buf`.
+async fn read_to_end<T: AsyncBufRead + Unpin>(io: &mut T) -> Vec<u8> {
+    let mut received = Vec::new();
+    loop {
+        let count = poll_fn(|cx| {
+            Pin::new(&mut *io).poll_fill_buf(cx).map_ok(|chunk| {
+                received.extend_from_slice(chunk);
+                chunk.len()
+            })
+        })
+        .await
+        .unwrap();
+        if count == 0 {
+            return received;
+        }
+        Pin::new(&mut *io).consume(count);
+    }
+}
+
+#[test]
+fn futures_io_round_trip() {
+    let (client_transport, server_transport) = in_memory_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_transport.clone(),
+        client_transport,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_transport.clone(),
+        server_transport,
+    )
+    .unwrap();
+
+    //The peer answers only after our close_notify arrived, so close has to flush it.
+    let echo = thread::spawn(move || {
+        let mut received = Vec::new();
+        server.copy_to(&mut received).unwrap();
+        server.write_all(&received).unwrap();
+        server.send_close_notify().unwrap();
+    });
+
+    let message: Vec<u8> = (0..0x4_0000u32).map(|i| (i % 251) as u8).collect();
+    let mut client = AsyncDuplexStream::new(client).unwrap();
+    let received = block_on(async {
+        write_all(&mut client, &message).await;
+        poll_fn(|cx| Pin::new(&mut client).poll_flush(cx)).await.unwrap();
+        poll_fn(|cx| Pin::new(&mut client).poll_close(cx)).await.unwrap();
+        read_to_end(&mut client).await
+    });
+    assert_eq!(received, message);
+
+    let mut buf = [0u8; 1];
+    let eof = block_on(poll_fn(|cx| Pin::new(&mut client).poll_read(cx, &mut buf))).unwrap();
+    assert_eq!(eof, 0);
+    echo.join().unwrap();
+}
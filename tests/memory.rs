@@ -0,0 +1,174 @@
+mod common;
+
+use rust_tls_duplex_stream::test_utils::in_memory_pair;
+use rust_tls_duplex_stream::{ClientDuplexStream, ServerDuplexStream};
+use rustls::StreamOwned;
+use std::io;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::thread;
+
+#[test]
+fn http_exchange_in_memory() {
+    let (client_transport, server_transport) = in_memory_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_transport.clone(),
+        client_transport,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_transport.clone(),
+        server_transport,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut request = Vec::new();
+        let mut reader = BufReader::new(&server);
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            request.push(line);
+        }
+
+        assert_eq!(request[0], "GET / HTTP/1.1\r\n");
+        server
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello")
+            .unwrap();
+        server.flush().unwrap();
+    });
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    client.flush().unwrap();
+
+    let mut response = String::new();
+    let mut reader = BufReader::new(&client);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "HTTP/1.1 200 OK\r\n");
+    reader.read_line(&mut response).unwrap();
+    assert_eq!(response, "Content-Length: 5\r\n");
+    handle.join().unwrap();
+}
+
+#[test]
+fn eof_after_drop() {
+    let (mut left, mut right) = in_memory_pair();
+    left.write_all(b"bye").unwrap();
+    drop(left);
+    let mut buf = Vec::new();
+    right.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"bye");
+    assert_eq!(right.write(b"x").unwrap_err().kind(), ErrorKind::BrokenPipe);
+}
+
+/// Destination that fails every write.
+struct FullDisk;
+
+impl Write for FullDisk {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("disk full"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn copy_to_download() {
+    let (client_transport, server_transport) = in_memory_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_transport.clone(),
+        client_transport,
+    )
+    .unwrap();
+
+    let payload: Vec<u8> = (0..5_000_000u32).map(|i| (i % 241) as u8).collect();
+    let expected = payload.clone();
+    let handle = thread::spawn(move || {
+        let mut tls = StreamOwned::new(common::server_connection(), server_transport);
+        tls.write_all(&payload).unwrap();
+        tls.conn.send_close_notify();
+        tls.flush().unwrap();
+        tls.sock.shutdown();
+    });
+
+    let mut downloaded = Vec::new();
+    let count = client.copy_to(&mut downloaded).unwrap();
+    assert_eq!(count, expected.len() as u64);
+    assert!(downloaded == expected);
+    handle.join().unwrap();
+}
+
+#[test]
+fn copy_to_destination_error() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"data").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let err = client.copy_to(&mut FullDisk).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert_eq!(err.to_string(), "disk full");
+    drop(handle.join().unwrap());
+}
+
+/// Source that yields some data and then fails.
+struct BrokenSource(usize);
+
+impl Read for BrokenSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0 == 0 {
+            return Err(io::Error::from(ErrorKind::InvalidData));
+        }
+        let count = buf.len().min(self.0);
+        buf[..count].fill(b'x');
+        self.0 -= count;
+        Ok(count)
+    }
+}
+
+#[test]
+fn write_reader_upload() {
+    let (client, server) = common::stream_pair();
+    let payload: Vec<u8> = (0..1_000_000u32).map(|i| (i % 239) as u8).collect();
+    let expected = payload.clone();
+    let handle = thread::spawn(move || {
+        let mut received = vec![0u8; expected.len()];
+        server.read_exact(&mut received).unwrap();
+        assert!(received == expected);
+        server
+    });
+
+    let count = client.write_reader(&mut payload.as_slice()).unwrap();
+    assert_eq!(count, payload.len() as u64);
+    client.flush().unwrap();
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn write_reader_source_error() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        let mut received = [0u8; 50_000];
+        server.read_exact(&mut received).unwrap();
+        assert!(received.iter().all(|byte| *byte == b'x'));
+        server
+    });
+
+    let err = client.write_reader(&mut BrokenSource(50_000)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("source"));
+    client.flush().unwrap();
+    drop(handle.join().unwrap());
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use rust_tls_duplex_stream::{ClientDuplexStream, OverflowPolicy, ServerDuplexStream, StreamConfig};
+use std::sync::Arc;
+use std::thread;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+const LIMIT: usize = 0x1_0000;
+
+#[test]
+fn limit_is_enforced() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::with_config(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+        |task| thread::Builder::new().spawn(task).map(|_| {}),
+        &StreamConfig {
+            total_memory_limit: Some(LIMIT),
+            ..StreamConfig::default()
+        },
+    )
+    .unwrap();
+
+    let data: Vec<u8> = (0..8_000_000u32).map(|i| (i % 233) as u8).collect();
+    let expected = data.clone();
+    let writer = thread::spawn(move || {
+        client.write_all(&data).unwrap();
+        client.flush().unwrap();
+        client
+    });
+
+    let mut first = [0u8; 1];
+    server.read_exact(&mut first).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    // The background reader may overshoot by at most one read of the underlying connection.
+    assert!(server.current_memory_usage() <= LIMIT + 0x1_0000);
+    assert!(!writer.is_finished());
+
+    let mut received = vec![0u8; expected.len() - 1];
+    server.read_exact(&mut received).unwrap();
+    assert_eq!(first[0], expected[0]);
+    assert!(received == expected[1..]);
+    drop(writer.join().unwrap());
+    assert_eq!(server.current_memory_usage(), 0);
+}
+
+#[test]
+fn read_watermarks_bound_buffering() {
+    let (client, server) = common::stream_pair();
+    assert_eq!(
+        server.set_read_watermarks(2, 2).unwrap_err().kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+    server.set_read_watermarks(1, 2).unwrap();
+    client.set_write_watermarks(1, 4).unwrap();
+
+    let data: Vec<u8> = (0..16_000_000u32).map(|i| (i % 233) as u8).collect();
+    let expected = data.clone();
+    let writer = thread::spawn(move || {
+        client.write_all(&data).unwrap();
+        client.flush().unwrap();
+        client
+    });
+
+    let mut first = [0u8; 1];
+    server.read_exact(&mut first).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    // At most 3 queued reads of the underlying connection plus the overshoot of one more.
+    assert!(server.current_memory_usage() <= 4 * 0x1_0000);
+    assert!(!writer.is_finished());
+
+    let mut received = vec![0u8; expected.len() - 1];
+    server.read_exact(&mut received).unwrap();
+    assert_eq!(first[0], expected[0]);
+    assert!(received == expected[1..]);
+    drop(writer.join().unwrap());
+}
+
+#[test]
+fn pending_bytes() {
+    let (client, server) = common::stream_pair();
+    server.set_read_watermarks(1, 2).unwrap();
+    client.set_write_watermarks(1, 4).unwrap();
+    assert_eq!(client.pending_write_bytes(), 0);
+    assert_eq!(server.pending_read_bytes(), 0);
+
+    let data: Vec<u8> = (0..16_000_000u32).map(|i| (i % 233) as u8).collect();
+    let expected = data.clone();
+    let client = Arc::new(client);
+    let writer = {
+        let client = Arc::clone(&client);
+        thread::spawn(move || {
+            client.write_all(&data).unwrap();
+            client.flush().unwrap();
+        })
+    };
+
+    let mut first = [0u8; 1];
+    server.read_exact(&mut first).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    // Both sides stalled, the queues are as full as their watermarks allow.
+    let pending_read = server.pending_read_bytes();
+    assert!(pending_read > 0);
+    assert!(pending_read <= server.current_memory_usage());
+    assert!(client.pending_write_bytes() > 0);
+    // At most the high watermark plus one records of at most 16KiB of plaintext each.
+    assert!(client.pending_write_bytes() <= 5 * 0x4100);
+
+    let mut received = vec![0u8; expected.len() - 1];
+    server.read_exact(&mut received).unwrap();
+    assert!(received == expected[1..]);
+    writer.join().unwrap();
+    assert_eq!(client.pending_write_bytes(), 0);
+    assert_eq!(server.pending_read_bytes(), 0);
+}
+
+#[test]
+fn read_on_full_drops() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::with_config(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+        |task| thread::Builder::new().spawn(task).map(|_| {}),
+        &StreamConfig {
+            read_on_full: OverflowPolicy::DropOldest,
+            ..StreamConfig::default()
+        },
+    )
+    .unwrap();
+    let server = common::ping(&client, server);
+    assert_eq!(server.dropped_read_bytes(), 0);
+    server.set_read_watermarks(1, 2).unwrap();
+
+    //Nothing is read, the background reader keeps reading and drops the oldest data.
+    let writer = thread::spawn(move || {
+        client.write_all(&vec![7u8; 0x10_0000]).unwrap();
+        client.flush().unwrap();
+        client
+    });
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while server.dropped_read_bytes() == 0 {
+        assert!(Instant::now() < deadline, "nothing was dropped");
+        thread::sleep(Duration::from_millis(1));
+    }
+    let client = writer.join().unwrap();
+
+    //The records that are left do not follow the last one rust-tls saw.
+    server.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let err = server.read(&mut [0u8; 0x4000]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    drop(client);
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "tcp")]
+
+mod common;
+
+use rust_tls_duplex_stream::{ClientDuplexStream, ServerDuplexStream};
+use std::io::ErrorKind;
+
+#[test]
+fn addresses_from_meta() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client_addr = client_socket.local_addr().unwrap();
+    let server_addr = server_socket.local_addr().unwrap();
+    let client = ClientDuplexStream::new_with_meta(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    )
+    .unwrap();
+    assert_eq!(client.local_addr().unwrap(), client_addr);
+    assert_eq!(client.peer_addr().unwrap(), server_addr);
+
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    assert_eq!(server.peer_addr().unwrap_err().kind(), ErrorKind::Unsupported);
+    assert_eq!(server.local_addr().unwrap_err().kind(), ErrorKind::Unsupported);
+}
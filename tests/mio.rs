@@ -0,0 +1,107 @@
+#![cfg(all(unix, feature = "mio"))]
+
+mod common;
+
+use mio::{Events, Interest, Poll, Token};
+use std::io::ErrorKind;
+use std::thread;
+use std::time::Duration;
+
+const CLIENT: Token = Token(7);
+
+/// Waits for the next batch of events of the client, every wait must see one.
+fn next_events(poll: &mut Poll, events: &mut Events) -> (bool, bool) {
+    poll.poll(events, Some(Duration::from_secs(10))).unwrap();
+    assert!(!events.is_empty(), "no event within 10s");
+    let mut readable = false;
+    let mut writable = false;
+    for event in events.iter() {
+        assert_eq!(event.token(), CLIENT);
+        readable |= event.is_readable();
+        writable |= event.is_writable();
+    }
+    (readable, writable)
+}
+
+#[test]
+fn mio_echo() {
+    let (mut client, server) = common::stream_pair();
+    let echo = thread::spawn(move || {
+        let mut buf = [0u8; 0x1000];
+        loop {
+            let count = server.read(&mut buf).unwrap();
+            if count == 0 {
+                break;
+            }
+            server.write_all(&buf[..count]).unwrap();
+            server.flush().unwrap();
+        }
+        server.send_close_notify().unwrap();
+    });
+    //The handshake is driven blocking, everything after this only reacts to events.
+    client.flush().unwrap();
+    client.set_read_non_block(true).unwrap();
+    client.set_write_non_block(true).unwrap();
+    client.set_write_watermarks(4, 16).unwrap();
+
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+    poll.registry()
+        .register(&mut client, CLIENT, Interest::READABLE | Interest::WRITABLE)
+        .unwrap();
+
+    let message: Vec<u8> = (0..0x4_0000u32).map(|i| (i % 251) as u8).collect();
+    let mut sent = 0;
+    let mut received = Vec::new();
+    let mut buf = [0u8; 0x1000];
+    while received.len() < message.len() {
+        let (readable, writable) = next_events(&mut poll, &mut events);
+        if writable && sent < message.len() {
+            while sent < message.len() {
+                match client.write(&message[sent..(sent + 0x1000).min(message.len())]) {
+                    Ok(count) => sent += count,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => panic!("{err}"),
+                }
+            }
+
+            if sent == message.len() {
+                //Nothing left to write, only wait for the echo from now on.
+                poll.registry().reregister(&mut client, CLIENT, Interest::READABLE).unwrap();
+            }
+        }
+
+        if readable {
+            loop {
+                match client.read(&mut buf) {
+                    Ok(0) => panic!("unexpected EOF"),
+                    Ok(count) => received.extend_from_slice(&buf[..count]),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => panic!("{err}"),
+                }
+            }
+        }
+    }
+    assert_eq!(sent, message.len());
+    assert_eq!(received, message);
+
+    //A deregistered stream can be registered again.
+    poll.registry().deregister(&mut client).unwrap();
+    poll.registry().register(&mut &client, CLIENT, Interest::READABLE).unwrap();
+    client.send_close_notify().unwrap();
+    let mut eof = false;
+    while !eof {
+        let (readable, writable) = next_events(&mut poll, &mut events);
+        assert!(readable);
+        assert!(!writable);
+        //A spurious wakeup just waits for the next event.
+        match client.read(&mut buf) {
+            Ok(0) => eof = true,
+            Ok(count) => panic!("unexpected data {:?}", &buf[..count]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => panic!("{err}"),
+        }
+    }
+    poll.registry().deregister(&mut client).unwrap();
+    echo.join().unwrap();
+}
@@ -0,0 +1,121 @@
+#![cfg(all(windows, feature = "windows-named-pipe"))]
+
+mod common;
+
+use rust_tls_duplex_stream::{ClientDuplexStream, NamedPipeTransport, ServerDuplexStream};
+use std::ffi::c_void;
+use std::os::windows::io::{FromRawHandle, OwnedHandle};
+use std::thread;
+
+/// `PIPE_ACCESS_DUPLEX`
+const PIPE_ACCESS_DUPLEX: u32 = 0x3;
+/// `FILE_FLAG_OVERLAPPED`
+const FILE_FLAG_OVERLAPPED: u32 = 0x4000_0000;
+/// `PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT`
+const PIPE_BYTE_WAIT: u32 = 0;
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn CreateNamedPipeW(
+        name: *const u16,
+        open_mode: u32,
+        pipe_mode: u32,
+        max_instances: u32,
+        out_buffer_size: u32,
+        in_buffer_size: u32,
+        default_timeout: u32,
+        attributes: *const c_void,
+    ) -> *mut c_void;
+}
+
+/// Creates the server end of a new pipe, a client can connect to it right away.
+fn create_pipe(name: &str) -> OwnedHandle {
+    let wide: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+    // SAFETY: the name is nul terminated and outlives the call, null attributes ask for the default security.
+    let handle = unsafe {
+        CreateNamedPipeW(
+            wide.as_ptr(),
+            PIPE_ACCESS_DUPLEX | FILE_FLAG_OVERLAPPED,
+            PIPE_BYTE_WAIT,
+            1,
+            0x10000,
+            0x10000,
+            0,
+            std::ptr::null(),
+        )
+    };
+    assert_ne!(handle as isize, -1, "CreateNamedPipeW failed: {}", std::io::Error::last_os_error());
+    // SAFETY: the handle was just created and is owned by nothing else.
+    unsafe { OwnedHandle::from_raw_handle(handle) }
+}
+
+fn pipe_pair(name: &str) -> (ClientDuplexStream, ServerDuplexStream) {
+    let path = format!(r"\\.\pipe\{name}-{}", std::process::id());
+    let server_end = create_pipe(&path);
+    let (client_read, client_write) = NamedPipeTransport::connect(&path).unwrap();
+    let (server_read, server_write) = NamedPipeTransport::from_handle(server_end).unwrap();
+    let client = ClientDuplexStream::new_unpooled(common::client_connection(), client_read, client_write).unwrap();
+    let server = ServerDuplexStream::new_unpooled(common::server_connection(), server_read, server_write).unwrap();
+    (client, server)
+}
+
+#[test]
+fn named_pipe_ping_pong() {
+    let (client, server) = pipe_pair("ping-pong");
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        server.write_all(b"pong").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    client.write_all(b"ping").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+
+    //Closing one end is EOF for the other.
+    let server = handle.join().unwrap();
+    server.send_close_notify().unwrap();
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn named_pipe_full_duplex() {
+    //Both sides write more than the pipe buffers before reading, this only finishes if reads don't block writes.
+    let (client, server) = pipe_pair("full-duplex");
+    let data: Vec<u8> = (0..4_000_000u32).map(|i| (i % 251) as u8).collect();
+    let len = data.len();
+
+    let server_data = data.clone();
+    let handle = thread::spawn(move || {
+        let reader = thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                let mut received = vec![0u8; len];
+                server.read_exact(&mut received).unwrap();
+                received
+            });
+            server.write_all(&server_data).unwrap();
+            server.flush().unwrap();
+            reader.join().unwrap()
+        });
+        assert_eq!(reader, server_data);
+    });
+
+    thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let mut received = vec![0u8; len];
+            client.read_exact(&mut received).unwrap();
+            received
+        });
+        client.write_all(&data).unwrap();
+        client.flush().unwrap();
+        assert_eq!(reader.join().unwrap(), data);
+    });
+    handle.join().unwrap();
+}
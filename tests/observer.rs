@@ -0,0 +1,114 @@
+mod common;
+
+use rust_tls_duplex_stream::StreamObserver;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Observer that sums up everything it is told.
+#[derive(Default)]
+struct Totals {
+    read: AtomicUsize,
+    written: AtomicUsize,
+    waited: Mutex<Duration>,
+    max_depth: AtomicUsize,
+    depth_changes: AtomicUsize,
+}
+
+impl StreamObserver for Totals {
+    fn on_bytes_read(&self, count: usize) {
+        self.read.fetch_add(count, SeqCst);
+    }
+
+    fn on_bytes_written(&self, count: usize) {
+        self.written.fetch_add(count, SeqCst);
+    }
+
+    fn on_backpressure(&self, waited: Duration) {
+        *self.waited.lock().unwrap() += waited;
+    }
+
+    fn on_queue_depth_change(&self, depth: usize) {
+        self.max_depth.fetch_max(depth, SeqCst);
+        self.depth_changes.fetch_add(1, SeqCst);
+    }
+}
+
+#[test]
+fn observer_counts_plaintext() {
+    let (client, server) = common::stream_pair();
+    let client_totals = Arc::new(Totals::default());
+    let server_totals = Arc::new(Totals::default());
+    client.set_observer(Some(client_totals.clone()));
+    server.set_observer(Some(server_totals.clone()));
+
+    let server = common::ping(&client, server);
+    assert_eq!(client_totals.written.load(SeqCst), 4);
+    assert_eq!(client_totals.read.load(SeqCst), 4);
+    assert_eq!(server_totals.read.load(SeqCst), 4);
+    assert_eq!(server_totals.written.load(SeqCst), 4);
+    assert!(client_totals.depth_changes.load(SeqCst) > 0);
+
+    //Removed observers are not called anymore.
+    client.set_observer(None);
+    client.write_all(b"more").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 4];
+    server.read_exact(&mut buf).unwrap();
+    assert_eq!(client_totals.written.load(SeqCst), 4);
+    assert_eq!(server_totals.read.load(SeqCst), 8);
+}
+
+#[test]
+fn observer_backpressure() {
+    let (client, server) = common::connected_pair();
+    server.set_read_watermarks(1, 2).unwrap();
+    client.set_write_watermarks(1, 4).unwrap();
+    let totals = Arc::new(Totals::default());
+    client.set_observer(Some(totals.clone()));
+
+    let data: Vec<u8> = (0..16_000_000u32).map(|i| (i % 233) as u8).collect();
+    let len = data.len();
+    let writer = thread::spawn(move || {
+        client.write_all(&data).unwrap();
+        client.flush().unwrap();
+        client
+    });
+
+    //The server reads nothing for a while, so the writes have to wait.
+    thread::sleep(Duration::from_millis(300));
+    let mut received = vec![0u8; len];
+    server.read_exact(&mut received).unwrap();
+    drop(writer.join().unwrap());
+
+    assert_eq!(totals.written.load(SeqCst), len);
+    assert!(*totals.waited.lock().unwrap() >= Duration::from_millis(100));
+    assert!(totals.max_depth.load(SeqCst) > 1);
+}
+
+/// Observer that panics on every call.
+struct Panicking(AtomicUsize);
+
+impl StreamObserver for Panicking {
+    fn on_bytes_written(&self, _count: usize) {
+        self.0.fetch_add(1, SeqCst);
+        panic!("broken exporter");
+    }
+}
+
+#[test]
+fn observer_panic_removes_it() {
+    let (client, server) = common::stream_pair();
+    let observer = Arc::new(Panicking(AtomicUsize::new(0)));
+    client.set_observer(Some(observer.clone()));
+
+    let server = common::ping(&client, server);
+    client.write_all(b"more").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 4];
+    server.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"more");
+    assert_eq!(observer.0.load(SeqCst), 1);
+}
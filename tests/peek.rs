@@ -0,0 +1,161 @@
+mod common;
+
+use rust_tls_duplex_stream::DeadlineExceeded;
+use std::io::ErrorKind;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn peek_does_not_consume() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"GET / HTTP/1.1").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut first = [0u8; 3];
+    let count = client.peek(&mut first).unwrap();
+    assert!(count > 0);
+    let mut second = [0u8; 3];
+    assert_eq!(client.peek(&mut second).unwrap(), count);
+    assert_eq!(first, second);
+
+    let mut all = [0u8; 14];
+    client.read_exact(&mut all).unwrap();
+    assert_eq!(&all, b"GET / HTTP/1.1");
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn peek_non_blocking() {
+    let (client, server) = common::stream_pair();
+    client.set_read_non_block(true).unwrap();
+    let err = client.peek(&mut [0u8; 4]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    drop(server);
+}
+
+#[test]
+fn read_available() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"hello").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut out = Vec::new();
+    let mut peeked = [0u8; 1];
+    assert_eq!(client.peek(&mut peeked).unwrap(), 1);
+    while out.len() < 5 {
+        client.read_available(&mut out).unwrap();
+    }
+    assert_eq!(out, b"hello");
+    assert_eq!(client.read_available(&mut out).unwrap(), 0);
+    assert_eq!(out, b"hello");
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn read_chunk() {
+    let (client, server) = common::stream_pair();
+    assert_eq!(client.try_read_chunk().unwrap(), None);
+    let handle = thread::spawn(move || {
+        server.write_all(b"chunky").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut peeked = [0u8; 2];
+    assert_eq!(client.peek(&mut peeked).unwrap(), 2);
+    let mut data = Vec::new();
+    while data.len() < 6 {
+        let chunk = client.read_chunk().unwrap();
+        assert!(!chunk.is_empty());
+        data.extend(chunk);
+    }
+    assert_eq!(data, b"chunky");
+    assert_eq!(client.try_read_chunk().unwrap(), None);
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn wait_readable() {
+    let (client, server) = common::stream_pair();
+    let err = client.wait_readable(Some(Duration::from_millis(100))).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    let handle = thread::spawn(move || {
+        server.write_all(b"ab").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    client.wait_readable(None).unwrap();
+    let server = handle.join().unwrap();
+    let mut buf = [0u8; 1];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"a");
+    //The rest was decrypted by the read above and waits inside rust-tls.
+    client.wait_readable(Some(Duration::ZERO)).unwrap();
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"b");
+    let err = client.wait_readable(Some(Duration::ZERO)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    server.send_close_notify().unwrap();
+    client.wait_readable(Some(Duration::from_secs(10))).unwrap();
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn discard() {
+    let (client, server) = common::stream_pair();
+    client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let err = client.discard(4).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    let handle = thread::spawn(move || {
+        server.write_all(&vec![7u8; 100_000]).unwrap();
+        server.write_all(b"tail").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut peeked = [0u8; 2];
+    assert_eq!(client.peek(&mut peeked).unwrap(), 2);
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    assert_eq!(client.discard(0).unwrap(), 0);
+    assert_eq!(client.discard(100_000).unwrap(), 100_000);
+    let mut tail = [0u8; 4];
+    client.read_exact(&mut tail).unwrap();
+    assert_eq!(&tail, b"tail");
+
+    let server = handle.join().unwrap();
+    server.write_all(b"rest").unwrap();
+    server.flush().unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+    let err = client.discard(1000).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    let exceeded = err.get_ref().and_then(|err| err.downcast_ref::<DeadlineExceeded>()).unwrap();
+    assert_eq!(exceeded.transferred, 4);
+
+    server.send_close_notify().unwrap();
+    assert_eq!(client.discard(1000).unwrap(), 0);
+}
+
+#[test]
+fn discard_non_blocking() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"abc").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    client.wait_readable(Some(Duration::from_secs(10))).unwrap();
+    client.set_read_non_block(true).unwrap();
+    assert_eq!(client.discard(10).unwrap(), 3);
+    let err = client.discard(10).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    drop(handle.join().unwrap());
+}
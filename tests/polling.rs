@@ -0,0 +1,117 @@
+#![cfg(feature = "polling")]
+
+mod common;
+
+use polling::{Event, Events, PollMode, Poller};
+use rust_tls_duplex_stream::{ClientDuplexStream, PollerSink, ServerDuplexStream};
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Returns a connected pair whose handshake is done and a non-blocking client.
+fn settled_pair() -> (ClientDuplexStream, ServerDuplexStream) {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"hello").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+    let mut buf = [0u8; 5];
+    client.read_exact(&mut buf).unwrap();
+    client.set_read_non_block(true).unwrap();
+    (client, server)
+}
+
+/// Waits on the poller until the sink holds events or the timeout elapsed.
+fn wait_events(sink: &PollerSink, timeout: Duration) -> Vec<Event> {
+    let deadline = Instant::now() + timeout;
+    let mut events = Vec::new();
+    let mut polled = Events::new();
+    loop {
+        sink.drain_into(&mut events).unwrap();
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+            return events;
+        };
+        if !events.is_empty() {
+            return events;
+        }
+        sink.poller().wait(&mut polled, Some(left)).unwrap();
+    }
+}
+
+fn read_available(client: &ClientDuplexStream) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        match client.read(&mut buf) {
+            Ok(count) => data.extend_from_slice(&buf[..count]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return data,
+            Err(err) => panic!("{err}"),
+        }
+    }
+}
+
+fn send(server: &ServerDuplexStream, data: &[u8]) {
+    server.write_all(data).unwrap();
+    server.flush().unwrap();
+}
+
+#[test]
+fn oneshot() {
+    let (client, server) = settled_pair();
+    let sink = Arc::new(PollerSink::new(Arc::new(Poller::new().unwrap())));
+    client.attach_poller(&sink, Event::readable(3), PollMode::Oneshot).unwrap();
+    assert!(wait_events(&sink, Duration::from_millis(100)).is_empty());
+
+    send(&server, b"one");
+    let events = wait_events(&sink, Duration::from_secs(10));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].key, 3);
+    assert!(events[0].readable);
+    assert!(!events[0].writable);
+    assert_eq!(read_available(&client), b"one");
+
+    //Not re-armed, so nothing is posted.
+    send(&server, b"two");
+    assert!(wait_events(&sink, Duration::from_millis(200)).is_empty());
+
+    //Re-arming posts right away since data waits.
+    client.attach_poller(&sink, Event::readable(3), PollMode::Oneshot).unwrap();
+    let events = wait_events(&sink, Duration::from_millis(100));
+    assert_eq!(events.len(), 1);
+    assert!(events[0].readable);
+    assert_eq!(read_available(&client), b"two");
+}
+
+#[test]
+fn level() {
+    let (client, server) = settled_pair();
+    let sink = Arc::new(PollerSink::new(Arc::new(Poller::new().unwrap())));
+    client.attach_poller(&sink, Event::all(5), PollMode::Level).unwrap();
+
+    //The write queue is empty, so writing is possible right away.
+    let events = wait_events(&sink, Duration::from_millis(100));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].key, 5);
+    assert!(events[0].writable);
+    assert!(!events[0].readable);
+
+    for data in [b"one", b"two"] {
+        send(&server, data);
+        let events = wait_events(&sink, Duration::from_secs(10));
+        assert_eq!(events.len(), 1);
+        assert!(events[0].readable);
+        assert_eq!(read_available(&client), data);
+    }
+
+    client.detach_poller().unwrap();
+    send(&server, b"three");
+    assert!(wait_events(&sink, Duration::from_millis(200)).is_empty());
+    assert_eq!(read_available(&client), b"three");
+
+    let err = client.attach_poller(&sink, Event::readable(5), PollMode::Edge).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+}
@@ -0,0 +1,69 @@
+#![cfg(all(unix, feature = "unix"))]
+
+mod common;
+
+use std::io::ErrorKind;
+use std::os::fd::BorrowedFd;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Peeks through a duplicate of the fd, which shares the non-blocking flag and the pending bytes.
+fn readable(fd: BorrowedFd<'_>) -> bool {
+    //recv with MSG_PEEK works on unix sockets as well, UnixStream::peek is unstable.
+    let socket = TcpStream::from(fd.try_clone_to_owned().unwrap());
+    match socket.peek(&mut [0u8; 1]) {
+        Ok(count) => count > 0,
+        Err(err) if err.kind() == ErrorKind::WouldBlock => false,
+        Err(err) => panic!("{err}"),
+    }
+}
+
+fn await_readable(fd: BorrowedFd<'_>) {
+    let start = Instant::now();
+    while !readable(fd) {
+        assert!(start.elapsed() < Duration::from_secs(10), "fd never became readable");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn readiness_fd() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"early").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    //The data arrived before the fd existed.
+    let fd = client.readiness_fd().unwrap();
+    assert!(readable(fd));
+    client.set_read_non_block(true).unwrap();
+    let mut received = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        match client.read(&mut buf) {
+            Ok(count) => received.extend_from_slice(&buf[..count]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => panic!("{err}"),
+        }
+    }
+    assert_eq!(received, b"early");
+    assert!(!readable(fd));
+
+    server.write_all(b"late").unwrap();
+    server.flush().unwrap();
+    await_readable(fd);
+    let mut buf = [0u8; 4];
+    client.set_read_non_block(false).unwrap();
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"late");
+
+    server.send_close_notify().unwrap();
+    await_readable(fd);
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+}
@@ -0,0 +1,71 @@
+#![cfg(all(windows, feature = "windows"))]
+
+mod common;
+
+use std::ffi::c_void;
+use std::io::ErrorKind;
+use std::os::windows::io::RawHandle;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `WAIT_OBJECT_0`
+const WAIT_OBJECT_0: u32 = 0;
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn WaitForSingleObject(handle: *mut c_void, millis: u32) -> u32;
+}
+
+fn signaled(event: RawHandle) -> bool {
+    // SAFETY: the event stays valid while the stream lives, a zero timeout only queries the state.
+    unsafe { WaitForSingleObject(event, 0) == WAIT_OBJECT_0 }
+}
+
+fn await_signaled(event: RawHandle) {
+    let start = Instant::now();
+    while !signaled(event) {
+        assert!(start.elapsed() < Duration::from_secs(10), "event was never signaled");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn readiness_event() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"early").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    //The data arrived before the event existed.
+    let event = client.readiness_event().unwrap();
+    assert!(signaled(event));
+    client.set_read_non_block(true).unwrap();
+    let mut received = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        match client.read(&mut buf) {
+            Ok(count) => received.extend_from_slice(&buf[..count]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => panic!("{err}"),
+        }
+    }
+    assert_eq!(received, b"early");
+    assert!(!signaled(event));
+
+    server.write_all(b"late").unwrap();
+    server.flush().unwrap();
+    await_signaled(event);
+    let mut buf = [0u8; 4];
+    client.set_read_non_block(false).unwrap();
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"late");
+
+    server.send_close_notify().unwrap();
+    await_signaled(event);
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+}
@@ -0,0 +1,201 @@
+mod common;
+
+use rust_tls_duplex_stream::{ClientDuplexStream, ServerDuplexStream};
+use std::io::{ErrorKind, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Returns a server on a new loopback connection and the client socket of it.
+fn new_server() -> (TcpStream, ServerDuplexStream) {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    (client_socket, server)
+}
+
+#[test]
+fn reconnect_keeps_state() {
+    let (socket, server) = new_server();
+    let client =
+        ClientDuplexStream::new_unpooled(common::client_connection(), socket.try_clone().unwrap(), socket.try_clone().unwrap())
+            .unwrap();
+    client.set_label("reconnecting").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let server = common::ping(&client, server);
+    let before = client.connection_info().unwrap();
+
+    //Unread data of the old connection is discarded.
+    server.write_all(b"stale").unwrap();
+    server.flush().unwrap();
+    client.wait_readable(Some(Duration::from_secs(10))).unwrap();
+
+    socket.shutdown(Shutdown::Both).unwrap();
+    let (new_socket, next_server) = new_server();
+    client
+        .reconnect(common::client_connection(), new_socket.try_clone().unwrap(), new_socket)
+        .unwrap();
+    let next_server = common::ping(&client, next_server);
+
+    let after = client.connection_info().unwrap();
+    assert_eq!(after.id, before.id);
+    assert!(after.bytes_read > before.bytes_read);
+    assert!(after.bytes_written > before.bytes_written);
+    assert_eq!(client.label().unwrap().as_deref(), Some("reconnecting"));
+    assert_eq!(client.read_timeout().unwrap(), Some(Duration::from_secs(10)));
+
+    //The old server only sees its connection die.
+    let mut buf = [0u8; 4];
+    assert!(!matches!(server.read(&mut buf), Ok(count) if count > 0));
+
+    client.write_all(b"more").unwrap();
+    client.flush().unwrap();
+    next_server.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"more");
+}
+
+#[test]
+fn reconnect_after_failure() {
+    let (socket, server) = new_server();
+    let client =
+        ClientDuplexStream::new_unpooled(common::client_connection(), socket.try_clone().unwrap(), socket.try_clone().unwrap())
+            .unwrap();
+    common::ping(&client, server);
+
+    //The transport went away, the stream is dead.
+    socket.shutdown(Shutdown::Both).unwrap();
+    let mut buf = [0u8; 4];
+    assert!(!matches!(client.read(&mut buf), Ok(count) if count > 0));
+
+    let (new_socket, next_server) = new_server();
+    client
+        .reconnect(common::client_connection(), new_socket.try_clone().unwrap(), new_socket)
+        .unwrap();
+    common::ping(&client, next_server);
+}
+
+#[test]
+fn reconnect_custom_spawner() {
+    let spawner = |task: Box<dyn FnOnce() + Send>| thread::Builder::new().spawn(task).map(|_| {});
+    let (socket, server) = new_server();
+    let client =
+        ClientDuplexStream::new(common::client_connection(), socket.try_clone().unwrap(), socket.try_clone().unwrap(), spawner)
+            .unwrap();
+    common::ping(&client, server);
+    socket.shutdown(Shutdown::Both).unwrap();
+
+    let (new_socket, next_server) = new_server();
+    let err = client
+        .reconnect(common::client_connection(), new_socket.try_clone().unwrap(), new_socket.try_clone().unwrap())
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+    client
+        .reconnect_with(common::client_connection(), new_socket.try_clone().unwrap(), new_socket, spawner)
+        .unwrap();
+    common::ping(&client, next_server);
+}
+
+/// Writes to a socket the test can exchange, like a server that continues the session on a new connection.
+struct MovableWriter(Arc<Mutex<TcpStream>>);
+
+impl Write for MovableWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+/// Returns a client and a server whose writes go to the socket in the returned mutex.
+fn movable_pair(socket: TcpStream, server_socket: TcpStream) -> (ClientDuplexStream, ServerDuplexStream, Arc<Mutex<TcpStream>>) {
+    let server_out = Arc::new(Mutex::new(server_socket.try_clone().unwrap()));
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket,
+        MovableWriter(Arc::clone(&server_out)),
+    )
+    .unwrap();
+    let client =
+        ClientDuplexStream::new_unpooled(common::client_connection(), socket.try_clone().unwrap(), socket).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    (client, server, server_out)
+}
+
+#[test]
+fn replace_read_transport_keeps_queued_data() {
+    let (old_socket, old_server_socket) = common::tcp_pair();
+    let (new_socket, new_server_socket) = common::tcp_pair();
+    let (client, server, server_out) = movable_pair(old_socket.try_clone().unwrap(), old_server_socket);
+    let server = common::ping(&client, server);
+
+    //Received from the old connection but not read yet.
+    server.write_all(b"old data").unwrap();
+    server.flush().unwrap();
+    let sent = server.connection_info().unwrap().bytes_written;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while client.connection_info().unwrap().bytes_read < sent {
+        assert!(Instant::now() < deadline, "data of the old connection did not arrive");
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(client.pending_read_bytes() > 0);
+
+    client.replace_read_transport(new_socket).unwrap();
+    *server_out.lock().unwrap() = new_server_socket;
+    old_socket.shutdown(Shutdown::Read).unwrap();
+
+    server.write_all(b" new data").unwrap();
+    server.flush().unwrap();
+    let mut buf = [0u8; 17];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"old data new data");
+
+    //The client still writes to the old connection.
+    common::ping(&client, server);
+}
+
+#[test]
+fn replace_read_transport_wakes_waiting_read() {
+    let (old_socket, old_server_socket) = common::tcp_pair();
+    let (new_socket, new_server_socket) = common::tcp_pair();
+    let (client, server, server_out) = movable_pair(old_socket.try_clone().unwrap(), old_server_socket);
+    let server = common::ping(&client, server);
+
+    thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let mut buf = [0u8; 5];
+            client.read_exact(&mut buf).unwrap();
+            buf
+        });
+        //Give the read time to start waiting.
+        thread::sleep(Duration::from_millis(100));
+
+        client.replace_read_transport(new_socket).unwrap();
+        *server_out.lock().unwrap() = new_server_socket;
+        old_socket.shutdown(Shutdown::Read).unwrap();
+        server.write_all(b"hello").unwrap();
+        server.flush().unwrap();
+        assert_eq!(&reader.join().unwrap(), b"hello");
+    });
+}
+
+#[test]
+fn replace_read_transport_after_eof() {
+    let (socket, server_socket) = common::tcp_pair();
+    let (client, server, _server_out) = movable_pair(socket, server_socket.try_clone().unwrap());
+    common::ping(&client, server);
+    server_socket.shutdown(Shutdown::Both).unwrap();
+
+    let mut rest = Vec::new();
+    _ = client.read_to_end(&mut rest);
+    let (new_socket, _new_server) = common::tcp_pair();
+    let err = client.replace_read_transport(new_socket).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use rust_tls_duplex_stream::{ServerDuplexStream, SingleThreadedDuplexStream};
+use std::io::{Read, Write};
+use std::thread;
+
+#[test]
+fn single_threaded_echo() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    let echo = thread::spawn(move || {
+        let mut buf = [0u8; 0x1000];
+        loop {
+            let count = server.read(&mut buf).unwrap();
+            if count == 0 {
+                break;
+            }
+            server.write_all(&buf[..count]).unwrap();
+            server.flush().unwrap();
+        }
+        server.send_close_notify().unwrap();
+    });
+
+    let mut client = SingleThreadedDuplexStream::new(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+    );
+    for round in 0..16u8 {
+        let message = vec![round; 0x800 * usize::from(round + 1)];
+        client.write_all(&message).unwrap();
+        client.flush().unwrap();
+        let mut received = vec![0u8; message.len()];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(received, message);
+    }
+    assert!(!client.connection().is_handshaking());
+
+    client.connection_mut().send_close_notify();
+    client.flush().unwrap();
+    let mut buf = [0u8; 1];
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+    echo.join().unwrap();
+
+    let (_conn, read, _write) = client.into_inner();
+    assert!(read.peer_addr().is_ok());
+}
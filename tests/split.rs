@@ -0,0 +1,157 @@
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn halves_in_different_threads() {
+    let (client, server) = common::stream_pair();
+    client
+        .set_read_timeout(Some(Duration::from_secs(30)))
+        .unwrap();
+    let (mut read, mut write) = client.split().unwrap();
+    assert_eq!(read.read_timeout(), Some(Duration::from_secs(30)));
+    assert_eq!(write.write_timeout(), None);
+
+    let echo = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(&buf).unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let writer = thread::spawn(move || {
+        write.write_all(b"ping").unwrap();
+        write.flush().unwrap();
+        write
+    });
+    let mut buf = [0u8; 4];
+    read.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+
+    let write = writer.join().unwrap();
+    let client = read.reunite(write).unwrap();
+    assert_eq!(
+        client.read_timeout().unwrap(),
+        Some(Duration::from_secs(30))
+    );
+    drop(echo.join().unwrap());
+    drop(client);
+}
+
+#[test]
+fn read_half_timeout_and_drop() {
+    let (client, server) = common::stream_pair();
+    let (mut read, mut write) = client.split().unwrap();
+    read.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    assert_eq!(
+        read.set_read_timeout(Some(Duration::ZERO)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        write.set_write_timeout(Some(Duration::ZERO)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        server
+    });
+
+    let err = read.read(&mut [0u8; 4]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    drop(read);
+
+    write.write_all(b"hello").unwrap();
+    write.flush().unwrap();
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn reunite_mismatch() {
+    let (first, _first_server) = common::stream_pair();
+    let (second, _second_server) = common::stream_pair();
+    let (first_read, first_write) = first.split().unwrap();
+    let (second_read, second_write) = second.split().unwrap();
+
+    let err = first_read.reunite(second_write).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "tried to reunite halves that are not from the same stream"
+    );
+    let first = err.0.reunite(first_write).unwrap();
+    let second = second_read.reunite(err.1).unwrap();
+    assert_ne!(first.connection_id(), second.connection_id());
+}
+
+#[test]
+fn shared_halves_in_thread_pool() {
+    let (client, server) = common::stream_pair();
+    let client = Arc::new(client);
+    let write = client.clone_write_half().unwrap();
+    let mut read = client.clone_read_half().unwrap();
+    read.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    assert_eq!(read.clone().read_timeout(), Some(Duration::from_millis(50)));
+    assert_eq!(client.read_timeout().unwrap(), None);
+
+    let echo = thread::spawn(move || {
+        let mut buf = vec![0u8; 800];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(&buf).unwrap();
+        server.flush().unwrap();
+        (server, buf)
+    });
+
+    let writers: Vec<_> = (0..4u8)
+        .map(|tag| {
+            let mut write = write.clone();
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    write.write_all(&[tag; 4]).unwrap();
+                }
+                write.flush().unwrap();
+            })
+        })
+        .collect();
+
+    let received = Arc::new(AtomicUsize::new(0));
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let mut read = read.clone();
+            let received = Arc::clone(&received);
+            thread::spawn(move || {
+                let mut buf = [0u8; 64];
+                while received.load(SeqCst) < 800 {
+                    match read.read(&mut buf) {
+                        Ok(count) => _ = received.fetch_add(count, SeqCst),
+                        Err(err) if err.kind() == ErrorKind::TimedOut => {}
+                        Err(err) => panic!("{err}"),
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(received.load(SeqCst), 800);
+
+    let (_server, sent) = echo.join().unwrap();
+    for tag in 0..4u8 {
+        assert_eq!(sent.iter().filter(|byte| **byte == tag).count(), 200);
+    }
+    for message in sent.chunks(4) {
+        assert!(message.iter().all(|byte| *byte == message[0]));
+    }
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use rust_tls_duplex_stream::test_utils::in_memory_pair;
+use rust_tls_duplex_stream::{ClientDuplexStream, ServerDuplexStream};
+use std::io::IoSlice;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+fn memory_pair() -> (ClientDuplexStream, ServerDuplexStream) {
+    let (client_transport, server_transport) = in_memory_pair();
+    let client =
+        ClientDuplexStream::new_unpooled(common::client_connection(), client_transport.clone(), client_transport)
+            .unwrap();
+    let server =
+        ServerDuplexStream::new_unpooled(common::server_connection(), server_transport.clone(), server_transport)
+            .unwrap();
+    (client, server)
+}
+
+#[test]
+fn stats_count_plaintext() {
+    let before = SystemTime::now();
+    let (client, server) = memory_pair();
+    let stats = client.stats();
+    assert!(stats.created_at >= before);
+    assert_eq!((stats.bytes_read, stats.bytes_written, stats.reads, stats.writes), (0, 0, 0, 0));
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 11];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello world");
+        server.write_all(b"pong").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    assert_eq!(client.write(b"hello").unwrap(), 5);
+    assert_eq!(client.write_vectored(&[IoSlice::new(b" "), IoSlice::new(b"world")]).unwrap(), 6);
+    client.flush().unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+    let server = handle.join().unwrap();
+
+    let stats = client.stats();
+    assert_eq!(stats.bytes_written, 11);
+    assert_eq!(stats.writes, 2);
+    assert_eq!(stats.bytes_read, 4);
+    assert!(stats.reads >= 1);
+
+    let stats = server.stats();
+    assert_eq!(stats.bytes_read, 11);
+    assert_eq!(stats.bytes_written, 4);
+    assert!(stats.writes >= 1);
+
+    //Peeked bytes count once.
+    server.write_all(b"more").unwrap();
+    server.flush().unwrap();
+    let reads = client.stats().reads;
+    let mut peeked = [0u8; 4];
+    let count = client.peek(&mut peeked).unwrap();
+    assert!(count > 0);
+    let mut received = Vec::new();
+    while received.len() < 4 {
+        client.read_available(&mut received).unwrap();
+    }
+    assert_eq!(received, b"more");
+    let stats = client.stats();
+    assert_eq!(stats.bytes_read, 8);
+    assert!(stats.reads > reads);
+
+    server.send_close_notify().unwrap();
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(client.stats().bytes_read, 8);
+}
+
+#[test]
+fn activity_timestamps() {
+    //The timestamps are truncated to whole milliseconds.
+    let slack = Duration::from_millis(1);
+    let created = Instant::now();
+    let (client, server) = memory_pair();
+    assert!(client.last_read_activity() + slack >= created);
+    assert_eq!(client.last_read_activity(), client.last_write_activity());
+    thread::sleep(Duration::from_millis(50));
+    assert!(client.idle_duration() >= Duration::from_millis(49));
+
+    //The handshake counts as activity of both directions.
+    let before = Instant::now();
+    let handle = thread::spawn(move || {
+        server.handshake().unwrap();
+        server
+    });
+    client.handshake().unwrap();
+    let server = handle.join().unwrap();
+    assert!(client.last_read_activity() + slack >= before);
+    assert!(client.last_write_activity() + slack >= before);
+
+    thread::sleep(Duration::from_millis(50));
+    let before = Instant::now();
+    client.write_all(b"ping").unwrap();
+    client.flush().unwrap();
+    assert!(client.last_write_activity() + slack >= before);
+    assert!(client.last_read_activity() < before);
+    assert!(client.idle_duration() < Duration::from_millis(50));
+
+    let mut buf = [0u8; 4];
+    server.read_exact(&mut buf).unwrap();
+    assert!(server.last_read_activity() + slack >= before);
+}
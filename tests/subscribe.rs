@@ -0,0 +1,166 @@
+mod common;
+
+use common::FailingWriter;
+use rust_tls_duplex_stream::{ClientDuplexStream, ServerDuplexStream, StreamEvent};
+use std::io::ErrorKind;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn next(events: &Receiver<StreamEvent>) -> StreamEvent {
+    events.recv_timeout(Duration::from_secs(10)).unwrap()
+}
+
+/// Drops the `Readable` of the pong, the background reader may report it after `ping` already read the pong.
+fn settle(events: &Receiver<StreamEvent>) {
+    while let Ok(event) = events.recv_timeout(Duration::from_millis(100)) {
+        assert_eq!(event, StreamEvent::Readable);
+    }
+}
+
+fn assert_no_more(events: &Receiver<StreamEvent>) {
+    assert_eq!(events.recv_timeout(Duration::from_millis(100)), Err(RecvTimeoutError::Timeout));
+}
+
+fn assert_disconnected(events: &Receiver<StreamEvent>) {
+    assert_eq!(events.recv_timeout(Duration::from_secs(10)), Err(RecvTimeoutError::Disconnected));
+}
+
+#[test]
+fn subscribe_readable_coalesces() {
+    let (client, server) = common::connected_pair();
+    let events = client.subscribe();
+    settle(&events);
+
+    //Nothing is drained, so the whole burst is one edge.
+    for _ in 0..16 {
+        server.write_all(b"burst").unwrap();
+        server.flush().unwrap();
+    }
+    assert_eq!(next(&events), StreamEvent::Readable);
+    //Draining while the burst still arrives would end the edge early.
+    let sent = server.connection_info().unwrap().bytes_written;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while client.connection_info().unwrap().bytes_read < sent {
+        assert!(Instant::now() < deadline, "burst did not arrive");
+        thread::sleep(Duration::from_millis(1));
+    }
+    let mut received = Vec::new();
+    while received.len() < 16 * 5 {
+        client.read_available(&mut received).unwrap();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_no_more(&events);
+
+    server.write_all(b"again").unwrap();
+    server.flush().unwrap();
+    assert_eq!(next(&events), StreamEvent::Readable);
+}
+
+#[test]
+fn subscribe_data_before_subscription() {
+    let (client, server) = common::connected_pair();
+    let first = client.subscribe();
+    settle(&first);
+    server.write_all(b"early").unwrap();
+    server.flush().unwrap();
+    assert_eq!(next(&first), StreamEvent::Readable);
+
+    //Only the new receiver learns about the data again.
+    let events = client.subscribe();
+    assert_eq!(next(&events), StreamEvent::Readable);
+    assert_no_more(&events);
+    assert_no_more(&first);
+}
+
+#[test]
+fn subscribe_peer_closed_then_drop() {
+    let (client, server) = common::connected_pair();
+    let events = client.subscribe();
+    let other = client.subscribe();
+    drop(other);
+    settle(&events);
+
+    server.send_close_notify().unwrap();
+    let mut buf = [0u8; 4];
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+    //The read may see the close_notify before the background reader reported it.
+    let received = [next(&events), next(&events)];
+    assert!(received.contains(&StreamEvent::Readable), "{received:?}");
+    assert!(received.contains(&StreamEvent::PeerClosed), "{received:?}");
+    assert_no_more(&events);
+
+    drop(client);
+    assert_disconnected(&events);
+}
+
+#[test]
+fn subscribe_error_disconnects() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let fail = Arc::new(AtomicBool::new(false));
+    let writer = FailingWriter {
+        inner: client_socket.try_clone().unwrap(),
+        fail: Arc::clone(&fail),
+    };
+    let client = ClientDuplexStream::new_unpooled(common::client_connection(), client_socket, writer).unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    let _server = common::ping(&client, server);
+    let events = client.subscribe();
+
+    fail.store(true, SeqCst);
+    client.write_all(b"lost").unwrap();
+    _ = client.flush();
+    assert_eq!(next(&events), StreamEvent::Error(ErrorKind::PermissionDenied));
+    assert_disconnected(&events);
+
+    //A dead stream disconnects new receivers right away.
+    assert_disconnected(&client.subscribe());
+}
+
+#[test]
+fn subscribe_write_capacity() {
+    let (client, server) = common::connected_pair();
+    client.set_write_watermarks(1, 8).unwrap();
+    server.set_read_watermarks(1, 2).unwrap();
+    let events = client.subscribe();
+
+    //The server reads nothing, so its buffer, the transport and then the write queue fill up.
+    //The writer may still make progress after the first refusal, so keep writing until it stalled for a while.
+    let chunk = vec![7u8; 0x4000];
+    let mut written = 0;
+    let mut stalled = 0;
+    while stalled < 10 {
+        match client.try_write(&chunk) {
+            Ok(count) => {
+                written += count;
+                stalled = 0;
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                stalled += 1;
+                thread::sleep(Duration::from_millis(20));
+            }
+            Err(err) => panic!("{err}"),
+        }
+    }
+    while events.try_recv().is_ok() {}
+    assert_eq!(client.try_write(&chunk).unwrap_err().kind(), ErrorKind::WouldBlock);
+    assert_no_more(&events);
+
+    let handle = thread::spawn(move || {
+        let mut buf = vec![0u8; written];
+        server.read_exact(&mut buf).unwrap();
+        server
+    });
+    assert_eq!(next(&events), StreamEvent::WriteCapacityAvailable);
+    client.flush().unwrap();
+    handle.join().unwrap();
+    assert_no_more(&events);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use rust_tls_duplex_stream::Tap;
+use std::io::IoSlice;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn capture() -> (Arc<Mutex<Vec<u8>>>, Tap) {
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&captured);
+    (captured, Box::new(move |data| sink.lock().unwrap().extend_from_slice(data)))
+}
+
+#[test]
+fn taps_see_all_plaintext() {
+    let (client, server) = common::stream_pair();
+    let data: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    let (written, tap) = capture();
+    server.set_write_tap(tap);
+    let (read, tap) = capture();
+    client.set_read_tap(tap);
+
+    let expected = data.clone();
+    let handle = thread::spawn(move || {
+        server.write_all(&data[..100_000]).unwrap();
+        let mut rest = &data[100_000..];
+        while !rest.is_empty() {
+            let half = rest.len() / 2;
+            let count = server
+                .write_vectored(&[IoSlice::new(&rest[..half]), IoSlice::new(&rest[half..])])
+                .unwrap();
+            rest = &rest[count..];
+        }
+        server.send_close_notify().unwrap();
+        server
+    });
+
+    let mut received = Vec::new();
+    let mut peeked = [0u8; 16];
+    client.peek(&mut peeked).unwrap();
+    let mut buf = [0u8; 3000];
+    loop {
+        let count = client.read(&mut buf).unwrap();
+        if count == 0 {
+            break;
+        }
+        received.extend_from_slice(&buf[..count]);
+    }
+    let _server = handle.join().unwrap();
+
+    assert_eq!(received, expected);
+    assert_eq!(*read.lock().unwrap(), expected);
+    assert_eq!(*written.lock().unwrap(), expected);
+}
+
+#[test]
+fn panicking_tap_is_removed() {
+    let (client, server) = common::stream_pair();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&calls);
+    client.set_write_tap(Box::new(move |_| {
+        counter.fetch_add(1, SeqCst);
+        panic!("tap failed");
+    }));
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 6];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"abcdef");
+        server
+    });
+    client.write_all(b"abc").unwrap();
+    client.write_all(b"def").unwrap();
+    client.flush().unwrap();
+    drop(handle.join().unwrap());
+    assert_eq!(calls.load(SeqCst), 1);
+
+    let (written, tap) = capture();
+    client.set_write_tap(tap);
+    client.write_all(b"more").unwrap();
+    client.clear_taps();
+    client.write_all(b"unseen").unwrap();
+    assert_eq!(*written.lock().unwrap(), b"more");
+}
@@ -0,0 +1,821 @@
+mod common;
+
+use common::{DribblingReader, FailingWriter, StallingWriter};
+use rust_tls_duplex_stream::{ClientDuplexStream, DeadlineExceeded, ServerDuplexStream, StreamConfig};
+use std::io::ErrorKind;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn slow_consumer_times_out_writer() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let stall = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        StallingWriter {
+            inner: client_socket.try_clone().unwrap(),
+            stall: Arc::clone(&stall),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut sink = Vec::new();
+        _ = server.read_to_end(&mut sink);
+    });
+
+    client.flush().unwrap();
+    client
+        .set_write_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    stall.store(true, SeqCst);
+
+    let mut result = Ok(0);
+    for _ in 0..100_000 {
+        result = client.write(b"x");
+        if result.is_err() {
+            break;
+        }
+    }
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
+    stall.store(false, SeqCst);
+    client_socket.shutdown(std::net::Shutdown::Both).unwrap();
+    drop(client);
+    handle.join().unwrap();
+}
+
+#[test]
+fn trickling_record_does_not_restart_read_timeout() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let dribble = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        DribblingReader::new(client_socket.try_clone().unwrap(), Arc::clone(&dribble)),
+        client_socket,
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(b"y").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    client.read_exact(&mut [0u8; 1]).unwrap();
+    let server = handle.join().unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    // Every byte of the record wakes the reader without producing plaintext.
+    dribble.store(true, SeqCst);
+    server.write_all(&[7u8; 200]).unwrap();
+    server.flush().unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_millis(300)))
+        .unwrap();
+    let start = Instant::now();
+    let err = client.read(&mut [0u8; 200]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    dribble.store(false, SeqCst);
+    let mut data = [0u8; 200];
+    client.read_exact_timeout(&mut data, Duration::from_secs(10)).unwrap();
+    assert_eq!(data, [7u8; 200]);
+}
+
+#[test]
+fn deadlines() {
+    let (client, server) = common::stream_pair();
+    let past = Instant::now();
+    thread::sleep(Duration::from_millis(1));
+    assert_eq!(
+        client.write_before(b"x", past).unwrap_err().kind(),
+        ErrorKind::TimedOut
+    );
+    assert_eq!(
+        client.read_before(&mut [0u8; 4], past).unwrap_err().kind(),
+        ErrorKind::TimedOut
+    );
+
+    let handle = thread::spawn(move || {
+        server.write_all(b"abc").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut buf = [0u8; 3];
+    client.read_exact_before(&mut buf, deadline).unwrap();
+    assert_eq!(&buf, b"abc");
+    client.write_all_before(b"def", deadline).unwrap();
+
+    let start = Instant::now();
+    let err = client
+        .read_exact_before(&mut [0u8; 4], start + Duration::from_millis(200))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn deadline_reports_transferred() {
+    let (client, server) = common::stream_pair();
+
+    let handle = thread::spawn(move || {
+        server.write_all(b"abc").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut buf = [0u8; 8];
+    let err = client
+        .read_exact_deadline(&mut buf, Instant::now() + Duration::from_millis(300))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    let payload = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<DeadlineExceeded>())
+        .unwrap();
+    assert_eq!(payload.transferred, 3);
+    assert_eq!(&buf[..3], b"abc");
+
+    let err = client
+        .write_all_deadline(b"x", Instant::now() - Duration::from_millis(1))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    let payload = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<DeadlineExceeded>())
+        .unwrap();
+    assert_eq!(payload, &DeadlineExceeded { transferred: 0 });
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn per_call_timeouts() {
+    let (client, server) = common::stream_pair();
+    client
+        .set_read_timeout(Some(Duration::from_secs(60)))
+        .unwrap();
+
+    let handle = thread::spawn(move || {
+        server.write_all(b"ab").unwrap();
+        server.flush().unwrap();
+        thread::sleep(Duration::from_millis(150));
+        server.write_all(b"c").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    let mut buf = [0u8; 3];
+    client
+        .read_exact_timeout(&mut buf, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(&buf, b"abc");
+    client
+        .write_all_timeout(b"def", Duration::from_secs(5))
+        .unwrap();
+
+    let start = Instant::now();
+    let err = client
+        .read_exact_timeout(&mut [0u8; 4], Duration::from_millis(200))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert!(start.elapsed() < Duration::from_secs(60));
+
+    let err = client
+        .read_exact_timeout(&mut [0u8; 4], Duration::MAX)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn timeout_override() {
+    let (client, server) = common::stream_pair();
+    client
+        .set_read_timeout(Some(Duration::from_secs(60)))
+        .unwrap();
+    client
+        .set_write_timeout(Some(Duration::from_millis(1)))
+        .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 3];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"abc");
+        thread::sleep(Duration::from_millis(200));
+        server.write_all(b"d").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    assert_eq!(client.write_with_timeout(b"abc", None).unwrap(), 3);
+    client.flush().unwrap();
+
+    let start = Instant::now();
+    let err = client
+        .read_with_timeout(&mut [0u8; 4], Some(Duration::from_millis(50)))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_secs(60));
+
+    client
+        .set_read_timeout(Some(Duration::from_millis(1)))
+        .unwrap();
+    let mut buf = [0u8; 4];
+    assert_eq!(client.read_with_timeout(&mut buf, None).unwrap(), 1);
+    assert_eq!(&buf[..1], b"d");
+
+    assert_eq!(
+        client.read_timeout().unwrap(),
+        Some(Duration::from_millis(1))
+    );
+    assert_eq!(
+        client.write_timeout().unwrap(),
+        Some(Duration::from_millis(1))
+    );
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn flush_honors_write_timeout() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let stall = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        StallingWriter {
+            inner: client_socket,
+            stall: Arc::clone(&stall),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 6];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"abcdef");
+        server
+    });
+
+    client.flush().unwrap();
+    stall.store(true, SeqCst);
+    // The background writer takes the first record and stalls, the second one stays queued.
+    client.write_all(b"abc").unwrap();
+    client.write_all(b"def").unwrap();
+    client
+        .set_write_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let start = Instant::now();
+    assert_eq!(client.flush().unwrap_err().kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!(
+        client
+            .flush_with_timeout(Some(Duration::from_millis(50)))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::TimedOut
+    );
+
+    stall.store(false, SeqCst);
+    client.flush().unwrap();
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn try_write_and_flush_non_block() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let stall = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        StallingWriter {
+            inner: client_socket.try_clone().unwrap(),
+            stall: Arc::clone(&stall),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut sink = Vec::new();
+        _ = server.read_to_end(&mut sink);
+    });
+
+    client.flush().unwrap();
+    stall.store(true, SeqCst);
+
+    let mut result = Ok(0);
+    for _ in 0..100_000 {
+        result = client.try_write(b"x");
+        if result.is_err() {
+            break;
+        }
+    }
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
+    assert_eq!(
+        client.flush_non_block().unwrap_err().kind(),
+        ErrorKind::WouldBlock
+    );
+    stall.store(false, SeqCst);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        match client.flush_non_block() {
+            Ok(()) => break,
+            Err(err) => assert_eq!(err.kind(), ErrorKind::WouldBlock),
+        }
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+    client.flush().unwrap();
+    client.try_write(b"x").unwrap();
+    client_socket.shutdown(std::net::Shutdown::Both).unwrap();
+    drop(client);
+    handle.join().unwrap();
+}
+
+#[test]
+fn write_non_block() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let stall = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        StallingWriter {
+            inner: client_socket.try_clone().unwrap(),
+            stall: Arc::clone(&stall),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut sink = Vec::new();
+        _ = server.read_to_end(&mut sink);
+    });
+
+    client.flush().unwrap();
+    assert!(client.is_writable().unwrap());
+    stall.store(true, SeqCst);
+    client.set_write_non_block(true).unwrap();
+
+    let mut result = Ok(0);
+    for _ in 0..100_000 {
+        result = client.write(&[0u8; 64]);
+        if result.is_err() {
+            break;
+        }
+    }
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
+    assert!(!client.is_writable().unwrap());
+    stall.store(false, SeqCst);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !client.is_writable().unwrap() {
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(client.write(&[0u8; 64]).unwrap() > 0);
+    client.set_write_non_block(false).unwrap();
+    client.flush().unwrap();
+    client_socket.shutdown(std::net::Shutdown::Both).unwrap();
+    drop(client);
+    handle.join().unwrap();
+}
+
+#[test]
+fn zero_timeouts() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let stall = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        StallingWriter {
+            inner: client_socket.try_clone().unwrap(),
+            stall: Arc::clone(&stall),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    let handle = thread::spawn(move || {
+        let mut sink = Vec::new();
+        _ = server.read_to_end(&mut sink);
+    });
+    client.flush().unwrap();
+
+    //Like TcpStream a zero stored timeout is rejected and the old one is kept.
+    client.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    client.set_write_timeout(Some(Duration::from_secs(1))).unwrap();
+    assert_eq!(
+        client.set_read_timeout(Some(Duration::ZERO)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        client.set_write_timeout(Some(Duration::ZERO)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(client.read_timeout().unwrap(), Some(Duration::from_secs(1)));
+    assert_eq!(client.write_timeout().unwrap(), Some(Duration::from_secs(1)));
+
+    //A zero timeout for a single call polls once.
+    let start = Instant::now();
+    assert_eq!(
+        client
+            .read_with_timeout(&mut [0u8; 16], Some(Duration::ZERO))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::TimedOut
+    );
+    assert!(start.elapsed() < Duration::from_secs(1));
+    stall.store(true, SeqCst);
+    assert_eq!(client.write_with_timeout(b"x", Some(Duration::ZERO)).unwrap(), 1);
+    assert_eq!(
+        client.flush_with_timeout(Some(Duration::ZERO)).unwrap_err().kind(),
+        ErrorKind::TimedOut
+    );
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    stall.store(false, SeqCst);
+    client.flush().unwrap();
+    client_socket.shutdown(std::net::Shutdown::Both).unwrap();
+    drop(client);
+    handle.join().unwrap();
+}
+
+#[test]
+fn non_block_wakes_blocked_read() {
+    let (client, server) = common::stream_pair();
+    let client = Arc::new(client);
+    let reader = Arc::clone(&client);
+    let handle = thread::spawn(move || {
+        let start = Instant::now();
+        let err = reader.read(&mut [0u8; 16]).unwrap_err();
+        (err.kind(), start.elapsed())
+    });
+
+    thread::sleep(Duration::from_millis(200));
+    client.set_read_non_block(true).unwrap();
+    let (kind, elapsed) = handle.join().unwrap();
+    assert_eq!(kind, ErrorKind::WouldBlock);
+    assert!(elapsed < Duration::from_secs(5));
+    drop(server);
+}
+
+#[test]
+fn shortened_timeouts_release_blocked_calls() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let stall = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        StallingWriter {
+            inner: client_socket.try_clone().unwrap(),
+            stall: Arc::clone(&stall),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(&buf).unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    client.read_exact(&mut [0u8; 1]).unwrap();
+    let server = handle.join().unwrap();
+
+    let client = Arc::new(client);
+    client.set_read_timeout(Some(Duration::from_secs(60))).unwrap();
+    let reader = Arc::clone(&client);
+    let handle = thread::spawn(move || {
+        let start = Instant::now();
+        (reader.read(&mut [0u8; 16]).unwrap_err().kind(), start.elapsed())
+    });
+    thread::sleep(Duration::from_millis(200));
+    client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let (kind, elapsed) = handle.join().unwrap();
+    assert_eq!(kind, ErrorKind::TimedOut);
+    assert!(elapsed < Duration::from_secs(10));
+
+    stall.store(true, SeqCst);
+    client.set_write_timeout(Some(Duration::from_secs(60))).unwrap();
+    let writer = Arc::clone(&client);
+    let handle = thread::spawn(move || {
+        let start = Instant::now();
+        loop {
+            if let Err(err) = writer.write(&[0u8; 64]) {
+                return (err.kind(), start.elapsed());
+            }
+        }
+    });
+    thread::sleep(Duration::from_millis(500));
+    assert!(!handle.is_finished());
+    client.set_write_timeout(Some(Duration::from_millis(100))).unwrap();
+    let (kind, elapsed) = handle.join().unwrap();
+    assert_eq!(kind, ErrorKind::TimedOut);
+    assert!(elapsed < Duration::from_secs(10));
+
+    stall.store(false, SeqCst);
+    client_socket.shutdown(std::net::Shutdown::Both).unwrap();
+    drop(client);
+    drop(server);
+}
+
+#[test]
+fn idle_read_timeout() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let client = ClientDuplexStream::with_config(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        client_socket,
+        |task| thread::Builder::new().spawn(task).map(|_| {}),
+        &StreamConfig {
+            idle_read_timeout: Some(Duration::from_millis(400)),
+            ..StreamConfig::default()
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    assert_eq!(
+        client.set_idle_read_timeout(Duration::ZERO).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        server.set_idle_read_timeout(Duration::from_secs(1)).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+
+    //A slow peer keeps the connection alive.
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        for _ in 0..10 {
+            server.write_all(&buf).unwrap();
+            server.flush().unwrap();
+            thread::sleep(Duration::from_millis(100));
+        }
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 10];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"xxxxxxxxxx");
+    let _server = handle.join().unwrap();
+
+    //A silent peer does not.
+    let start = Instant::now();
+    let err = client.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn wait_writable() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let stall = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        StallingWriter {
+            inner: client_socket,
+            stall: Arc::clone(&stall),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+
+    //Drives the handshake.
+    let handle = thread::spawn(move || {
+        server.read_exact(&mut [0u8; 1]).unwrap();
+        server
+    });
+    client.wait_writable(Some(Duration::from_secs(10))).unwrap();
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+
+    stall.store(true, SeqCst);
+    loop {
+        match client.write_with_timeout(&[0u8; 1024], Some(Duration::ZERO)) {
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::TimedOut => break,
+            Err(err) => panic!("{err}"),
+        }
+    }
+    let start = Instant::now();
+    let err = client.wait_writable(Some(Duration::from_millis(200))).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    let handle = thread::spawn(move || {
+        let mut buf = vec![0u8; 0x4000];
+        while server.read(&mut buf).unwrap() > 0 {}
+    });
+    stall.store(false, SeqCst);
+    client.wait_writable(Some(Duration::from_secs(10))).unwrap();
+    client.send_close_notify().unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn wait_writable_reports_dead_writer() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let fail = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        FailingWriter {
+            inner: client_socket,
+            fail: Arc::clone(&fail),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    let handle = thread::spawn(move || {
+        server.read_exact(&mut [0u8; 1]).unwrap();
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    let _server = handle.join().unwrap();
+
+    fail.store(true, SeqCst);
+    _ = client.write_all(b"doomed");
+    _ = client.flush();
+    let start = Instant::now();
+    let err = client.wait_writable(None).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn read_deadline_visible_while_waiting() {
+    let (client, server) = common::stream_pair();
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    assert_eq!(client.read_deadline().unwrap(), None);
+
+    let start = Instant::now();
+    thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let mut buf = [0u8; 4];
+            client.read_exact(&mut buf).unwrap();
+            buf
+        });
+
+        let deadline = loop {
+            if let Some(deadline) = client.read_deadline().unwrap() {
+                break deadline;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "deadline never became visible");
+            thread::sleep(Duration::from_millis(5));
+        };
+        assert!(deadline > start + Duration::from_secs(9));
+        assert!(deadline <= Instant::now() + Duration::from_secs(10));
+
+        server.write_all(b"data").unwrap();
+        server.flush().unwrap();
+        assert_eq!(&reader.join().unwrap(), b"data");
+    });
+    assert_eq!(client.read_deadline().unwrap(), None);
+
+    //A read without a timeout has no deadline.
+    client.set_read_timeout(None).unwrap();
+    thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let mut buf = [0u8; 4];
+            client.read_exact(&mut buf).unwrap();
+        });
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(client.read_deadline().unwrap(), None);
+        server.write_all(b"more").unwrap();
+        server.flush().unwrap();
+        reader.join().unwrap();
+    });
+}
+
+#[test]
+fn idle_timeout_kills_silent_stream() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        server.write_all(b"pong").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.write_all(b"ping").unwrap();
+    client.flush().unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).unwrap();
+    let _server = handle.join().unwrap();
+
+    assert_eq!(client.set_idle_timeout(Some(Duration::ZERO)).unwrap_err().kind(), ErrorKind::InvalidInput);
+    client.set_idle_timeout(Some(Duration::from_millis(300))).unwrap();
+    let start = Instant::now();
+    assert_eq!(client.read(&mut buf).unwrap_err().kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(client.write_all(b"late").unwrap_err().kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn idle_timeout_spares_progressing_transfer() {
+    let (client, server) = common::stream_pair();
+    server.set_read_watermarks(1, 2).unwrap();
+    client.set_write_watermarks(1, 4).unwrap();
+
+    thread::scope(|scope| {
+        //Far more than fits into the queues and the socket buffers, so the write blocks until the server reads.
+        let writer = scope.spawn(|| client.write_all(&vec![7u8; 64_000_000]));
+
+        //The server trickles for much longer than the timeout, which keeps the transfer alive.
+        //The timeout is only set once the socket buffers that filled up first were drained.
+        let start = Instant::now();
+        let mut buf = vec![0u8; 0x4000];
+        let mut timeout_set = false;
+        while start.elapsed() < Duration::from_millis(2000) {
+            if !timeout_set && start.elapsed() >= Duration::from_millis(500) {
+                client.set_idle_timeout(Some(Duration::from_millis(300))).unwrap();
+                timeout_set = true;
+            }
+            server.read_exact(&mut buf).unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!writer.is_finished());
+
+        //Once it stops the transfer is idle.
+        assert_eq!(writer.join().unwrap().unwrap_err().kind(), ErrorKind::TimedOut);
+    });
+}
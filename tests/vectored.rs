@@ -0,0 +1,84 @@
+mod common;
+
+use std::io::{ErrorKind, IoSlice, IoSliceMut};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn read_vectored_fills_all_buffers() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"headerbody").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    // Handshake and wait until the data has surely arrived.
+    client.flush().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let mut header = [0u8; 6];
+    let mut body = [0u8; 4];
+    let count = client
+        .read_vectored(&mut [IoSliceMut::new(&mut header), IoSliceMut::new(&mut body)])
+        .unwrap();
+    assert_eq!(count, 10);
+    assert_eq!(&header, b"header");
+    assert_eq!(&body, b"body");
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn read_vectored_after_peek_keeps_order() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"01234567ABCDEF").unwrap();
+        server.flush().unwrap();
+        server
+    });
+
+    //The whole write arrives as one record, so the peek takes 8 bytes and rust-tls keeps the rest.
+    let mut peeked = [0u8; 8];
+    assert_eq!(client.peek(&mut peeked).unwrap(), 8);
+    assert_eq!(&peeked, b"01234567");
+
+    let mut first = [0u8; 4];
+    let mut second = [0u8; 10];
+    let count = client
+        .read_vectored(&mut [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)])
+        .unwrap();
+    assert_eq!(count, 14);
+    assert_eq!(&first, b"0123");
+    assert_eq!(&second, b"4567ABCDEF");
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn read_vectored_non_blocking() {
+    let (client, server) = common::stream_pair();
+    client.set_read_non_block(true).unwrap();
+    let mut buf = [0u8; 4];
+    let err = client
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    drop(server);
+}
+
+#[test]
+fn write_vectored_preserves_order() {
+    let (client, server) = common::stream_pair();
+    assert!(client.is_write_vectored());
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 11];
+        server.read_exact(&mut buf).unwrap();
+        buf
+    });
+
+    let count = client
+        .write_vectored(&[IoSlice::new(b"header"), IoSlice::new(b""), IoSlice::new(b"body!")])
+        .unwrap();
+    assert_eq!(count, 11);
+    client.flush().unwrap();
+    assert_eq!(&handle.join().unwrap(), b"headerbody!");
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use common::StallingWriter;
+use rust_tls_duplex_stream::{ClientDuplexStream, ServerDuplexStream};
+use std::io::ErrorKind;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::Arc;
+use std::task::{Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Counter(AtomicUsize);
+
+impl Wake for Counter {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, SeqCst);
+    }
+}
+
+fn counting_waker() -> (Arc<Counter>, Waker) {
+    let counter = Arc::new(Counter::default());
+    let waker = Waker::from(Arc::clone(&counter));
+    (counter, waker)
+}
+
+fn await_woken(counter: &Counter) {
+    let start = Instant::now();
+    while counter.0.load(SeqCst) == 0 {
+        assert!(start.elapsed() < Duration::from_secs(10), "waker was never woken");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn read_waker() {
+    let (client, server) = common::stream_pair();
+    let handle = thread::spawn(move || {
+        server.write_all(b"hello").unwrap();
+        server.flush().unwrap();
+        server
+    });
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+    let mut buf = [0u8; 5];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    client.set_read_non_block(true).unwrap();
+    assert_eq!(client.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    //Only the most recent waker is woken.
+    let (replaced, waker) = counting_waker();
+    client.register_read_waker(waker).unwrap();
+    let (counter, waker) = counting_waker();
+    client.register_read_waker(waker).unwrap();
+    server.write_all(b"world").unwrap();
+    server.flush().unwrap();
+    await_woken(&counter);
+    assert_eq!(replaced.0.load(SeqCst), 0);
+
+    //Data that was not read yet wakes a new waker right away.
+    let (counter, waker) = counting_waker();
+    client.register_read_waker(waker).unwrap();
+    assert_eq!(counter.0.load(SeqCst), 1);
+    client.set_read_non_block(false).unwrap();
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+}
+
+#[test]
+fn write_waker() {
+    let (client_socket, server_socket) = common::tcp_pair();
+    let stall = Arc::new(AtomicBool::new(false));
+    let client = ClientDuplexStream::new_unpooled(
+        common::client_connection(),
+        client_socket.try_clone().unwrap(),
+        StallingWriter {
+            inner: client_socket,
+            stall: Arc::clone(&stall),
+        },
+    )
+    .unwrap();
+    let server = ServerDuplexStream::new_unpooled(
+        common::server_connection(),
+        server_socket.try_clone().unwrap(),
+        server_socket,
+    )
+    .unwrap();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1];
+        server.read_exact(&mut buf).unwrap();
+        server
+    });
+    client.write_all(b"x").unwrap();
+    client.flush().unwrap();
+    let server = handle.join().unwrap();
+
+    stall.store(true, SeqCst);
+    client.set_write_watermarks(1, 4).unwrap();
+    client.set_write_non_block(true).unwrap();
+    let mut accepted = 0;
+    loop {
+        match client.write(&[7u8; 64]) {
+            Ok(count) => accepted += count,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    let (counter, waker) = counting_waker();
+    client.register_write_waker(waker).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(counter.0.load(SeqCst), 0);
+
+    let handle = thread::spawn(move || {
+        let mut data = vec![0u8; accepted];
+        server.read_exact(&mut data).unwrap();
+        data
+    });
+    stall.store(false, SeqCst);
+    await_woken(&counter);
+    assert_eq!(counter.0.load(SeqCst), 1);
+    assert_eq!(handle.join().unwrap(), vec![7u8; accepted]);
+}